@@ -0,0 +1,167 @@
+/* Generated by cbindgen from src/capi.rs. Do not edit. */
+
+#ifndef THETA_MP4_H
+#define THETA_MP4_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Opaque handle to a parsed THETA MP4.
+ */
+typedef struct ThetaFile ThetaFile;
+
+/**
+ * RDTA (accelerometer), RDTB (gyroscope) or RDTC (magnetometer) sample.
+ */
+typedef struct ThetaImuSample {
+  float x;
+  float y;
+  float z;
+  uint64_t timestamp;
+} ThetaImuSample;
+
+/**
+ * RDTL sample.
+ */
+typedef struct ThetaLocationSample {
+  double timestamp;
+  double latitude;
+  double longitude;
+  double altitude;
+} ThetaLocationSample;
+
+/**
+ * RDL2 sample.
+ */
+typedef struct ThetaGpsSample {
+  double timestamp;
+  double latitude;
+  double longitude;
+  int16_t gps_fix_type;
+  float altitude;
+  float horizontal_accuracy;
+  float vertical_accuracy;
+  float velocity_east;
+  float velocity_north;
+  float velocity_up;
+  float speed_accuracy;
+} ThetaGpsSample;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Library version as a static NUL-terminated string.
+ */
+const char *theta_version(void);
+
+/**
+ * Message of the last failed call on this thread, or NULL.
+ *
+ * The string is valid until the next failing call on the same thread.
+ */
+const char *theta_last_error(void);
+
+/**
+ * Opens and parses all THETA boxes of an MP4. Returns NULL on failure.
+ *
+ * # Safety
+ *
+ * `path` must be a valid NUL-terminated string.
+ */
+struct ThetaFile *theta_open(const char *path);
+
+/**
+ * Releases a handle returned by `theta_open`. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a handle from `theta_open` that has not been closed.
+ */
+void theta_close(struct ThetaFile *file);
+
+/**
+ * Whether the box `name` (e.g. "RDTA") was present and parsed.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle, `name` NULL or a NUL-terminated string.
+ */
+bool theta_has_box(const struct ThetaFile *file, const char *name);
+
+/**
+ * RDTA, RDTB or RDTC samples selected by `name`. Stores the count in `len`.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle, `name` NULL or a NUL-terminated string,
+ * `len` NULL or writable.
+ */
+const struct ThetaImuSample *theta_imu_table(const struct ThetaFile *file,
+                                             const char *name,
+                                             size_t *len);
+
+/**
+ * RDTG frame timestamps. Stores the count in `len`.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle, `len` NULL or writable.
+ */
+const uint64_t *theta_frame_timestamps(const struct ThetaFile *file, size_t *len);
+
+/**
+ * RDTL samples. Stores the count in `len`.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle, `len` NULL or writable.
+ */
+const struct ThetaLocationSample *theta_location_table(const struct ThetaFile *file, size_t *len);
+
+/**
+ * RDL2 samples. Stores the count in `len`.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle, `len` NULL or writable.
+ */
+const struct ThetaGpsSample *theta_gps_table(const struct ThetaFile *file, size_t *len);
+
+/**
+ * RTHU thumbnail (JFIF) bytes. Stores the size in `len`.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle, `len` NULL or writable.
+ */
+const uint8_t *theta_thumbnail(const struct ThetaFile *file, size_t *len);
+
+/**
+ * Metadata as JSON, the same as the CLI output. Free with `theta_string_free`.
+ *
+ * # Safety
+ *
+ * `file` must be NULL or a live handle.
+ */
+char *theta_json(const struct ThetaFile *file);
+
+/**
+ * Releases a string returned by `theta_json`. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `s` must be NULL or a string from `theta_json` that has not been freed.
+ */
+void theta_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* THETA_MP4_H */
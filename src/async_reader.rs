@@ -0,0 +1,116 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, SeekFrom};
+
+use crate::{theta::ThetaMeta, Error};
+
+/// Async counterpart of [`crate::parse_reader`].
+///
+/// Only the `ftyp` and `moov` boxes are read; `mdat` and other top-level boxes are
+/// skipped by seeking. The buffered boxes are then parsed in memory on the calling
+/// task, which takes no I/O and little time next to reading them.
+pub async fn parse_async<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: R,
+    target_boxes: Option<&[String]>,
+) -> Result<Option<ThetaMeta>, Error> {
+    let header = read_header_boxes(reader).await?;
+    crate::parse_bytes(&header, target_boxes)
+}
+
+async fn read_header_boxes<R: AsyncRead + AsyncSeek + Unpin>(
+    mut reader: R,
+) -> std::io::Result<Vec<u8>> {
+    let start = reader.stream_position().await?;
+    let size = reader.seek(SeekFrom::End(0)).await?;
+    let mut header = Vec::new();
+    let mut offset = start;
+    while offset + 8 <= size {
+        reader.seek(SeekFrom::Start(offset)).await?;
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf).await?;
+        let name = [buf[4], buf[5], buf[6], buf[7]];
+        let box_size = match u32::from_be_bytes(buf[0..4].try_into().unwrap()) {
+            0 => size - offset,
+            1 => {
+                reader.read_exact(&mut buf).await?;
+                u64::from_be_bytes(buf)
+            }
+            n => n as u64,
+        };
+        if box_size < 8 || offset.checked_add(box_size).is_none_or(|end| end > size) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid box size",
+            ));
+        }
+        if &name == b"ftyp" || &name == b"moov" {
+            let mut data = vec![0u8; box_size as usize];
+            reader.seek(SeekFrom::Start(offset)).await?;
+            reader.read_exact(&mut data).await?;
+            header.extend_from_slice(&data);
+        }
+        offset += box_size;
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn setup() -> Vec<u8> {
+        vec![
+            0x00, 0x00, 0x00, 0x0c, b'f', b't', b'y', b'p', // ftyp (12 bytes)
+            b'i', b's', b'o', b'm', // major_brand
+            0x00, 0x00, 0x00, 0x0c, b'm', b'd', b'a', b't', // mdat (12 bytes)
+            0xde, 0xad, 0xbe, 0xef, // payload
+            0x00, 0x00, 0x00, 0x0a, b'm', b'o', b'o', b'v', // moov (10 bytes)
+            0x01, 0x02, // payload
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_read_header_boxes() {
+        let data = setup();
+        let header = read_header_boxes(Cursor::new(&data)).await.unwrap();
+        let mut expected = data[0..12].to_vec();
+        expected.extend_from_slice(&data[24..34]);
+        assert_eq!(header, expected);
+    }
+
+    #[tokio::test]
+    async fn test_read_header_boxes_truncated() {
+        let mut data = setup();
+        data.truncate(32);
+        assert!(read_header_boxes(Cursor::new(&data)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_async() {
+        let mp4_box = |name: &[u8], payload: &[u8]| {
+            let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+            data.extend_from_slice(name);
+            data.extend_from_slice(payload);
+            data
+        };
+        let udta = mp4_box(b"udta", &mp4_box(b"modl", b"RICOH THETA Z1"));
+        let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &[0; 100]), udta].concat());
+        let mut data = setup();
+        data.truncate(24);
+        data.extend(moov);
+
+        let theta_meta = parse_async(Cursor::new(&data), None).await.unwrap();
+        assert_eq!(theta_meta.unwrap().modl, "RICOH THETA Z1");
+        assert!(parse_async(Cursor::new(&data[..30]), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_header_boxes_overflow() {
+        let mut data = setup();
+        // A 64-bit size that would wrap around past the end of the file.
+        data.truncate(32);
+        data[24..28].copy_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(read_header_boxes(Cursor::new(&data)).await.is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Gyroscope bias and accelerometer offset/scale from stationary intervals.
+//!
+//! RDTA and RDTB are cut into windows; a window is stationary when both the
+//! gyroscope and the accelerometer magnitude barely vary. The gyroscope bias is
+//! the mean rate over stationary windows. The accelerometer calibration fits an
+//! axis-aligned ellipsoid through the stationary gravity readings, which needs the
+//! camera to have rested in several orientations.
+
+use serde::Serialize;
+
+use crate::{
+    math::{least_squares, Vector3},
+    theta::{rdta, rdta::RdtaBox, rdtb, rdtb::RdtbBox},
+    timeline::Timeline,
+};
+
+/// Minimum number of stationary windows for the accelerometer fit.
+const MIN_ACCEL_WINDOWS: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationConfig {
+    /// Window length in seconds.
+    pub window: f64,
+    /// Largest standard deviation of the angular rate (rad/s) in a stationary window.
+    pub gyro_threshold: f64,
+    /// Largest standard deviation of the acceleration magnitude in a stationary window.
+    pub accel_threshold: f64,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        CalibrationConfig {
+            window: 0.5,
+            gyro_threshold: 0.02,
+            accel_threshold: 0.02,
+        }
+    }
+}
+
+/// Seconds from video start.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct StationaryInterval {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Corrected reading: `(raw - offset) * scale`, per axis.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct AccelCalibration {
+    pub offset: Vector3,
+    pub scale: Vector3,
+}
+
+impl AccelCalibration {
+    pub fn apply(&self, raw: Vector3) -> Vector3 {
+        let v = raw - self.offset;
+        Vector3::new(v.x * self.scale.x, v.y * self.scale.y, v.z * self.scale.z)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct CalibrationReport {
+    pub stationary: Vec<StationaryInterval>,
+    /// Mean angular rate while stationary.
+    pub gyro_bias: Option<Vector3>,
+    /// Standard deviation of the angular rate while stationary.
+    pub gyro_noise: Option<Vector3>,
+    /// `None` unless the camera rested in enough orientations.
+    pub accel: Option<AccelCalibration>,
+}
+
+struct Window {
+    start: f64,
+    end: f64,
+    gyro: Vec<Vector3>,
+    accel_mean: Vector3,
+}
+
+fn mean(values: &[Vector3]) -> Vector3 {
+    values.iter().fold(Vector3::default(), |sum, &v| sum + v) * (1.0 / values.len() as f64)
+}
+
+fn std_dev(values: &[Vector3]) -> Vector3 {
+    let m = mean(values);
+    let var = values.iter().fold(Vector3::default(), |sum, &v| {
+        let d = v - m;
+        sum + Vector3::new(d.x * d.x, d.y * d.y, d.z * d.z)
+    }) * (1.0 / values.len() as f64);
+    Vector3::new(var.x.sqrt(), var.y.sqrt(), var.z.sqrt())
+}
+
+fn stationary_windows(
+    rdta: &RdtaBox,
+    rdtb: &RdtbBox,
+    timeline: &Timeline,
+    config: CalibrationConfig,
+) -> Vec<Window> {
+    let gyro: Vec<_> = rdtb
+        .entries()
+        .iter()
+        .map(|e| {
+            (
+                timeline.tick_to_seconds(e.timestamp),
+                Vector3::from_f32(e.x, e.y, e.z),
+            )
+        })
+        .collect();
+    let accel: Vec<_> = rdta
+        .entries()
+        .iter()
+        .map(|e| {
+            (
+                timeline.tick_to_seconds(e.timestamp),
+                Vector3::from_f32(e.x, e.y, e.z),
+            )
+        })
+        .collect();
+    let (Some(first), Some(last)) = (gyro.first(), gyro.last()) else {
+        return Vec::new();
+    };
+    if config.window <= 0.0 {
+        return Vec::new();
+    }
+
+    let in_range = |series: &[(f64, Vector3)], start: f64, end: f64| -> Vec<Vector3> {
+        let from = series.partition_point(|&(t, _)| t < start);
+        let to = series.partition_point(|&(t, _)| t < end);
+        series[from..to].iter().map(|&(_, v)| v).collect()
+    };
+    let mut windows = Vec::new();
+    let mut start = first.0;
+    while start < last.0 {
+        let end = start + config.window;
+        let gyro = in_range(&gyro, start, end);
+        let accel = in_range(&accel, start, end);
+        start = end;
+        if gyro.len() < 2 || accel.len() < 2 {
+            continue;
+        }
+        let magnitudes: Vec<_> = accel.iter().map(|a| a.norm()).collect();
+        let magnitude_mean = magnitudes.iter().sum::<f64>() / magnitudes.len() as f64;
+        let magnitude_var = magnitudes
+            .iter()
+            .map(|m| (m - magnitude_mean) * (m - magnitude_mean))
+            .sum::<f64>()
+            / magnitudes.len() as f64;
+        if std_dev(&gyro).norm() > config.gyro_threshold
+            || magnitude_var.sqrt() > config.accel_threshold
+        {
+            continue;
+        }
+        windows.push(Window {
+            start: end - config.window,
+            end,
+            gyro,
+            accel_mean: mean(&accel),
+        });
+    }
+    windows
+}
+
+/// Fits `a x² + b y² + c z² + d x + e y + f z = 1` through the readings.
+fn fit_accel(readings: &[Vector3]) -> Option<AccelCalibration> {
+    if readings.len() < MIN_ACCEL_WINDOWS {
+        return None;
+    }
+    // Every axis must have seen gravity from both sides for the fit to be determined.
+    let directions: Vec<_> = readings.iter().filter_map(|a| a.normalize()).collect();
+    let spread = |axis: fn(&Vector3) -> f64| {
+        let (min, max) = directions
+            .iter()
+            .map(axis)
+            .fold((1.0f64, -1.0f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        max - min
+    };
+    if [spread(|v| v.x), spread(|v| v.y), spread(|v| v.z)]
+        .iter()
+        .any(|&s| s < 1.0)
+    {
+        return None;
+    }
+
+    let rows = readings
+        .iter()
+        .map(|r| ([r.x * r.x, r.y * r.y, r.z * r.z, r.x, r.y, r.z], 1.0));
+    let [a, b, c, d, e, f] = least_squares(rows)?;
+    if a <= 0.0 || b <= 0.0 || c <= 0.0 {
+        return None;
+    }
+    let offset = Vector3::new(-d / (2.0 * a), -e / (2.0 * b), -f / (2.0 * c));
+    let g = 1.0 + a * offset.x * offset.x + b * offset.y * offset.y + c * offset.z * offset.z;
+    Some(AccelCalibration {
+        offset,
+        scale: Vector3::new((a / g).sqrt(), (b / g).sqrt(), (c / g).sqrt()),
+    })
+}
+
+impl CalibrationReport {
+    /// Finds stationary intervals and estimates the calibration from them.
+    pub fn new(
+        rdta: &RdtaBox,
+        rdtb: &RdtbBox,
+        timeline: &Timeline,
+        config: CalibrationConfig,
+    ) -> CalibrationReport {
+        let windows = stationary_windows(rdta, rdtb, timeline, config);
+
+        let mut stationary: Vec<StationaryInterval> = Vec::new();
+        for w in &windows {
+            match stationary.last_mut() {
+                Some(last) if last.end >= w.start => last.end = w.end,
+                _ => stationary.push(StationaryInterval {
+                    start: w.start,
+                    end: w.end,
+                }),
+            }
+        }
+        let gyro: Vec<_> = windows
+            .iter()
+            .flat_map(|w| w.gyro.iter().copied())
+            .collect();
+        let readings: Vec<_> = windows.iter().map(|w| w.accel_mean).collect();
+        CalibrationReport {
+            stationary,
+            gyro_bias: (!gyro.is_empty()).then(|| mean(&gyro)),
+            gyro_noise: (!gyro.is_empty()).then(|| std_dev(&gyro)),
+            accel: fit_accel(&readings),
+        }
+    }
+
+    /// RDTB with the gyroscope bias removed.
+    pub fn correct_gyroscope(&self, rdtb: &RdtbBox) -> RdtbBox {
+        let bias = self.gyro_bias.unwrap_or_default();
+        rdtb.map_entries(|e| rdtb::DataEntry {
+            x: (e.x as f64 - bias.x) as f32,
+            y: (e.y as f64 - bias.y) as f32,
+            z: (e.z as f64 - bias.z) as f32,
+            ..e.clone()
+        })
+    }
+
+    /// RDTA with the offset and scale applied, or unchanged without a fit.
+    pub fn correct_accelerometer(&self, rdta: &RdtaBox) -> RdtaBox {
+        rdta.map_entries(|e| match &self.accel {
+            Some(calibration) => {
+                let a = calibration.apply(Vector3::from_f32(e.x, e.y, e.z));
+                rdta::DataEntry {
+                    x: a.x as f32,
+                    y: a.y as f32,
+                    z: a.z as f32,
+                    ..e.clone()
+                }
+            }
+            None => e.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing, theta::ThetaMeta};
+
+    const BIAS: (f32, f32, f32) = (0.01, -0.02, 0.005);
+
+    /// Rests 1 s in each of six orientations, turning for 0.5 s in between.
+    fn setup() -> ThetaMeta {
+        let offset = [0.05f32, -0.03, 0.02];
+        let scale = [1.1f32, 0.9, 1.05];
+        let poses = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        let (mut accel, mut gyro) = (Vec::new(), Vec::new());
+        let mut tick = 0u64;
+        for pose in poses {
+            for i in 0..150 {
+                let moving = i >= 100;
+                let g: Vec<f32> = (0..3)
+                    .map(|k| pose[k] / scale[k] + offset[k] + if moving { 0.3 } else { 0.0 })
+                    .collect();
+                accel.push((g[0], g[1], g[2], tick));
+                let turn = if moving { (i % 2) as f32 } else { 0.0 };
+                gyro.push((BIAS.0 + turn, BIAS.1, BIAS.2, tick));
+                tick += 10_000;
+            }
+        }
+        ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtb: Some(testing::rdtb(&gyro)),
+            modl: "RICOH THETA X".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn report(meta: &ThetaMeta) -> CalibrationReport {
+        let rdta = meta.rdta.as_ref().unwrap();
+        let rdtb = meta.rdtb.as_ref().unwrap();
+        CalibrationReport::new(rdta, rdtb, &Timeline::new(meta), Default::default())
+    }
+
+    #[test]
+    fn test_calibration_report() {
+        let meta = setup();
+        let report = report(&meta);
+        assert_eq!(report.stationary.len(), 6);
+        assert_eq!(report.stationary[1].start, 1.5);
+        assert_eq!(report.stationary[1].end, 2.5);
+
+        let bias = report.gyro_bias.unwrap();
+        let expected = Vector3::from_f32(BIAS.0, BIAS.1, BIAS.2);
+        assert!((bias - expected).norm() < 1e-6);
+
+        let accel = report.accel.unwrap();
+        assert!((accel.offset - Vector3::new(0.05, -0.03, 0.02)).norm() < 1e-4);
+        assert!((accel.scale - Vector3::new(1.1, 0.9, 1.05)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_corrected_tables() {
+        let meta = setup();
+        let report = report(&meta);
+        let rdtb = report.correct_gyroscope(meta.rdtb.as_ref().unwrap());
+        assert!(rdtb.entries()[0].x.abs() < 1e-6);
+        assert_eq!(rdtb.entries()[0].timestamp, 0);
+
+        let rdta = report.correct_accelerometer(meta.rdta.as_ref().unwrap());
+        let e = &rdta.entries()[0];
+        assert!((Vector3::from_f32(e.x, e.y, e.z) - Vector3::new(1.0, 0.0, 0.0)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_single_orientation() {
+        let ticks: Vec<u64> = (0..200).map(|i| i * 10_000).collect();
+        let meta = ThetaMeta {
+            rdta: Some(testing::rdta(
+                &ticks
+                    .iter()
+                    .map(|&t| (0.0, 0.0, 1.0, t))
+                    .collect::<Vec<_>>(),
+            )),
+            rdtb: Some(testing::rdtb(
+                &ticks
+                    .iter()
+                    .map(|&t| (0.0, 0.0, 0.0, t))
+                    .collect::<Vec<_>>(),
+            )),
+            modl: "RICOH THETA X".to_string(),
+            ..Default::default()
+        };
+        let report = report(&meta);
+        assert_eq!(report.stationary.len(), 1);
+        assert_eq!(report.gyro_bias, Some(Vector3::default()));
+        assert_eq!(report.accel, None);
+    }
+}
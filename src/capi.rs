@@ -0,0 +1,373 @@
+//! C ABI for embedding the parser in native applications.
+//!
+//! Arrays returned by the table accessors are owned by the `ThetaFile` handle and
+//! stay valid until `theta_close` is called on it.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::theta::ThetaMeta;
+
+/// Opaque handle to a parsed THETA MP4.
+pub struct ThetaFile {
+    meta: ThetaMeta,
+    rdta: Vec<ThetaImuSample>,
+    rdtb: Vec<ThetaImuSample>,
+    rdtc: Vec<ThetaImuSample>,
+    rdtg: Vec<u64>,
+    rdtl: Vec<ThetaLocationSample>,
+    rdl2: Vec<ThetaGpsSample>,
+}
+
+/// RDTA (accelerometer), RDTB (gyroscope) or RDTC (magnetometer) sample.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ThetaImuSample {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub timestamp: u64,
+}
+
+/// RDTL sample.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ThetaLocationSample {
+    pub timestamp: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+/// RDL2 sample.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ThetaGpsSample {
+    pub timestamp: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub gps_fix_type: i16,
+    pub altitude: f32,
+    pub horizontal_accuracy: f32,
+    pub vertical_accuracy: f32,
+    pub velocity_east: f32,
+    pub velocity_north: f32,
+    pub velocity_up: f32,
+    pub speed_accuracy: f32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+/// Runs `f`, turning a panic into the last error and `fallback`, since unwinding
+/// out of an `extern "C"` function aborts the host process.
+fn catch_panic<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(&format!("internal error: {}", message));
+            fallback
+        }
+    }
+}
+
+macro_rules! imu_samples {
+    ($box:expr) => {
+        $box.as_ref()
+            .map(|b| {
+                b.entries()
+                    .iter()
+                    .map(|e| ThetaImuSample {
+                        x: e.x,
+                        y: e.y,
+                        z: e.z,
+                        timestamp: e.timestamp,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+}
+
+impl ThetaFile {
+    fn new(meta: ThetaMeta) -> ThetaFile {
+        let rdta = imu_samples!(meta.rdta);
+        let rdtb = imu_samples!(meta.rdtb);
+        let rdtc = imu_samples!(meta.rdtc);
+        let rdtg = meta
+            .rdtg
+            .as_ref()
+            .map(|b| b.entries().iter().map(|e| e.timestamp).collect())
+            .unwrap_or_default();
+        let rdtl = meta
+            .rdtl
+            .as_ref()
+            .map(|b| {
+                b.entries()
+                    .iter()
+                    .map(|e| ThetaLocationSample {
+                        timestamp: e.timestamp,
+                        latitude: e.latitude,
+                        longitude: e.longitude,
+                        altitude: e.altitude,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let rdl2 = meta
+            .rdl2
+            .as_ref()
+            .map(|b| {
+                b.entries()
+                    .iter()
+                    .map(|e| ThetaGpsSample {
+                        timestamp: e.timestamp,
+                        latitude: e.latitude,
+                        longitude: e.longitude,
+                        gps_fix_type: e.gps_fix_type,
+                        altitude: e.altitude,
+                        horizontal_accuracy: e.horizontal_accuracy,
+                        vertical_accuracy: e.vertical_accuracy,
+                        velocity_east: e.velocity_east,
+                        velocity_north: e.velocity_north,
+                        velocity_up: e.velocity_up,
+                        speed_accuracy: e.speed_accuracy,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        ThetaFile {
+            meta,
+            rdta,
+            rdtb,
+            rdtc,
+            rdtg,
+            rdtl,
+            rdl2,
+        }
+    }
+}
+
+/// Stores the length of `values()` in `len` and returns its data, or NULL when
+/// empty or on a panic.
+unsafe fn table<'a, T: 'a>(len: *mut usize, values: impl FnOnce() -> &'a [T]) -> *const T {
+    let values = catch_panic(&[][..], values);
+    if !len.is_null() {
+        *len = values.len();
+    }
+    if values.is_empty() {
+        ptr::null()
+    } else {
+        values.as_ptr()
+    }
+}
+
+/// Library version as a static NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn theta_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Message of the last failed call on this thread, or NULL.
+///
+/// The string is valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn theta_last_error() -> *const c_char {
+    catch_panic(ptr::null(), || {
+        LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+    })
+}
+
+/// Opens and parses all THETA boxes of an MP4. Returns NULL on failure.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn theta_open(path: *const c_char) -> *mut ThetaFile {
+    catch_panic(ptr::null_mut(), || {
+        if path.is_null() {
+            set_last_error("path is NULL");
+            return ptr::null_mut();
+        }
+        let Ok(path) = CStr::from_ptr(path).to_str() else {
+            set_last_error("path is not valid UTF-8");
+            return ptr::null_mut();
+        };
+        let targets = vec!["all".to_string()];
+        match crate::parse(&path, Some(&targets)) {
+            Some((_, Some(meta))) => Box::into_raw(Box::new(ThetaFile::new(meta))),
+            Some((_, None)) => {
+                set_last_error("Metadata not found");
+                ptr::null_mut()
+            }
+            None => {
+                set_last_error("Failed to parse the file");
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Releases a handle returned by `theta_open`. NULL is ignored.
+///
+/// # Safety
+///
+/// `file` must be NULL or a handle from `theta_open` that has not been closed.
+#[no_mangle]
+pub unsafe extern "C" fn theta_close(file: *mut ThetaFile) {
+    catch_panic((), || {
+        if !file.is_null() {
+            drop(Box::from_raw(file));
+        }
+    })
+}
+
+/// Whether the box `name` (e.g. "RDTA") was present and parsed.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle, `name` NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn theta_has_box(file: *const ThetaFile, name: *const c_char) -> bool {
+    catch_panic(false, || {
+        if file.is_null() || name.is_null() {
+            return false;
+        }
+        match CStr::from_ptr(name).to_str() {
+            Ok(name) => (*file).meta.has_box(name),
+            Err(_) => false,
+        }
+    })
+}
+
+/// RDTA, RDTB or RDTC samples selected by `name`. Stores the count in `len`.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle, `name` NULL or a NUL-terminated string,
+/// `len` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn theta_imu_table(
+    file: *const ThetaFile,
+    name: *const c_char,
+    len: *mut usize,
+) -> *const ThetaImuSample {
+    table(len, || match (file.as_ref(), name.is_null()) {
+        (Some(file), false) => match CStr::from_ptr(name).to_bytes() {
+            b"RDTA" => &file.rdta,
+            b"RDTB" => &file.rdtb,
+            b"RDTC" => &file.rdtc,
+            _ => &[],
+        },
+        _ => &[],
+    })
+}
+
+/// RDTG frame timestamps. Stores the count in `len`.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle, `len` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn theta_frame_timestamps(
+    file: *const ThetaFile,
+    len: *mut usize,
+) -> *const u64 {
+    table(len, || file.as_ref().map_or(&[], |f| &f.rdtg))
+}
+
+/// RDTL samples. Stores the count in `len`.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle, `len` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn theta_location_table(
+    file: *const ThetaFile,
+    len: *mut usize,
+) -> *const ThetaLocationSample {
+    table(len, || file.as_ref().map_or(&[], |f| &f.rdtl))
+}
+
+/// RDL2 samples. Stores the count in `len`.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle, `len` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn theta_gps_table(
+    file: *const ThetaFile,
+    len: *mut usize,
+) -> *const ThetaGpsSample {
+    table(len, || file.as_ref().map_or(&[], |f| &f.rdl2))
+}
+
+/// RTHU thumbnail (JFIF) bytes. Stores the size in `len`.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle, `len` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn theta_thumbnail(file: *const ThetaFile, len: *mut usize) -> *const u8 {
+    table(len, || {
+        file.as_ref()
+            .and_then(|f| f.meta.rthu.as_ref())
+            .map_or(&[][..], |rthu| &rthu.data)
+    })
+}
+
+/// Metadata as JSON, the same as the CLI output. Free with `theta_string_free`.
+///
+/// # Safety
+///
+/// `file` must be NULL or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn theta_json(file: *const ThetaFile) -> *mut c_char {
+    catch_panic(ptr::null_mut(), || {
+        let Some(file) = file.as_ref() else {
+            return ptr::null_mut();
+        };
+        match serde_json::to_string_pretty(&file.meta.to_serializable()) {
+            Ok(json) => match CString::new(json) {
+                Ok(json) => json.into_raw(),
+                Err(e) => {
+                    set_last_error(&e.to_string());
+                    ptr::null_mut()
+                }
+            },
+            Err(e) => {
+                set_last_error(&e.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Releases a string returned by `theta_json`. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string from `theta_json` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn theta_string_free(s: *mut c_char) {
+    catch_panic((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
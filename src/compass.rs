@@ -0,0 +1,392 @@
+//! Magnetometer calibration and tilt-compensated compass heading.
+//!
+//! RDTC readings are distorted by the camera itself: a constant hard-iron offset
+//! and a soft-iron matrix that squashes the sphere of field directions into an
+//! ellipsoid. [`MagCalibration::fit`] recovers both from the samples, and the
+//! heading of the camera's forward axis is then measured in the plane levelled by
+//! the accelerometer (RDTA).
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+    fusion::vectors,
+    geo::{self, GeoPoint, TrackFix},
+    math::{interpolate, least_squares, solve, Matrix3, Vector3},
+    theta::ThetaMeta,
+    timeline::Timeline,
+    wmm,
+};
+
+/// Corrected reading: `soft_iron * (raw - offset)`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct MagCalibration {
+    /// Hard-iron offset.
+    pub offset: Vector3,
+    /// Soft-iron correction, scaled so corrected readings keep `field_strength`.
+    pub soft_iron: Matrix3,
+    pub field_strength: f64,
+}
+
+impl MagCalibration {
+    /// Fits an ellipsoid through `samples`, falling back to a sphere (hard-iron
+    /// offset only) when the samples do not determine one.
+    pub fn fit(samples: &[Vector3]) -> Option<MagCalibration> {
+        MagCalibration::fit_ellipsoid(samples).or_else(|| MagCalibration::fit_sphere(samples))
+    }
+
+    /// `a x² + b y² + c z² + 2d xy + 2e xz + 2f yz + 2g x + 2h y + 2i z = 1`.
+    fn fit_ellipsoid(samples: &[Vector3]) -> Option<MagCalibration> {
+        if samples.len() < 9 {
+            return None;
+        }
+        let rows = samples.iter().map(|m| {
+            let (x, y, z) = (m.x, m.y, m.z);
+            let row = [
+                x * x,
+                y * y,
+                z * z,
+                2.0 * x * y,
+                2.0 * x * z,
+                2.0 * y * z,
+                2.0 * x,
+                2.0 * y,
+                2.0 * z,
+            ];
+            (row, 1.0)
+        });
+        let [a, b, c, d, e, f, g, h, i] = least_squares(rows)?;
+        let shape = [[a, d, e], [d, b, f], [e, f, c]];
+        let [ox, oy, oz] = solve(shape, [-g, -h, -i])?;
+        let offset = Vector3::new(ox, oy, oz);
+        let scale = 1.0 + offset.dot(Matrix3(shape) * offset);
+        let m = Matrix3(shape) * (1.0 / scale);
+        let field_strength = m.determinant().powf(-1.0 / 6.0);
+        if !field_strength.is_finite() {
+            return None;
+        }
+        Some(MagCalibration {
+            offset,
+            soft_iron: m.symmetric_sqrt()? * field_strength,
+            field_strength,
+        })
+    }
+
+    /// `x² + y² + z² = 2 ox x + 2 oy y + 2 oz z + k`.
+    fn fit_sphere(samples: &[Vector3]) -> Option<MagCalibration> {
+        if samples.len() < 4 {
+            return None;
+        }
+        let rows = samples
+            .iter()
+            .map(|m| ([2.0 * m.x, 2.0 * m.y, 2.0 * m.z, 1.0], m.dot(*m)));
+        let [ox, oy, oz, k] = least_squares(rows)?;
+        let offset = Vector3::new(ox, oy, oz);
+        let radius_squared = k + offset.dot(offset);
+        (radius_squared > 0.0).then(|| MagCalibration {
+            offset,
+            soft_iron: Matrix3::IDENTITY,
+            field_strength: radius_squared.sqrt(),
+        })
+    }
+
+    pub fn apply(&self, raw: Vector3) -> Vector3 {
+        self.soft_iron * (raw - self.offset)
+    }
+}
+
+/// Heading of `forward` in degrees clockwise from magnetic north, in `[0, 360)`.
+///
+/// `accel` is the accelerometer reading (pointing up at rest) and `mag` the
+/// calibrated field, both in the sensor frame. `None` when `forward` is vertical.
+pub fn tilt_compensated_heading(mag: Vector3, accel: Vector3, forward: Vector3) -> Option<f64> {
+    let up = accel.normalize()?;
+    let north = (mag - up * mag.dot(up)).normalize()?;
+    let east = north.cross(up);
+    let forward = forward - up * forward.dot(up);
+    if forward.norm() < 1e-9 {
+        return None;
+    }
+    Some(
+        forward
+            .dot(east)
+            .atan2(forward.dot(north))
+            .to_degrees()
+            .rem_euclid(360.0),
+    )
+}
+
+/// Correction from magnetic to true north, in degrees east.
+#[derive(Debug, Clone, Copy)]
+pub enum Declination {
+    /// Report magnetic headings.
+    None,
+    Fixed(f64),
+    /// The World Magnetic Model ([`wmm`]) at the first GPS fix and its date.
+    Wmm,
+    /// A caller-supplied model evaluated at the first GPS fix.
+    Model(fn(&GeoPoint) -> f64),
+}
+
+impl Declination {
+    /// Degrees east for the recording in `meta`; a model without a GPS fix, or
+    /// the WMM outside its years, gives 0.
+    pub fn degrees(&self, meta: &ThetaMeta) -> f64 {
+        match *self {
+            Declination::None => 0.0,
+            Declination::Fixed(degrees) => degrees,
+            Declination::Wmm => wmm_declination(meta).unwrap_or(0.0),
+            Declination::Model(model) => first_fix(meta).map_or(0.0, |fix| model(&fix.position)),
+        }
+    }
+}
+
+/// `auto` for [`Declination::Wmm`], or else degrees east.
+impl FromStr for Declination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Declination::Wmm),
+            _ => s
+                .parse()
+                .map(Declination::Fixed)
+                .map_err(|_| format!("invalid declination: {}", s)),
+        }
+    }
+}
+
+/// WMM declination at the first GPS fix of `meta`, in degrees east.
+pub fn wmm_declination(meta: &ThetaMeta) -> Option<f64> {
+    let fix = first_fix(meta)?;
+    let field = wmm::magnetic_field(&fix.position, wmm::decimal_year(fix.timestamp))?;
+    Some(field.declination())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompassConfig {
+    /// Sensor-frame axis whose heading is reported.
+    pub forward: Vector3,
+    pub declination: Declination,
+}
+
+impl Default for CompassConfig {
+    fn default() -> Self {
+        CompassConfig {
+            forward: Vector3::new(1.0, 0.0, 0.0),
+            declination: Declination::None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct HeadingSample {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame: Option<usize>,
+    /// Seconds from video start.
+    pub time: f64,
+    /// Degrees clockwise from north, in `[0, 360)`.
+    pub heading: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct CompassHeadings {
+    pub calibration: MagCalibration,
+    /// Degrees added to magnetic headings.
+    pub declination: f64,
+    /// One heading per RDTC sample.
+    pub samples: Vec<HeadingSample>,
+    /// One heading per video frame.
+    pub frames: Vec<HeadingSample>,
+}
+
+fn first_fix(meta: &ThetaMeta) -> Option<TrackFix> {
+    geo::track(meta).first().copied()
+}
+
+/// Calibrates RDTC and computes headings, or `None` without RDTA, RDTC or a fit.
+pub fn compass_headings(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: CompassConfig,
+) -> Option<CompassHeadings> {
+    let (rdta, rdtc) = (meta.rdta.as_ref()?, meta.rdtc.as_ref()?);
+    let (accel_times, accel) = vectors(
+        rdta.entries().iter().map(|e| (e.x, e.y, e.z, e.timestamp)),
+        timeline,
+    );
+    let (mag_times, raw) = vectors(
+        rdtc.entries().iter().map(|e| (e.x, e.y, e.z, e.timestamp)),
+        timeline,
+    );
+    let calibration = MagCalibration::fit(&raw)?;
+    let mag: Vec<_> = raw.iter().map(|&m| calibration.apply(m)).collect();
+
+    let declination = config.declination.degrees(meta);
+    let heading = |frame: Option<usize>, time: f64, m: Vector3| {
+        let a = interpolate(&accel_times, &accel, time, Vector3::lerp)?;
+        let heading = tilt_compensated_heading(m, a, config.forward)?;
+        Some(HeadingSample {
+            frame,
+            time,
+            heading: (heading + declination).rem_euclid(360.0),
+        })
+    };
+
+    let samples = mag_times
+        .iter()
+        .zip(&mag)
+        .filter_map(|(&time, &m)| heading(None, time, m))
+        .collect();
+    let frames = timeline
+        .frame_times()
+        .iter()
+        .enumerate()
+        .filter_map(|(frame, &time)| {
+            let m = interpolate(&mag_times, &mag, time, Vector3::lerp)?;
+            heading(Some(frame), time, m)
+        })
+        .collect();
+    Some(CompassHeadings {
+        calibration,
+        declination,
+        samples,
+        frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    const OFFSET: Vector3 = Vector3::new(12.0, -7.0, 3.0);
+
+    /// Field of 40 µT seen from many directions, through a known distortion.
+    fn distorted_samples() -> Vec<Vector3> {
+        let distortion = Matrix3([[1.2, 0.1, 0.0], [0.1, 0.9, 0.05], [0.0, 0.05, 1.0]]);
+        let mut samples = Vec::new();
+        for i in 0..12 {
+            for j in 1..12 {
+                let (lon, lat) = (i as f64 * 30f64.to_radians(), j as f64 * 15f64.to_radians());
+                let field = Vector3::new(lat.sin() * lon.cos(), lat.sin() * lon.sin(), lat.cos());
+                samples.push(distortion * (field * 40.0) + OFFSET);
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_fit() {
+        let samples = distorted_samples();
+        let calibration = MagCalibration::fit(&samples).unwrap();
+        assert!((calibration.offset - OFFSET).norm() < 1e-6);
+        for &m in &samples {
+            let corrected = calibration.apply(m).norm();
+            assert!((corrected - calibration.field_strength).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_fit_sphere_fallback() {
+        let samples: Vec<_> = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ]
+        .iter()
+        .map(|&v| v * 30.0 + OFFSET)
+        .collect();
+        let calibration = MagCalibration::fit(&samples).unwrap();
+        assert_eq!(calibration.soft_iron, Matrix3::IDENTITY);
+        assert!((calibration.offset - OFFSET).norm() < 1e-9);
+        assert!((calibration.field_strength - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tilt_compensated_heading() {
+        let up = Vector3::new(0.0, 0.0, 1.0);
+        let forward = Vector3::new(1.0, 0.0, 0.0);
+        // Field dipping downwards towards north, which lies along +y.
+        let mag = Vector3::new(0.0, 30.0, -40.0);
+        let heading = tilt_compensated_heading(mag, up, forward).unwrap();
+        assert!((heading - 90.0).abs() < 1e-9);
+
+        // Pitching the camera does not change the heading.
+        let q = crate::math::Quaternion::from_euler(0.0, 0.3, 0.0).conjugate();
+        let heading = tilt_compensated_heading(q.rotate(mag), q.rotate(up), forward).unwrap();
+        assert!((heading - 90.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_compass_headings() {
+        let samples = distorted_samples();
+        let ticks: Vec<u64> = (0..samples.len() as u64).map(|i| i * 10_000).collect();
+        let accel: Vec<_> = ticks.iter().map(|&t| (0.0, 0.0, 1.0, t)).collect();
+        let mag: Vec<_> = samples
+            .iter()
+            .zip(&ticks)
+            .map(|(m, &t)| (m.x as f32, m.y as f32, m.z as f32, t))
+            .collect();
+        let meta = ThetaMeta {
+            rdtg: Some(testing::rdtg(&[0, 500_000])),
+            rdta: Some(testing::rdta(&accel)),
+            rdtc: Some(testing::rdtc(&mag)),
+            rdl2: Some(testing::rdl2(&[Fix::new(100.0, 48.9, 2.3)])),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 2.0, 2)),
+            ..Default::default()
+        };
+        let timeline = Timeline::new(&meta);
+        let magnetic = compass_headings(&meta, &timeline, Default::default()).unwrap();
+        assert_eq!(magnetic.samples.len(), samples.len());
+        assert_eq!(magnetic.frames.len(), 2);
+        assert_eq!(magnetic.frames[1].frame, Some(1));
+
+        let config = CompassConfig {
+            declination: Declination::Fixed(10.0),
+            ..Default::default()
+        };
+        let corrected = compass_headings(&meta, &timeline, config).unwrap();
+        let diff = corrected.samples[0].heading - magnetic.samples[0].heading;
+        assert!((diff.rem_euclid(360.0) - 10.0).abs() < 1e-6);
+
+        let config = CompassConfig {
+            declination: Declination::Model(|p| p.longitude),
+            ..Default::default()
+        };
+        let modelled = compass_headings(&meta, &timeline, config).unwrap();
+        assert!((modelled.declination - 2.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_wmm_declination() {
+        // Paris on 2022-07-02: WMM2020 gives about 1.6° east.
+        let mut meta = ThetaMeta {
+            rdl2: Some(testing::rdl2(&[Fix::new(1_656_763_200.0, 48.9, 2.3)])),
+            modl: "RICOH THETA X".to_string(),
+            ..Default::default()
+        };
+        let point = GeoPoint::new(48.9, 2.3, 10.0);
+        let expected = wmm::magnetic_field(&point, wmm::decimal_year(1_656_763_200.0))
+            .unwrap()
+            .declination();
+        assert_eq!(wmm_declination(&meta), Some(expected));
+        assert!((Declination::Wmm.degrees(&meta) - 1.6).abs() < 0.2);
+
+        // No model before 2020.
+        meta.rdl2 = Some(testing::rdl2(&[Fix::new(100.0, 48.9, 2.3)]));
+        assert_eq!(wmm_declination(&meta), None);
+        assert_eq!(Declination::Wmm.degrees(&meta), 0.0);
+    }
+
+    #[test]
+    fn test_declination_from_str() {
+        assert!(matches!("auto".parse(), Ok(Declination::Wmm)));
+        assert!(matches!("-7.5".parse(), Ok(Declination::Fixed(d)) if d == -7.5));
+        assert!("east".parse::<Declination>().is_err());
+    }
+}
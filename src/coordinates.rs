@@ -0,0 +1,218 @@
+//! Export of the GPS track in metric coordinate frames.
+
+use std::{fmt::Write, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{
+    geo::{self, GeoPoint, UtmZone},
+    theta::ThetaMeta,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordinateFrame {
+    /// WGS84 latitude and longitude in degrees, altitude in metres.
+    #[default]
+    Geodetic,
+    /// Earth-centred, earth-fixed metres.
+    Ecef,
+    /// East, north and up metres from the first fix.
+    Enu,
+    /// Easting, northing and altitude in the zone of the first fix.
+    Utm,
+}
+
+impl FromStr for CoordinateFrame {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "geodetic" => Ok(CoordinateFrame::Geodetic),
+            "ecef" => Ok(CoordinateFrame::Ecef),
+            "enu" => Ok(CoordinateFrame::Enu),
+            "utm" => Ok(CoordinateFrame::Utm),
+            _ => Err(format!("unknown coordinate frame: {}", s)),
+        }
+    }
+}
+
+impl CoordinateFrame {
+    /// Names of the `x`, `y` and `z` components.
+    pub fn axes(self) -> [&'static str; 3] {
+        match self {
+            CoordinateFrame::Geodetic => ["latitude", "longitude", "altitude"],
+            CoordinateFrame::Ecef => ["x", "y", "z"],
+            CoordinateFrame::Enu => ["east", "north", "up"],
+            CoordinateFrame::Utm => ["easting", "northing", "altitude"],
+        }
+    }
+}
+
+/// Why a track cannot be projected.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProjectionError {
+    /// Neither RDL2 nor RDTL has a usable fix.
+    NoFixes,
+    /// A fix lies outside the 80°S to 84°N covered by UTM.
+    OutsideUtm,
+}
+
+impl std::fmt::Display for ProjectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectionError::NoFixes => write!(f, "RDL2 or RDTL with at least one fix is required"),
+            ProjectionError::OutsideUtm => write!(f, "the track leaves the 80°S to 84°N UTM range"),
+        }
+    }
+}
+
+impl std::error::Error for ProjectionError {}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct ProjectedPoint {
+    /// UTC seconds, as in RDL2/RDTL.
+    pub timestamp: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ProjectedTrack {
+    pub frame: CoordinateFrame,
+    /// Component names of `x`, `y` and `z`.
+    pub axes: [&'static str; 3],
+    /// The first fix, for ENU.
+    pub origin: Option<GeoPoint>,
+    /// For UTM.
+    pub zone: Option<UtmZone>,
+    pub points: Vec<ProjectedPoint>,
+}
+
+impl ProjectedTrack {
+    /// RDL2 fixes, or RDTL when RDL2 is absent, in `frame`.
+    pub fn new(
+        meta: &ThetaMeta,
+        frame: CoordinateFrame,
+    ) -> Result<ProjectedTrack, ProjectionError> {
+        let track: Vec<(f64, GeoPoint)> = geo::track(meta)
+            .into_iter()
+            .map(|fix| (fix.timestamp, fix.position))
+            .collect();
+        let origin = track.first().ok_or(ProjectionError::NoFixes)?.1;
+        let zone = UtmZone::of(&origin);
+        if frame == CoordinateFrame::Utm && zone.is_none() {
+            return Err(ProjectionError::OutsideUtm);
+        }
+        let points = track
+            .iter()
+            .map(|&(timestamp, p)| {
+                let (x, y, z) = match frame {
+                    CoordinateFrame::Geodetic => (p.latitude, p.longitude, p.altitude),
+                    CoordinateFrame::Ecef => {
+                        let v = p.to_ecef();
+                        (v.x, v.y, v.z)
+                    }
+                    CoordinateFrame::Enu => {
+                        let v = p.to_enu(&origin);
+                        (v.x, v.y, v.z)
+                    }
+                    CoordinateFrame::Utm => {
+                        let v = zone
+                            .and_then(|zone| p.to_utm(zone))
+                            .ok_or(ProjectionError::OutsideUtm)?;
+                        (v.x, v.y, v.z)
+                    }
+                };
+                Ok(ProjectedPoint { timestamp, x, y, z })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ProjectedTrack {
+            frame,
+            axes: frame.axes(),
+            origin: (frame == CoordinateFrame::Enu).then_some(origin),
+            zone: zone.filter(|_| frame == CoordinateFrame::Utm),
+            points,
+        })
+    }
+
+    /// CSV with a header row of `timestamp` and the axis names.
+    pub fn to_csv(&self) -> String {
+        let [x, y, z] = self.axes;
+        let mut csv = format!("timestamp,{},{},{}\n", x, y, z);
+        // Degrees need more digits than metres for millimetre resolution.
+        let digits = if self.frame == CoordinateFrame::Geodetic {
+            9
+        } else {
+            3
+        };
+        for p in &self.points {
+            let _ = writeln!(
+                csv,
+                "{:.3},{:.*},{:.*},{:.3}",
+                p.timestamp, digits, p.x, digits, p.y, p.z
+            );
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    fn setup() -> ThetaMeta {
+        let mut lost = Fix::new(101.0, 0.0, 0.0);
+        lost.gps_fix_type = 0;
+        ThetaMeta {
+            rdl2: Some(testing::rdl2(&[
+                Fix::new(100.0, 35.658581, 139.745433),
+                lost,
+                Fix::new(102.0, 35.659481, 139.745433),
+            ])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_projected_track() {
+        let meta = setup();
+        let enu = ProjectedTrack::new(&meta, CoordinateFrame::Enu).unwrap();
+        assert_eq!(enu.points.len(), 2);
+        assert_eq!(enu.origin.unwrap().latitude, 35.658581);
+        assert!(enu.points[0].x.abs() < 1e-9);
+        assert!((enu.points[1].y - 99.857).abs() < 0.01);
+
+        let utm = ProjectedTrack::new(&meta, CoordinateFrame::Utm).unwrap();
+        assert_eq!(utm.zone.map(|z| z.number), Some(54));
+        assert!((utm.points[0].x - 386_440.563).abs() < 0.01);
+        assert_eq!(utm.origin, None);
+
+        let csv = utm.to_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "timestamp,easting,northing,altitude");
+        assert!(lines[1].starts_with("100.000,386440.563,"));
+
+        let polar = ThetaMeta {
+            rdl2: Some(testing::rdl2(&[Fix::new(100.0, 85.0, 10.0)])),
+            ..Default::default()
+        };
+        assert!(ProjectedTrack::new(&polar, CoordinateFrame::Enu).is_ok());
+        assert_eq!(
+            ProjectedTrack::new(&polar, CoordinateFrame::Utm),
+            Err(ProjectionError::OutsideUtm)
+        );
+    }
+
+    #[test]
+    fn test_coordinate_frame() {
+        assert_eq!("ecef".parse(), Ok(CoordinateFrame::Ecef));
+        assert!("wgs84".parse::<CoordinateFrame>().is_err());
+        assert_eq!(
+            ProjectedTrack::new(&ThetaMeta::default(), CoordinateFrame::Enu),
+            Err(ProjectionError::NoFixes)
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Dead reckoning through GPS outages.
+//!
+//! Across a gap between two fixes, the path is integrated from the heading
+//! change measured by the IMU, starting from the GPS course before the gap, and
+//! a speed varying linearly between the speeds reported at either end. The drift
+//! accumulated by the time of the next fix is then spread linearly over the gap,
+//! so the path reconnects with it exactly.
+
+use serde::Serialize;
+
+use crate::{
+    fusion::{estimate_orientation, FusionConfig},
+    geo::GeoPoint,
+    math::{interpolate, unwrap_angle, Vector3},
+    theta::ThetaMeta,
+    timeline::Timeline,
+};
+
+/// Step of the heading integration, in seconds.
+const INTEGRATION_STEP: f64 = 0.05;
+/// Slowest reported speed trusted for a course, in m/s.
+const MIN_COURSE_SPEED: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadReckoningConfig {
+    /// Shortest interval between fixes treated as an outage, in seconds.
+    pub min_gap: f64,
+    /// Spacing of the estimated points, in seconds.
+    pub step: f64,
+}
+
+impl Default for DeadReckoningConfig {
+    fn default() -> Self {
+        DeadReckoningConfig {
+            min_gap: 2.0,
+            step: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct TrackPoint {
+    /// UTC seconds, as in RDL2.
+    pub timestamp: f64,
+    pub position: GeoPoint,
+    /// Estimated by dead reckoning rather than measured.
+    pub interpolated: bool,
+}
+
+/// A usable RDL2 fix.
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    /// Seconds from video start.
+    time: f64,
+    utc: f64,
+    position: GeoPoint,
+    /// East and north, in m/s.
+    velocity: (f64, f64),
+}
+
+impl Anchor {
+    fn speed(&self) -> f64 {
+        self.velocity.0.hypot(self.velocity.1)
+    }
+}
+
+/// Unwrapped yaw of the fused orientation, in radians counter-clockwise.
+fn yaw_series(meta: &ThetaMeta, timeline: &Timeline) -> Option<(Vec<f64>, Vec<f64>)> {
+    let (rdta, rdtb) = (meta.rdta.as_ref()?, meta.rdtb.as_ref()?);
+    let samples = estimate_orientation(
+        rdta,
+        rdtb,
+        meta.rdtc.as_ref(),
+        timeline,
+        FusionConfig::default(),
+    );
+    let mut prev: Option<f64> = None;
+    Some(
+        samples
+            .iter()
+            .map(|s| {
+                let (_, _, yaw) = s.orientation.to_euler();
+                let yaw = prev.map_or(yaw, |prev| unwrap_angle(prev, yaw));
+                prev = Some(yaw);
+                (s.time, yaw)
+            })
+            .unzip(),
+    )
+}
+
+/// Estimated points strictly between `a` and `b`, or `None` without IMU data there.
+fn bridge(
+    before: Option<&Anchor>,
+    a: &Anchor,
+    b: &Anchor,
+    yaw_at: impl Fn(f64) -> Option<f64>,
+    step: f64,
+) -> Option<Vec<TrackPoint>> {
+    let duration = b.time - a.time;
+    let yaw0 = yaw_at(a.time)?;
+    // GPS course in radians clockwise from north.
+    let course = if a.speed() >= MIN_COURSE_SPEED {
+        a.velocity.0.atan2(a.velocity.1)
+    } else if let Some(before) = before {
+        before.position.bearing_to(&a.position).to_radians()
+    } else {
+        a.position.bearing_to(&b.position).to_radians()
+    };
+    let (mut speed0, mut speed1) = (a.speed(), b.speed());
+    if speed0 < MIN_COURSE_SPEED && speed1 < MIN_COURSE_SPEED {
+        let average = a.position.distance_to(&b.position) / duration;
+        (speed0, speed1) = (average, average);
+    }
+
+    let steps = (duration / INTEGRATION_STEP).ceil() as usize;
+    let dt = duration / steps as f64;
+    let mut yaw = yaw0;
+    let mut path = Vec::with_capacity(steps + 1);
+    let mut p = Vector3::default();
+    path.push(p);
+    for k in 0..steps {
+        let t = a.time + (k as f64 + 0.5) * dt;
+        yaw = yaw_at(t).unwrap_or(yaw);
+        let heading = course - (yaw - yaw0);
+        let speed = speed0 + (speed1 - speed0) * (t - a.time) / duration;
+        p = p + Vector3::new(heading.sin(), heading.cos(), 0.0) * (speed * dt);
+        path.push(p);
+    }
+
+    let target = b.position.to_local(&a.position);
+    let drift = Vector3::new(target.x - p.x, target.y - p.y, 0.0);
+    let mut points = Vec::new();
+    let mut offset = step;
+    while offset < duration - 1e-6 {
+        let fraction = offset / duration;
+        let index = ((offset / dt).round() as usize).min(steps);
+        let local = path[index] + drift * fraction + Vector3::new(0.0, 0.0, target.z * fraction);
+        points.push(TrackPoint {
+            timestamp: a.utc + offset,
+            position: GeoPoint::from_local(&a.position, local),
+            interpolated: true,
+        });
+        offset += step;
+    }
+    Some(points)
+}
+
+/// RDL2 fixes with dead-reckoned points added in every outage, or `None`
+/// without RDL2, RDTA or RDTB.
+///
+/// Gaps outside the IMU recording are left as they are.
+pub fn bridge_gaps(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: DeadReckoningConfig,
+) -> Option<Vec<TrackPoint>> {
+    let rdl2 = meta.rdl2.as_ref()?;
+    let (times, yaws) = yaw_series(meta, timeline)?;
+    let yaw_at = |t| interpolate(&times, &yaws, t, |a, b, t| a + (b - a) * t);
+    let anchors: Vec<_> = rdl2
+        .fixes()
+        .filter_map(|e| {
+            Some(Anchor {
+                time: timeline.utc_to_seconds(e.timestamp)?,
+                utc: e.timestamp,
+                position: GeoPoint::new(e.latitude, e.longitude, e.altitude as f64),
+                velocity: (e.velocity_east as f64, e.velocity_north as f64),
+            })
+        })
+        .collect();
+
+    let mut track = Vec::with_capacity(anchors.len());
+    for (i, a) in anchors.iter().enumerate() {
+        track.push(TrackPoint {
+            timestamp: a.utc,
+            position: a.position,
+            interpolated: false,
+        });
+        let Some(b) = anchors
+            .get(i + 1)
+            .filter(|b| b.time - a.time > config.min_gap)
+        else {
+            continue;
+        };
+        let before = i.checked_sub(1).map(|j| &anchors[j]);
+        if let Some(points) = bridge(before, a, b, yaw_at, config.step) {
+            track.extend(points);
+        }
+    }
+    Some(track)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+    use std::f64::consts::FRAC_PI_2;
+
+    const TURN: f64 = 2.0 / std::f64::consts::PI;
+
+    /// North at 1 m/s for 20 s, a 1 s right turn, then east, seen by GPS only
+    /// until 10 s and after 30 s.
+    fn truth(t: f64) -> Vector3 {
+        if t <= 20.0 {
+            Vector3::new(0.0, t, 0.0)
+        } else if t <= 21.0 {
+            let h = FRAC_PI_2 * (t - 20.0);
+            Vector3::new(TURN * (1.0 - h.cos()), 20.0 + TURN * h.sin(), 0.0)
+        } else {
+            Vector3::new(TURN + t - 21.0, 20.0 + TURN, 0.0)
+        }
+    }
+
+    fn setup() -> ThetaMeta {
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        let fixes: Vec<_> = (0..=40)
+            .filter(|t| !(11..30).contains(t))
+            .map(|t| {
+                let p = GeoPoint::from_local(&origin, truth(t as f64));
+                let mut fix = Fix::new(100.0 + t as f64, p.latitude, p.longitude);
+                fix.velocity = if t <= 20 {
+                    [0.0, 1.0, 0.0]
+                } else {
+                    [1.0, 0.0, 0.0]
+                };
+                fix
+            })
+            .collect();
+        let ticks: Vec<u64> = (0..=4000).map(|i| i * 10_000).collect();
+        let accel: Vec<_> = ticks.iter().map(|&t| (0.0, 0.0, 1.0, t)).collect();
+        let gyro: Vec<_> = ticks
+            .iter()
+            .map(|&t| {
+                let turning = (20_000_000..21_000_000).contains(&t);
+                (0.0, 0.0, if turning { -FRAC_PI_2 as f32 } else { 0.0 }, t)
+            })
+            .collect();
+        ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtb: Some(testing::rdtb(&gyro)),
+            rdl2: Some(testing::rdl2(&fixes)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 30.0, 1200)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bridge_gaps() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let track = bridge_gaps(&meta, &timeline, Default::default()).unwrap();
+        assert_eq!(track.len(), 41);
+        assert_eq!(track.iter().filter(|p| p.interpolated).count(), 19);
+
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        for point in &track {
+            let t = point.timestamp - 100.0;
+            let local = point.position.to_local(&origin);
+            let error = (local - truth(t)).norm();
+            assert!(error < 0.5, "{} {:?} {}", t, local, error);
+            assert_eq!(point.interpolated, (11.0..30.0).contains(&t));
+        }
+    }
+
+    #[test]
+    fn test_requires_imu() {
+        let meta = ThetaMeta {
+            rdtb: None,
+            ..setup()
+        };
+        let timeline = Timeline::new(&meta);
+        assert_eq!(bridge_gaps(&meta, &timeline, Default::default()), None);
+    }
+}
@@ -0,0 +1,471 @@
+//! Detection of impacts, drops, jumps, free-fall and sharp turns from RDTA/RDTB.
+//!
+//! Each detector finds the regions where a signal crosses its threshold, joins
+//! regions closer than the minimum separation and reports the peak of each:
+//!
+//! - airborne: acceleration magnitude near zero for at least the minimum airtime;
+//!   a jump when it follows a push-off, a drop when it ends in an impact, and
+//!   free-fall otherwise,
+//! - impact: acceleration magnitude spikes not already part of a jump or drop,
+//! - sharp turn: angular rate about the vertical, taken from the slowly varying
+//!   part of the accelerometer, above the rate and angle thresholds.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::{math::Vector3, theta::ThetaMeta, timeline::Timeline};
+
+/// Seconds around an airborne phase searched for its push-off and landing.
+const TAKEOFF_LANDING_WINDOW: f64 = 0.3;
+/// Time constant of the gravity estimate, in seconds.
+const GRAVITY_TIME_CONSTANT: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Impact,
+    Drop,
+    Jump,
+    FreeFall,
+    SharpTurn,
+}
+
+impl EventKind {
+    pub fn title(self) -> &'static str {
+        match self {
+            EventKind::Impact => "Impact",
+            EventKind::Drop => "Drop",
+            EventKind::Jump => "Jump",
+            EventKind::FreeFall => "Free fall",
+            EventKind::SharpTurn => "Sharp turn",
+        }
+    }
+}
+
+/// Thresholds for a sensitivity of 1; a higher sensitivity lowers all of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventConfig {
+    pub sensitivity: f64,
+    /// Acceleration magnitude of an impact, in g.
+    pub impact_accel: f64,
+    /// Acceleration magnitude of a push-off before a jump, in g.
+    pub takeoff_accel: f64,
+    /// Largest acceleration magnitude while airborne, in g.
+    pub free_fall_accel: f64,
+    /// Shortest airborne phase, in seconds.
+    pub min_airtime: f64,
+    /// Angular rate about the vertical of a sharp turn, in rad/s.
+    pub turn_rate: f64,
+    /// Smallest heading change of a sharp turn, in degrees.
+    pub min_turn: f64,
+    /// Regions closer than this are one event, in seconds.
+    pub min_separation: f64,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        EventConfig {
+            sensitivity: 1.0,
+            impact_accel: 3.0,
+            takeoff_accel: 1.8,
+            free_fall_accel: 0.3,
+            min_airtime: 0.2,
+            turn_rate: 2.0,
+            min_turn: 45.0,
+            min_separation: 0.5,
+        }
+    }
+}
+
+impl EventConfig {
+    /// The thresholds at `sensitivity`.
+    fn scaled(&self) -> EventConfig {
+        let s = self.sensitivity.max(f64::EPSILON);
+        EventConfig {
+            sensitivity: 1.0,
+            impact_accel: 1.0 + (self.impact_accel - 1.0) / s,
+            takeoff_accel: 1.0 + (self.takeoff_accel - 1.0) / s,
+            free_fall_accel: (self.free_fall_accel * s).min(0.9),
+            min_airtime: self.min_airtime / s,
+            turn_rate: self.turn_rate / s,
+            min_turn: self.min_turn / s,
+            min_separation: self.min_separation,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    /// Seconds from video start of the peak, or of the start of an airborne phase.
+    pub time: f64,
+    pub start: f64,
+    pub end: f64,
+    /// Video frame at `time`.
+    pub frame: Option<usize>,
+    pub utc: Option<f64>,
+    /// Largest acceleration magnitude of an impact or landing, in g.
+    pub peak_accel: Option<f64>,
+    /// Seconds without support, for jumps, drops and free-fall.
+    pub airtime: Option<f64>,
+    /// Largest angular rate about the vertical, in rad/s.
+    pub peak_rate: Option<f64>,
+    /// Heading change of a turn in degrees, clockwise seen from above.
+    pub turn: Option<f64>,
+}
+
+impl Event {
+    fn new(kind: EventKind, time: f64, start: f64, end: f64) -> Event {
+        Event {
+            kind,
+            time,
+            start,
+            end,
+            frame: None,
+            utc: None,
+            peak_accel: None,
+            airtime: None,
+            peak_rate: None,
+            turn: None,
+        }
+    }
+}
+
+/// A chapter marker, in seconds from video start.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+/// Index ranges where `active` holds, joining those less than `join` seconds apart.
+fn regions(times: &[f64], active: impl Fn(usize) -> bool, join: f64) -> Vec<Range<usize>> {
+    let mut regions: Vec<Range<usize>> = Vec::new();
+    let mut open: Option<usize> = None;
+    for i in 0..=times.len() {
+        match (open, i < times.len() && active(i)) {
+            (None, true) => open = Some(i),
+            (Some(start), false) => {
+                match regions.last_mut() {
+                    Some(last) if times[start] - times[last.end - 1] < join => last.end = i,
+                    _ => regions.push(start..i),
+                }
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    regions
+}
+
+/// Time just after a region, at the next sample when there is one.
+fn end_time(times: &[f64], region: &Range<usize>) -> f64 {
+    times[region.end.min(times.len() - 1)]
+}
+
+/// Index of the first largest value in a region.
+fn peak(values: &[f64], region: &Range<usize>) -> usize {
+    region
+        .clone()
+        .max_by(|&a, &b| values[a].total_cmp(&values[b]).then(b.cmp(&a)))
+        .unwrap_or(region.start)
+}
+
+fn accel_events(times: &[f64], magnitudes: &[f64], config: &EventConfig) -> Vec<Event> {
+    if times.is_empty() {
+        return Vec::new();
+    }
+    let impacts = regions(
+        times,
+        |i| magnitudes[i] >= config.impact_accel,
+        config.min_separation,
+    );
+    let airborne: Vec<_> = regions(times, |i| magnitudes[i] <= config.free_fall_accel, 0.05)
+        .into_iter()
+        .filter(|r| end_time(times, r) - times[r.start] >= config.min_airtime)
+        .collect();
+
+    let mut consumed = vec![false; impacts.len()];
+    let mut events = Vec::new();
+    for r in &airborne {
+        let (start, end) = (times[r.start], end_time(times, r));
+        let before = times.partition_point(|&t| t < start - TAKEOFF_LANDING_WINDOW);
+        let takeoff = (before..r.start).any(|i| magnitudes[i] >= config.takeoff_accel);
+        let landing = impacts.iter().position(|impact| {
+            let t = times[impact.start];
+            t >= end - 1e-9 && t - end <= TAKEOFF_LANDING_WINDOW
+        });
+        // Spikes of the push-off are part of the jump.
+        for (i, impact) in impacts.iter().enumerate() {
+            if takeoff
+                && impact.end <= r.start
+                && start - times[impact.end - 1] <= TAKEOFF_LANDING_WINDOW
+            {
+                consumed[i] = true;
+            }
+        }
+        let kind = match (takeoff, landing) {
+            (true, _) => EventKind::Jump,
+            (false, Some(_)) => EventKind::Drop,
+            (false, None) => EventKind::FreeFall,
+        };
+        let mut event = Event::new(kind, start, start, end);
+        event.airtime = Some(end - start);
+        if let Some(l) = landing {
+            consumed[l] = true;
+            event.end = end_time(times, &impacts[l]);
+            event.peak_accel = Some(magnitudes[peak(magnitudes, &impacts[l])]);
+        }
+        events.push(event);
+    }
+    for (impact, _) in impacts.iter().zip(consumed).filter(|(_, c)| !c) {
+        let p = peak(magnitudes, impact);
+        let mut event = Event::new(
+            EventKind::Impact,
+            times[p],
+            times[impact.start],
+            end_time(times, impact),
+        );
+        event.peak_accel = Some(magnitudes[p]);
+        events.push(event);
+    }
+    events
+}
+
+fn turn_events(
+    accel: &[(f64, Vector3)],
+    gyro: &[(f64, Vector3)],
+    config: &EventConfig,
+) -> Vec<Event> {
+    // Gravity, low-passed from the accelerometer, at every accelerometer sample.
+    let mut gravity = Vec::with_capacity(accel.len());
+    let mut state: Option<(f64, Vector3)> = None;
+    for &(t, a) in accel {
+        let g = match state {
+            Some((prev, g)) => {
+                let alpha = ((t - prev) / GRAVITY_TIME_CONSTANT).clamp(0.0, 1.0);
+                g.lerp(a, alpha)
+            }
+            None => a,
+        };
+        state = Some((t, g));
+        gravity.push(g);
+    }
+    let (times, rates): (Vec<f64>, Vec<f64>) = gyro
+        .iter()
+        .filter_map(|&(t, w)| {
+            let i = accel.partition_point(|&(at, _)| at <= t).checked_sub(1)?;
+            // The accelerometer reads +1 g upwards at rest.
+            let up = gravity[i].normalize()?;
+            Some((t, w.dot(up)))
+        })
+        .unzip();
+    let magnitudes: Vec<f64> = rates.iter().map(|r| r.abs()).collect();
+    regions(
+        &times,
+        |i| magnitudes[i] >= config.turn_rate,
+        config.min_separation,
+    )
+    .into_iter()
+    .filter_map(|r| {
+        let p = peak(&magnitudes, &r);
+        let angle: f64 = r
+            .clone()
+            .map(|i| rates[i] * (times.get(i + 1).unwrap_or(&times[i]) - times[i]))
+            .sum();
+        let turn = -angle.to_degrees();
+        if turn.abs() < config.min_turn {
+            return None;
+        }
+        let mut event = Event::new(
+            EventKind::SharpTurn,
+            times[p],
+            times[r.start],
+            end_time(&times, &r),
+        );
+        event.peak_rate = Some(magnitudes[p]);
+        event.turn = Some(turn);
+        Some(event)
+    })
+    .collect()
+}
+
+/// Events in time order, or `None` without RDTA or RDTB.
+pub fn detect_events(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: EventConfig,
+) -> Option<Vec<Event>> {
+    let (rdta, rdtb) = (meta.rdta.as_ref()?, meta.rdtb.as_ref()?);
+    let config = config.scaled();
+    let accel: Vec<_> = rdta
+        .entries()
+        .iter()
+        .map(|e| {
+            (
+                timeline.tick_to_seconds(e.timestamp),
+                Vector3::from_f32(e.x, e.y, e.z),
+            )
+        })
+        .collect();
+    let gyro: Vec<_> = rdtb
+        .entries()
+        .iter()
+        .map(|e| {
+            (
+                timeline.tick_to_seconds(e.timestamp),
+                Vector3::from_f32(e.x, e.y, e.z),
+            )
+        })
+        .collect();
+    let (times, magnitudes): (Vec<f64>, Vec<f64>) =
+        accel.iter().map(|&(t, a)| (t, a.norm())).unzip();
+
+    let mut events = accel_events(&times, &magnitudes, &config);
+    events.extend(turn_events(&accel, &gyro, &config));
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    for event in &mut events {
+        event.frame = timeline.frame_at(event.time);
+        event.utc = timeline.seconds_to_utc(event.time);
+    }
+    Some(events)
+}
+
+/// One chapter from each event to the next, the last ending at `end`, after an
+/// opening chapter when the first event is not at the start.
+pub fn chapters(events: &[Event], end: f64) -> Vec<Chapter> {
+    let mut chapters = Vec::with_capacity(events.len() + 1);
+    let first = events.first().map_or(end, |e| e.time.max(0.0));
+    if first > 0.0 {
+        chapters.push(Chapter {
+            start: 0.0,
+            end: first,
+            title: "Start".to_string(),
+        });
+    }
+    for (i, event) in events.iter().enumerate() {
+        let detail = match (event.peak_accel, event.airtime, event.turn) {
+            (_, Some(airtime), _) => format!("{:.2} s", airtime),
+            (Some(accel), None, _) => format!("{:.1} g", accel),
+            (None, None, Some(turn)) => format!("{:.0}°", turn),
+            (None, None, None) => String::new(),
+        };
+        chapters.push(Chapter {
+            start: event.time.max(0.0),
+            end: events
+                .get(i + 1)
+                .map_or(end, |next| next.time)
+                .max(event.time),
+            title: format!("{} {}", event.kind.title(), detail)
+                .trim_end()
+                .to_string(),
+        });
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::f64::consts::PI;
+
+    /// Impact at 3 s, drop at 6 s, jump at 10 s, free-fall at 13 s, a quarter
+    /// turn to the right at 16 s and a light knock at 18 s.
+    fn setup() -> ThetaMeta {
+        let ticks: Vec<u64> = (0..2000).map(|i| i * 10_000).collect();
+        let seconds = |tick: u64| tick as f64 / 1e6;
+        let within = |t: f64, start: f64, end: f64| t >= start - 1e-9 && t < end - 1e-9;
+        let accel: Vec<_> = ticks
+            .iter()
+            .map(|&tick| {
+                let t = seconds(tick);
+                let z = if within(t, 3.0, 3.03) {
+                    5.0
+                } else if within(t, 6.0, 6.5) || within(t, 10.0, 10.6) || within(t, 13.0, 13.4) {
+                    0.05
+                } else if within(t, 6.5, 6.53) {
+                    4.0
+                } else if within(t, 9.8, 10.0) {
+                    2.2
+                } else if within(t, 10.6, 10.65) {
+                    3.5
+                } else if within(t, 18.0, 18.03) {
+                    2.5
+                } else {
+                    1.0
+                };
+                (0.0, 0.0, z as f32, tick)
+            })
+            .collect();
+        let gyro: Vec<_> = ticks
+            .iter()
+            .map(|&tick| {
+                let turning = within(seconds(tick), 16.0, 16.5);
+                (0.0, 0.0, if turning { -PI as f32 } else { 0.0 }, tick)
+            })
+            .collect();
+        ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtb: Some(testing::rdtb(&gyro)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 30.0, 600)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detect_events() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let events = detect_events(&meta, &timeline, Default::default()).unwrap();
+        let kinds: Vec<_> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                EventKind::Impact,
+                EventKind::Drop,
+                EventKind::Jump,
+                EventKind::FreeFall,
+                EventKind::SharpTurn
+            ]
+        );
+        assert!((events[0].time - 3.0).abs() < 0.02);
+        assert_eq!(events[0].frame, Some(90));
+        assert_eq!(events[0].utc, Some(103.0));
+        assert_eq!(events[0].peak_accel, Some(5.0));
+        assert!((events[1].airtime.unwrap() - 0.5).abs() < 0.02);
+        assert_eq!(events[1].peak_accel, Some(4.0));
+        assert!((events[2].airtime.unwrap() - 0.6).abs() < 0.02);
+        assert_eq!(events[3].peak_accel, None);
+        let turn = events[4].turn.unwrap();
+        assert!((turn - 90.0).abs() < 2.0, "{}", turn);
+
+        let config = EventConfig {
+            sensitivity: 2.0,
+            ..Default::default()
+        };
+        let events = detect_events(&meta, &timeline, config).unwrap();
+        let knock = events.last().unwrap();
+        assert_eq!(knock.kind, EventKind::Impact);
+        assert!((knock.time - 18.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_chapters() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let events = detect_events(&meta, &timeline, Default::default()).unwrap();
+        let chapters = chapters(&events, 20.0);
+        assert_eq!(chapters.len(), 6);
+        assert_eq!(chapters[0].title, "Start");
+        assert_eq!(chapters[0].start, 0.0);
+        assert_eq!(chapters[1].title, "Impact 5.0 g");
+        assert_eq!(chapters[1].end, chapters[2].start);
+        assert_eq!(chapters[3].title, "Jump 0.60 s");
+        assert_eq!(chapters[5].title, "Sharp turn 90°");
+        assert_eq!(chapters[5].end, 20.0);
+    }
+}
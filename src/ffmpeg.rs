@@ -0,0 +1,197 @@
+//! ffmpeg `sendcmd` scripts driving the `v360` filter from [`HorizonCorrection`]s.
+//!
+//! Each frame gets the inverse of the camera rotation, so `v360` is run with
+//! `rorder=rpy` and negated angles.
+//!
+//! Also builds the stream-copy commands cutting a recording to a
+//! [`crate::segmentation::TrimRange`] and adding chapter markers.
+
+use std::fmt::Write;
+
+use crate::{events::Chapter, horizon::HorizonCorrection};
+
+/// `v360` options the script's commands act on.
+pub const V360_FILTER: &str = "v360=e:e:rorder=rpy";
+
+/// `sendcmd` file setting `v360` roll, pitch and, with `heading_lock`, yaw per frame.
+///
+/// The THETA sensor axes are used as the `v360` axes: roll is about sensor x,
+/// pitch about y and yaw about z. A correction holds the camera rotation
+/// `Rz(yaw) Ry(pitch) Rx(roll)` of [`crate::math::Quaternion::from_euler`], whose
+/// inverse `Rx(-roll) Ry(-pitch) Rz(-yaw)` is the roll-pitch-yaw sequence selected
+/// by `rorder=rpy` in [`V360_FILTER`], so every angle is written negated.
+pub fn sendcmd_script(corrections: &[HorizonCorrection], heading_lock: bool) -> String {
+    // Subtracting from zero avoids printing `-0.0000`.
+    let inverse = |angle: f64| 0.0 - angle;
+    let mut script = String::new();
+    for c in corrections {
+        let yaw = if heading_lock { inverse(c.yaw) } else { 0.0 };
+        writeln!(
+            script,
+            "{:.6} v360 roll {:.4}, v360 pitch {:.4}, v360 yaw {:.4};",
+            c.time,
+            inverse(c.roll),
+            inverse(c.pitch),
+            yaw
+        )
+        .unwrap();
+    }
+    script
+}
+
+/// ffmpeg invocation applying the script at `script_path` to `input`.
+///
+/// Backslashes in `script_path` become slashes, which ffmpeg accepts on Windows too.
+pub fn command_line(input: &str, script_path: &str, output: &str) -> String {
+    format!(
+        "ffmpeg -i \"{}\" -vf \"sendcmd=f='{}',{}\" -c:a copy \"{}\"",
+        input,
+        script_path.replace('\\', "/"),
+        V360_FILTER,
+        output
+    )
+}
+
+/// ffmpeg invocation copying the streams of `input` between two times in seconds.
+pub fn trim_command_line(input: &str, start: f64, end: f64, output: &str) -> String {
+    format!(
+        "ffmpeg -ss {:.3} -to {:.3} -i \"{}\" -map 0 -c copy \"{}\"",
+        start, end, input, output
+    )
+}
+
+/// FFMETADATA file with `chapters`, in milliseconds.
+pub fn chapters_metadata(chapters: &[Chapter]) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for c in chapters {
+        let mut title = String::with_capacity(c.title.len());
+        for ch in c.title.chars() {
+            if matches!(ch, '=' | ';' | '#' | '\\' | '\n') {
+                title.push('\\');
+            }
+            title.push(ch);
+        }
+        writeln!(
+            metadata,
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}",
+            (c.start * 1000.0).round() as u64,
+            (c.end * 1000.0).round() as u64,
+            title
+        )
+        .unwrap();
+    }
+    metadata
+}
+
+/// ffmpeg invocation copying `input` with the chapters of the metadata file.
+pub fn chapters_command_line(input: &str, metadata_path: &str, output: &str) -> String {
+    format!(
+        "ffmpeg -i \"{}\" -i \"{}\" -map 0 -map_chapters 1 -c copy \"{}\"",
+        input,
+        metadata_path.replace('\\', "/"),
+        output
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{horizon::horizon_corrections, testing, theta::ThetaMeta, timeline::Timeline};
+
+    fn setup() -> Vec<HorizonCorrection> {
+        vec![
+            HorizonCorrection {
+                frame: 0,
+                time: 0.0,
+                roll: 1.5,
+                pitch: -2.0,
+                yaw: 0.0,
+            },
+            HorizonCorrection {
+                frame: 1,
+                time: 1.0 / 30.0,
+                roll: 1.25,
+                pitch: -2.5,
+                yaw: 10.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_sendcmd_script() {
+        let corrections = setup();
+        assert_eq!(
+            sendcmd_script(&corrections, false),
+            "0.000000 v360 roll -1.5000, v360 pitch 2.0000, v360 yaw 0.0000;\n\
+             0.033333 v360 roll -1.2500, v360 pitch 2.5000, v360 yaw 0.0000;\n"
+        );
+        let locked = sendcmd_script(&corrections, true);
+        assert!(locked
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("v360 yaw -10.0000;"));
+    }
+
+    #[test]
+    fn test_sendcmd_script_from_tilt() {
+        // Gravity of a camera rolled by 10 and pitched by -20 degrees.
+        let (roll, pitch) = (10f32.to_radians(), -20f32.to_radians());
+        let gravity = (
+            -pitch.sin(),
+            pitch.cos() * roll.sin(),
+            pitch.cos() * roll.cos(),
+        );
+        let accel: Vec<_> = (0..=100)
+            .map(|i| (gravity.0, gravity.1, gravity.2, i * 10_000))
+            .collect();
+        let meta = ThetaMeta {
+            rdtg: Some(testing::rdtg(&[0, 500_000])),
+            rdta: Some(testing::rdta(&accel)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 2.0, 2)),
+            ..Default::default()
+        };
+        let corrections = horizon_corrections(&meta, &Timeline::new(&meta), Default::default());
+        assert_eq!(
+            sendcmd_script(&corrections, false),
+            "0.000000 v360 roll -10.0000, v360 pitch 20.0000, v360 yaw 0.0000;\n\
+             0.500000 v360 roll -10.0000, v360 pitch 20.0000, v360 yaw 0.0000;\n"
+        );
+    }
+
+    #[test]
+    fn test_command_line() {
+        assert_eq!(
+            command_line("R0010001.MP4", "R0010001.cmd", "R0010001_level.mp4"),
+            "ffmpeg -i \"R0010001.MP4\" -vf \"sendcmd=f='R0010001.cmd',v360=e:e:rorder=rpy\" \
+             -c:a copy \"R0010001_level.mp4\""
+        );
+        assert_eq!(
+            trim_command_line("R0010001.MP4", 9.5, 30.0, "R0010001_trim.mp4"),
+            "ffmpeg -ss 9.500 -to 30.000 -i \"R0010001.MP4\" -map 0 -c copy \"R0010001_trim.mp4\""
+        );
+    }
+
+    #[test]
+    fn test_chapters_metadata() {
+        let chapters = [
+            Chapter {
+                start: 0.0,
+                end: 3.0,
+                title: "Start".to_string(),
+            },
+            Chapter {
+                start: 3.0,
+                end: 12.3456,
+                title: "Impact; 5.0 g".to_string(),
+            },
+        ];
+        assert_eq!(
+            chapters_metadata(&chapters),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=3000\ntitle=Start\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=3000\nEND=12346\ntitle=Impact\\; 5.0 g\n"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use serde::Serialize;
+
+use crate::{
+    geo::{self, GeoPoint},
+    math::{interpolate, Vector3},
+    theta::ThetaMeta,
+    timeline::Timeline,
+};
+
+/// Sensor readings interpolated at one presentation time.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FrameSensors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame: Option<usize>,
+    /// Seconds from video start.
+    pub time: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utc: Option<f64>,
+    pub acceleration: Option<Vector3>,
+    pub angular_velocity: Option<Vector3>,
+    pub magnetic_field: Option<Vector3>,
+    pub position: Option<GeoPoint>,
+}
+
+#[derive(Debug, Clone)]
+struct Series<T> {
+    times: Vec<f64>,
+    values: Vec<T>,
+}
+
+impl<T> Default for Series<T> {
+    fn default() -> Self {
+        Series {
+            times: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy> Series<T> {
+    fn new(samples: impl Iterator<Item = (f64, T)>) -> Series<T> {
+        let (times, values) = samples.unzip();
+        Series { times, values }
+    }
+
+    fn at(&self, time: f64, lerp: impl Fn(T, T, f64) -> T) -> Option<T> {
+        interpolate(&self.times, &self.values, time, lerp)
+    }
+}
+
+/// Interpolates accelerometer, gyroscope, magnetometer and GPS at frame times.
+///
+/// Vectors are interpolated linearly and positions along the great circle.
+/// Position comes from RDL2, or from RDTL when RDL2 is absent.
+#[derive(Debug, Clone)]
+pub struct FrameLookup {
+    timeline: Timeline,
+    acceleration: Series<Vector3>,
+    angular_velocity: Series<Vector3>,
+    magnetic_field: Series<Vector3>,
+    position: Series<GeoPoint>,
+}
+
+/// GPS positions in seconds from video start, from the fixes of [`geo::track`].
+pub(crate) fn positions(meta: &ThetaMeta, timeline: &Timeline) -> Vec<(f64, GeoPoint)> {
+    geo::track(meta)
+        .into_iter()
+        .filter_map(|fix| Some((timeline.utc_to_seconds(fix.timestamp)?, fix.position)))
+        .collect()
+}
+
+macro_rules! imu_series {
+    ($box:expr, $timeline:expr) => {
+        $box.as_ref()
+            .map(|b| {
+                Series::new(b.entries().iter().map(|e| {
+                    (
+                        $timeline.tick_to_seconds(e.timestamp),
+                        Vector3::from_f32(e.x, e.y, e.z),
+                    )
+                }))
+            })
+            .unwrap_or_default()
+    };
+}
+
+impl FrameLookup {
+    pub fn new(meta: &ThetaMeta, timeline: Timeline) -> FrameLookup {
+        let position = Series::new(positions(meta, &timeline).into_iter());
+        FrameLookup {
+            acceleration: imu_series!(meta.rdta, timeline),
+            angular_velocity: imu_series!(meta.rdtb, timeline),
+            magnetic_field: imu_series!(meta.rdtc, timeline),
+            position,
+            timeline,
+        }
+    }
+
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// Sensors at `time` seconds from video start.
+    pub fn at_time(&self, time: f64) -> FrameSensors {
+        FrameSensors {
+            frame: self.timeline.frame_at(time),
+            time,
+            utc: self.timeline.seconds_to_utc(time),
+            acceleration: self.acceleration.at(time, Vector3::lerp),
+            angular_velocity: self.angular_velocity.at(time, Vector3::lerp),
+            magnetic_field: self.magnetic_field.at(time, Vector3::lerp),
+            position: self.position.at(time, GeoPoint::interpolate),
+        }
+    }
+
+    /// Sensors at the presentation time of `frame`.
+    pub fn at_frame(&self, frame: usize) -> Option<FrameSensors> {
+        let time = self.timeline.frame_time(frame)?;
+        Some(FrameSensors {
+            frame: Some(frame),
+            ..self.at_time(time)
+        })
+    }
+
+    /// Sensors for every video frame, in order.
+    pub fn frames(&self) -> impl Iterator<Item = FrameSensors> + '_ {
+        (0..self.timeline.frame_count()).filter_map(|frame| self.at_frame(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    fn setup() -> ThetaMeta {
+        let mut lost = Fix::new(100.5, 0.0, 0.0);
+        lost.gps_fix_type = 0;
+        ThetaMeta {
+            rdtg: Some(testing::rdtg(&[0, 500_000, 1_000_000])),
+            rdta: Some(testing::rdta(&[
+                (0.0, 0.0, 0.0, 0),
+                (4.0, 0.0, 0.0, 1_000_000),
+            ])),
+            rdtb: Some(testing::rdtb(&[
+                (0.0, 0.0, 1.0, 0),
+                (0.0, 0.0, 3.0, 1_000_000),
+            ])),
+            rdtc: Some(testing::rdtc(&[(30.0, 0.0, 0.0, 0)])),
+            rdl2: Some(testing::rdl2(&[
+                Fix::new(100.0, 35.0, 139.0),
+                lost,
+                Fix::new(101.0, 35.0, 139.001),
+            ])),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 2.0, 3)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_at_frame() {
+        let meta = setup();
+        let lookup = FrameLookup::new(&meta, Timeline::new(&meta));
+        let sensors = lookup.at_frame(1).unwrap();
+        assert_eq!(sensors.time, 0.5);
+        assert_eq!(sensors.utc, Some(100.5));
+        assert_eq!(sensors.acceleration, Some(Vector3::new(2.0, 0.0, 0.0)));
+        assert_eq!(sensors.angular_velocity, Some(Vector3::new(0.0, 0.0, 2.0)));
+        assert_eq!(sensors.magnetic_field, None);
+        let position = sensors.position.unwrap();
+        assert!((position.longitude - 139.0005).abs() < 1e-9);
+        assert_eq!(position.altitude, 10.0);
+
+        assert_eq!(lookup.frames().count(), 3);
+        assert!(lookup.at_frame(3).is_none());
+    }
+
+    #[test]
+    fn test_frame_sensors_to_json() {
+        let meta = setup();
+        let lookup = FrameLookup::new(&meta, Timeline::new(&meta));
+        let json = serde_json::to_value(lookup.at_frame(0).unwrap()).unwrap();
+        assert_eq!(json["frame"], 0);
+        assert_eq!(json["acceleration"]["x"], 0.0);
+        assert_eq!(json["magnetic_field"]["x"], 30.0);
+        assert_eq!(json["position"]["latitude"], 35.0);
+    }
+}
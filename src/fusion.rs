@@ -0,0 +1,260 @@
+//! Orientation estimation from the IMU tables.
+//!
+//! [`Madgwick`] fuses the gyroscope (RDTB, rad/s) with the direction of gravity from
+//! the accelerometer (RDTA) and, optionally, of the magnetic field from the
+//! magnetometer (RDTC). The world frame has `z` pointing up and, when the
+//! magnetometer is used, `x` pointing to magnetic north in the horizontal plane.
+
+use serde::Serialize;
+
+use crate::{
+    math::{interpolate, Quaternion, Vector3},
+    theta::{rdta::RdtaBox, rdtb::RdtbBox, rdtc::RdtcBox},
+    timeline::Timeline,
+};
+
+const UP: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+
+/// Madgwick gradient-descent orientation filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Madgwick {
+    gain: f64,
+    orientation: Quaternion,
+}
+
+impl Madgwick {
+    /// Filter starting from the identity; `gain` is Madgwick's beta (rad/s).
+    pub fn new(gain: f64) -> Madgwick {
+        Madgwick {
+            gain,
+            orientation: Quaternion::IDENTITY,
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: Quaternion) -> Madgwick {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        self.orientation
+    }
+
+    /// Advances the filter by `dt` seconds.
+    ///
+    /// Without an accelerometer reading the gyroscope is integrated alone.
+    pub fn update(&mut self, gyro: Vector3, accel: Option<Vector3>, mag: Option<Vector3>, dt: f64) {
+        let q = self.orientation;
+        let mut rate = q * Quaternion::new(0.0, gyro.x, gyro.y, gyro.z) * 0.5;
+        if let Some(step) = accel
+            .and_then(Vector3::normalize)
+            .and_then(|a| self.gradient(a, mag.and_then(Vector3::normalize)))
+        {
+            rate = rate - step * self.gain;
+        }
+        self.orientation = (q + rate * dt).normalize().unwrap_or(q);
+    }
+
+    /// Normalized gradient of the alignment error of gravity and the magnetic field.
+    fn gradient(&self, a: Vector3, m: Option<Vector3>) -> Option<Quaternion> {
+        let Quaternion { w, x, y, z } = self.orientation;
+        // Gravity predicted in the sensor frame minus the measured one.
+        let f = Vector3::new(
+            2.0 * (x * z - w * y) - a.x,
+            2.0 * (w * x + y * z) - a.y,
+            2.0 * (0.5 - x * x - y * y) - a.z,
+        );
+        let mut g = Quaternion::new(
+            -2.0 * y * f.x + 2.0 * x * f.y,
+            2.0 * z * f.x + 2.0 * w * f.y - 4.0 * x * f.z,
+            -2.0 * w * f.x + 2.0 * z * f.y - 4.0 * y * f.z,
+            2.0 * x * f.x + 2.0 * y * f.y,
+        );
+        if let Some(m) = m {
+            // Reference field: the measured one in the world frame, without its east part.
+            let h = self.orientation.rotate(m);
+            let (bx, bz) = ((h.x * h.x + h.y * h.y).sqrt(), h.z);
+            let f = Vector3::new(
+                2.0 * bx * (0.5 - y * y - z * z) + 2.0 * bz * (x * z - w * y) - m.x,
+                2.0 * bx * (x * y - w * z) + 2.0 * bz * (w * x + y * z) - m.y,
+                2.0 * bx * (w * y + x * z) + 2.0 * bz * (0.5 - x * x - y * y) - m.z,
+            );
+            g = g + Quaternion::new(
+                -2.0 * bz * y * f.x + (-2.0 * bx * z + 2.0 * bz * x) * f.y + 2.0 * bx * y * f.z,
+                2.0 * bz * z * f.x
+                    + (2.0 * bx * y + 2.0 * bz * w) * f.y
+                    + (2.0 * bx * z - 4.0 * bz * x) * f.z,
+                (-4.0 * bx * y - 2.0 * bz * w) * f.x
+                    + (2.0 * bx * x + 2.0 * bz * z) * f.y
+                    + (2.0 * bx * w - 4.0 * bz * y) * f.z,
+                (-4.0 * bx * z + 2.0 * bz * x) * f.x
+                    + (-2.0 * bx * w + 2.0 * bz * y) * f.y
+                    + 2.0 * bx * x * f.z,
+            );
+        }
+        g.normalize()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FusionConfig {
+    /// Madgwick beta; larger values trust the accelerometer and magnetometer more.
+    pub gain: f64,
+    pub use_magnetometer: bool,
+    /// Start from the orientation that levels the first accelerometer reading.
+    pub align_to_gravity: bool,
+}
+
+impl Default for FusionConfig {
+    fn default() -> Self {
+        FusionConfig {
+            gain: 0.1,
+            use_magnetometer: true,
+            align_to_gravity: true,
+        }
+    }
+}
+
+/// Orientation at `time` seconds from video start.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct OrientationSample {
+    pub time: f64,
+    pub orientation: Quaternion,
+}
+
+/// Splits `(x, y, z, tick)` samples into seconds from video start and vectors.
+pub(crate) fn vectors<'a>(
+    entries: impl Iterator<Item = (f32, f32, f32, u64)> + 'a,
+    timeline: &'a Timeline,
+) -> (Vec<f64>, Vec<Vector3>) {
+    entries
+        .map(|(x, y, z, t)| (timeline.tick_to_seconds(t), Vector3::from_f32(x, y, z)))
+        .unzip()
+}
+
+/// Runs [`Madgwick`] over every gyroscope sample.
+///
+/// Accelerometer and magnetometer readings are interpolated at gyroscope times.
+pub fn estimate_orientation(
+    rdta: &RdtaBox,
+    rdtb: &RdtbBox,
+    rdtc: Option<&RdtcBox>,
+    timeline: &Timeline,
+    config: FusionConfig,
+) -> Vec<OrientationSample> {
+    let (accel_times, accel) = vectors(
+        rdta.entries().iter().map(|e| (e.x, e.y, e.z, e.timestamp)),
+        timeline,
+    );
+    let (mag_times, mag) = match rdtc.filter(|_| config.use_magnetometer) {
+        Some(rdtc) => vectors(
+            rdtc.entries().iter().map(|e| (e.x, e.y, e.z, e.timestamp)),
+            timeline,
+        ),
+        None => Default::default(),
+    };
+    let accel_at = |t| interpolate(&accel_times, &accel, t, Vector3::lerp);
+    let mag_at = |t| interpolate(&mag_times, &mag, t, Vector3::lerp);
+
+    let mut filter = Madgwick::new(config.gain);
+    let mut samples = Vec::with_capacity(rdtb.entries().len());
+    let mut prev: Option<f64> = None;
+    for entry in rdtb.entries() {
+        let time = timeline.tick_to_seconds(entry.timestamp);
+        let gyro = Vector3::from_f32(entry.x, entry.y, entry.z);
+        match prev {
+            Some(prev) if time > prev => {
+                filter.update(gyro, accel_at(time), mag_at(time), time - prev)
+            }
+            Some(_) => continue,
+            None if config.align_to_gravity => {
+                let gravity = accel_at(time).or_else(|| accel.first().copied());
+                if let Some(q) = gravity.and_then(|a| Quaternion::from_rotation_arc(a, UP)) {
+                    filter = filter.with_orientation(q);
+                }
+            }
+            None => {}
+        }
+        prev = Some(time);
+        samples.push(OrientationSample {
+            time,
+            orientation: filter.orientation(),
+        });
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing, theta::ThetaMeta};
+
+    fn assert_close(a: Vector3, b: Vector3, tolerance: f64) {
+        assert!((a - b).norm() < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    fn setup() -> ThetaMeta {
+        let ticks: Vec<u64> = (0..=100).map(|i| i * 10_000).collect();
+        let accel: Vec<_> = ticks.iter().map(|&t| (0.0, 0.6, 0.8, t)).collect();
+        let gyro: Vec<_> = ticks.iter().map(|&t| (0.0, 0.0, 0.0, t)).collect();
+        let mag: Vec<_> = ticks.iter().map(|&t| (0.0, 1.0, 0.0, t)).collect();
+        ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtb: Some(testing::rdtb(&gyro)),
+            rdtc: Some(testing::rdtc(&mag)),
+            modl: "RICOH THETA X".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn estimate(meta: &ThetaMeta, config: FusionConfig) -> Vec<OrientationSample> {
+        let timeline = Timeline::new(meta);
+        let rdtc = meta.rdtc.as_ref();
+        estimate_orientation(
+            meta.rdta.as_ref().unwrap(),
+            meta.rdtb.as_ref().unwrap(),
+            rdtc,
+            &timeline,
+            config,
+        )
+    }
+
+    #[test]
+    fn test_align_to_gravity() {
+        let meta = setup();
+        let config = FusionConfig {
+            use_magnetometer: false,
+            ..Default::default()
+        };
+        let samples = estimate(&meta, config);
+        assert_eq!(samples.len(), 101);
+        assert_eq!(samples[100].time, 1.0);
+        let gravity = Vector3::new(0.0, 0.6, 0.8);
+        assert_close(samples[0].orientation.rotate(gravity), UP, 1e-6);
+        assert_close(samples[100].orientation.rotate(gravity), UP, 1e-3);
+    }
+
+    #[test]
+    fn test_gyro_integration() {
+        let mut filter = Madgwick::new(0.1);
+        for _ in 0..100 {
+            filter.update(Vector3::new(0.0, 0.0, 1.0), Some(UP), None, 0.01);
+        }
+        let x = filter.orientation().rotate(Vector3::new(1.0, 0.0, 0.0));
+        assert_close(x, Vector3::new(1.0f64.cos(), 1.0f64.sin(), 0.0), 1e-3);
+    }
+
+    #[test]
+    fn test_magnetometer_heading() {
+        let meta = setup();
+        let config = FusionConfig {
+            gain: 2.0,
+            ..Default::default()
+        };
+        let samples = estimate(&meta, config);
+        let q = samples.last().unwrap().orientation;
+        let north = q.rotate(Vector3::new(0.0, 1.0, 0.0));
+        assert!(north.x > 0.0 && north.y.abs() < 0.05, "{:?}", north);
+        assert_close(q.rotate(Vector3::new(0.0, 0.6, 0.8)), UP, 0.05);
+    }
+}
@@ -0,0 +1,456 @@
+use serde::Serialize;
+
+use crate::{math::Vector3, theta::ThetaMeta};
+
+/// WGS84 equatorial radius in metres.
+pub const EARTH_RADIUS: f64 = 6_378_137.0;
+/// Mean radius in metres, for great-circle distances.
+const MEAN_RADIUS: f64 = 6_371_008.8;
+/// WGS84 flattening.
+pub const FLATTENING: f64 = 1.0 / 298.257_223_563;
+/// Square of the first eccentricity.
+const E2: f64 = FLATTENING * (2.0 - FLATTENING);
+/// UTM scale factor on the central meridian.
+const UTM_SCALE: f64 = 0.9996;
+/// Latitudes covered by UTM; the polar regions use UPS instead.
+const UTM_LATITUDES: std::ops::RangeInclusive<f64> = -80.0..=84.0;
+
+/// A UTM zone and hemisphere.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct UtmZone {
+    pub number: u8,
+    pub north: bool,
+}
+
+impl UtmZone {
+    /// Zone containing `point`, including the Norway and Svalbard exceptions;
+    /// `None` outside 80°S to 84°N.
+    pub fn of(point: &GeoPoint) -> Option<UtmZone> {
+        if !UTM_LATITUDES.contains(&point.latitude) {
+            return None;
+        }
+        let (lat, lon) = (
+            point.latitude,
+            (point.longitude + 180.0).rem_euclid(360.0) - 180.0,
+        );
+        let mut number = ((lon + 180.0) / 6.0).floor() as u8 % 60 + 1;
+        if (56.0..64.0).contains(&lat) && (3.0..12.0).contains(&lon) {
+            number = 32;
+        } else if (72.0..84.0).contains(&lat) && (0.0..42.0).contains(&lon) {
+            number = match lon {
+                lon if lon < 9.0 => 31,
+                lon if lon < 21.0 => 33,
+                lon if lon < 33.0 => 35,
+                _ => 37,
+            };
+        }
+        Some(UtmZone {
+            number,
+            north: lat >= 0.0,
+        })
+    }
+
+    /// Central meridian in degrees.
+    pub fn central_meridian(&self) -> f64 {
+        self.number as f64 * 6.0 - 183.0
+    }
+
+    /// Angle from true north to grid north at `point`, in degrees clockwise.
+    pub fn convergence(&self, point: &GeoPoint) -> f64 {
+        let dlon = (point.longitude - self.central_meridian() + 180.0).rem_euclid(360.0) - 180.0;
+        (dlon.to_radians().tan() * point.latitude.to_radians().sin())
+            .atan()
+            .to_degrees()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+impl GeoPoint {
+    pub fn new(latitude: f64, longitude: f64, altitude: f64) -> GeoPoint {
+        GeoPoint {
+            latitude,
+            longitude,
+            altitude,
+        }
+    }
+
+    fn to_unit_vector(self) -> Vector3 {
+        let (lat, lon) = (self.latitude.to_radians(), self.longitude.to_radians());
+        Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin())
+    }
+
+    /// East, north and up metres from `origin` on a flat-earth approximation,
+    /// adequate over a few kilometres.
+    pub fn to_local(&self, origin: &GeoPoint) -> Vector3 {
+        let dlon = (self.longitude - origin.longitude + 180.0).rem_euclid(360.0) - 180.0;
+        Vector3::new(
+            dlon.to_radians() * EARTH_RADIUS * origin.latitude.to_radians().cos(),
+            (self.latitude - origin.latitude).to_radians() * EARTH_RADIUS,
+            self.altitude - origin.altitude,
+        )
+    }
+
+    /// Inverse of [`GeoPoint::to_local`].
+    pub fn from_local(origin: &GeoPoint, local: Vector3) -> GeoPoint {
+        let longitude = origin.longitude
+            + (local.x / (EARTH_RADIUS * origin.latitude.to_radians().cos())).to_degrees();
+        GeoPoint::new(
+            origin.latitude + (local.y / EARTH_RADIUS).to_degrees(),
+            (longitude + 180.0).rem_euclid(360.0) - 180.0,
+            origin.altitude + local.z,
+        )
+    }
+
+    /// Earth-centred, earth-fixed coordinates in metres on the WGS84 ellipsoid.
+    pub fn to_ecef(&self) -> Vector3 {
+        let (lat, lon) = (self.latitude.to_radians(), self.longitude.to_radians());
+        let n = EARTH_RADIUS / (1.0 - E2 * lat.sin().powi(2)).sqrt();
+        Vector3::new(
+            (n + self.altitude) * lat.cos() * lon.cos(),
+            (n + self.altitude) * lat.cos() * lon.sin(),
+            (n * (1.0 - E2) + self.altitude) * lat.sin(),
+        )
+    }
+
+    /// Inverse of [`GeoPoint::to_ecef`].
+    pub fn from_ecef(ecef: Vector3) -> GeoPoint {
+        let p = ecef.x.hypot(ecef.y);
+        let mut lat = ecef.z.atan2(p * (1.0 - E2));
+        let normal = |lat: f64| EARTH_RADIUS / (1.0 - E2 * lat.sin().powi(2)).sqrt();
+        let altitude = |lat: f64| {
+            p * lat.cos() + ecef.z * lat.sin() - EARTH_RADIUS * EARTH_RADIUS / normal(lat)
+        };
+        for _ in 0..5 {
+            let n = normal(lat);
+            lat = ecef.z.atan2(p * (1.0 - E2 * n / (n + altitude(lat))));
+        }
+        GeoPoint::new(
+            lat.to_degrees(),
+            ecef.y.atan2(ecef.x).to_degrees(),
+            altitude(lat),
+        )
+    }
+
+    /// East, north and up metres from `origin`, exact on the ellipsoid.
+    pub fn to_enu(&self, origin: &GeoPoint) -> Vector3 {
+        let d = self.to_ecef() - origin.to_ecef();
+        let [east, north, up] = enu_axes(origin);
+        Vector3::new(d.dot(east), d.dot(north), d.dot(up))
+    }
+
+    /// Inverse of [`GeoPoint::to_enu`].
+    pub fn from_enu(origin: &GeoPoint, enu: Vector3) -> GeoPoint {
+        let [east, north, up] = enu_axes(origin);
+        GeoPoint::from_ecef(origin.to_ecef() + east * enu.x + north * enu.y + up * enu.z)
+    }
+
+    /// Easting, northing and altitude in metres in `zone`, by the Krüger series;
+    /// `None` outside 80°S to 84°N.
+    pub fn to_utm(&self, zone: UtmZone) -> Option<Vector3> {
+        if !UTM_LATITUDES.contains(&self.latitude) {
+            return None;
+        }
+        let n = FLATTENING / (2.0 - FLATTENING);
+        let a = EARTH_RADIUS / (1.0 + n) * (1.0 + n * n / 4.0 + n.powi(4) / 64.0);
+        let alpha = [
+            n / 2.0 - 2.0 * n * n / 3.0 + 5.0 * n.powi(3) / 16.0,
+            13.0 * n * n / 48.0 - 3.0 * n.powi(3) / 5.0,
+            61.0 * n.powi(3) / 240.0,
+        ];
+        let lat = self.latitude.to_radians();
+        let dlon = ((self.longitude - zone.central_meridian() + 180.0).rem_euclid(360.0) - 180.0)
+            .to_radians();
+        let e = 2.0 * n.sqrt() / (1.0 + n);
+        let t = (lat.sin().atanh() - e * (e * lat.sin()).atanh()).sinh();
+        let xi = t.atan2(dlon.cos());
+        let eta = (dlon.sin() / (1.0 + t * t).sqrt()).atanh();
+        let (mut x, mut y) = (eta, xi);
+        for (j, alpha) in alpha.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            x += alpha * (k * xi).cos() * (k * eta).sinh();
+            y += alpha * (k * xi).sin() * (k * eta).cosh();
+        }
+        let false_northing = if zone.north { 0.0 } else { 10_000_000.0 };
+        Some(Vector3::new(
+            500_000.0 + UTM_SCALE * a * x,
+            false_northing + UTM_SCALE * a * y,
+            self.altitude,
+        ))
+    }
+
+    /// Haversine distance in metres, ignoring altitude.
+    pub fn distance_to(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * MEAN_RADIUS * h.sqrt().min(1.0).asin()
+    }
+
+    /// Vincenty distance on the WGS84 ellipsoid in metres, ignoring altitude, or
+    /// `None` where the iteration does not converge, for nearly antipodal points.
+    pub fn vincenty_distance_to(&self, other: &GeoPoint) -> Option<f64> {
+        let f = FLATTENING;
+        let b = EARTH_RADIUS * (1.0 - f);
+        // Reduced latitudes.
+        let (sin_u1, cos_u1) = ((1.0 - f) * self.latitude.to_radians().tan())
+            .atan()
+            .sin_cos();
+        let (sin_u2, cos_u2) = ((1.0 - f) * other.latitude.to_radians().tan())
+            .atan()
+            .sin_cos();
+        let l = (other.longitude - self.longitude).to_radians();
+        let mut lambda = l;
+        for _ in 0..200 {
+            let (sin_lambda, cos_lambda) = lambda.sin_cos();
+            let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+                + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+            .sqrt();
+            if sin_sigma == 0.0 {
+                return Some(0.0);
+            }
+            let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+            let sigma = sin_sigma.atan2(cos_sigma);
+            let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+            let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+            // Both points on the equator.
+            let cos_2sigma_m = if cos2_alpha == 0.0 {
+                0.0
+            } else {
+                cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha
+            };
+            let c = f / 16.0 * cos2_alpha * (4.0 + f * (4.0 - 3.0 * cos2_alpha));
+            let previous = lambda;
+            lambda = l
+                + (1.0 - c)
+                    * f
+                    * sin_alpha
+                    * (sigma
+                        + c * sin_sigma
+                            * (cos_2sigma_m + c * cos_sigma * (2.0 * cos_2sigma_m.powi(2) - 1.0)));
+            if (lambda - previous).abs() < 1e-12 {
+                let u2 = cos2_alpha * (EARTH_RADIUS * EARTH_RADIUS - b * b) / (b * b);
+                let series_a =
+                    1.0 + u2 / 16384.0 * (4096.0 + u2 * (-768.0 + u2 * (320.0 - 175.0 * u2)));
+                let series_b = u2 / 1024.0 * (256.0 + u2 * (-128.0 + u2 * (74.0 - 47.0 * u2)));
+                let delta_sigma = series_b
+                    * sin_sigma
+                    * (cos_2sigma_m
+                        + series_b / 4.0
+                            * (cos_sigma * (2.0 * cos_2sigma_m.powi(2) - 1.0)
+                                - series_b / 6.0
+                                    * cos_2sigma_m
+                                    * (4.0 * sin_sigma.powi(2) - 3.0)
+                                    * (4.0 * cos_2sigma_m.powi(2) - 3.0)));
+                return Some(b * series_a * (sigma - delta_sigma));
+            }
+        }
+        None
+    }
+
+    /// Initial great-circle bearing towards `other`, in degrees clockwise from north.
+    pub fn bearing_to(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let y = dlon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Great-circle interpolation of the position, linear in altitude.
+    pub fn interpolate(self, other: GeoPoint, t: f64) -> GeoPoint {
+        let (a, b) = (self.to_unit_vector(), other.to_unit_vector());
+        let omega = a.dot(b).clamp(-1.0, 1.0).acos();
+        let p = if omega < 1e-12 {
+            a.lerp(b, t)
+        } else {
+            let sin = omega.sin();
+            a * (((1.0 - t) * omega).sin() / sin) + b * ((t * omega).sin() / sin)
+        };
+        GeoPoint {
+            latitude: p.z.atan2((p.x * p.x + p.y * p.y).sqrt()).to_degrees(),
+            longitude: p.y.atan2(p.x).to_degrees(),
+            altitude: self.altitude + (other.altitude - self.altitude) * t,
+        }
+    }
+}
+
+/// A position of the recorded GPS track.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TrackFix {
+    /// UTC seconds, as in RDL2/RDTL.
+    pub timestamp: f64,
+    pub position: GeoPoint,
+    /// Reported horizontal speed in m/s; RDTL has none.
+    pub speed: Option<f64>,
+}
+
+/// RDL2 entries with at least a 2D fix, or every RDTL entry when RDL2 is absent.
+pub fn track(meta: &ThetaMeta) -> Vec<TrackFix> {
+    match (&meta.rdl2, &meta.rdtl) {
+        (Some(rdl2), _) => rdl2
+            .fixes()
+            .map(|e| TrackFix {
+                timestamp: e.timestamp,
+                position: GeoPoint::new(e.latitude, e.longitude, e.altitude as f64),
+                speed: Some((e.velocity_east as f64).hypot(e.velocity_north as f64)),
+            })
+            .collect(),
+        (None, Some(rdtl)) => rdtl
+            .entries()
+            .iter()
+            .map(|e| TrackFix {
+                timestamp: e.timestamp,
+                position: GeoPoint::new(e.latitude, e.longitude, e.altitude),
+                speed: None,
+            })
+            .collect(),
+        (None, None) => Vec::new(),
+    }
+}
+
+/// Unit east, north and up vectors at `origin`, in ECEF.
+fn enu_axes(origin: &GeoPoint) -> [Vector3; 3] {
+    let (lat, lon) = (origin.latitude.to_radians(), origin.longitude.to_radians());
+    [
+        Vector3::new(-lon.sin(), lon.cos(), 0.0),
+        Vector3::new(-lat.sin() * lon.cos(), -lat.sin() * lon.sin(), lat.cos()),
+        Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let a = GeoPoint::new(0.0, 0.0, 100.0);
+        let b = GeoPoint::new(0.0, 90.0, 200.0);
+        let p = a.interpolate(b, 0.5);
+        assert!(p.latitude.abs() < 1e-9);
+        assert!((p.longitude - 45.0).abs() < 1e-9);
+        assert_eq!(p.altitude, 150.0);
+
+        // Crossing the antimeridian takes the short way round.
+        let a = GeoPoint::new(10.0, 179.0, 0.0);
+        let b = GeoPoint::new(10.0, -179.0, 0.0);
+        let p = a.interpolate(b, 0.5);
+        assert!((p.longitude.abs() - 180.0).abs() < 1e-6);
+        assert!(p.latitude > 10.0);
+    }
+
+    #[test]
+    fn test_local() {
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        let p = GeoPoint::new(35.001, 139.001, 15.0);
+        let local = p.to_local(&origin);
+        assert!((local.y - 111.32).abs() < 0.01);
+        assert!((local.x - 91.19).abs() < 0.01);
+        let back = GeoPoint::from_local(&origin, local);
+        assert!((back.latitude - p.latitude).abs() < 1e-12);
+        assert!((back.longitude - p.longitude).abs() < 1e-12);
+        assert_eq!(back.altitude, 15.0);
+    }
+
+    #[test]
+    fn test_ecef() {
+        let p = GeoPoint::new(35.0, 139.0, 10.0);
+        let ecef = p.to_ecef();
+        assert!((ecef.x + 3947459.4272).abs() < 1e-3);
+        assert!((ecef.y - 3431474.1281).abs() < 1e-3);
+        assert!((ecef.z - 3637872.6451).abs() < 1e-3);
+        let back = GeoPoint::from_ecef(ecef);
+        assert!((back.latitude - 35.0).abs() < 1e-10);
+        assert!((back.longitude - 139.0).abs() < 1e-10);
+        assert!((back.altitude - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_enu() {
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        let p = GeoPoint::new(35.001, 139.001, 15.0);
+        let enu = p.to_enu(&origin);
+        // Close to the flat-earth approximation over 150 m.
+        assert!((enu - p.to_local(&origin)).norm() < 0.5);
+        let back = GeoPoint::from_enu(&origin, enu);
+        assert!((back.latitude - p.latitude).abs() < 1e-10);
+        assert!((back.longitude - p.longitude).abs() < 1e-10);
+        assert!((back.altitude - 15.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_utm() {
+        let cases = [
+            (0.0, 0.0, 31, 166021.4432, 0.0),
+            (35.658581, 139.745433, 54, 386440.5631, 3946806.0097),
+            (-33.8688, 151.2093, 56, 334368.6336, 6250948.3453),
+            (48.8583701, 2.2944813, 31, 448250.5989, 5411951.5993),
+        ];
+        for (lat, lon, number, easting, northing) in cases {
+            let p = GeoPoint::new(lat, lon, 0.0);
+            let zone = UtmZone::of(&p).unwrap();
+            assert_eq!(zone.number, number);
+            assert_eq!(zone.north, lat >= 0.0);
+            let utm = p.to_utm(zone).unwrap();
+            assert!((utm.x - easting).abs() < 0.01, "{:?}", utm);
+            assert!((utm.y - northing).abs() < 0.01, "{:?}", utm);
+        }
+        let zone = |lat, lon| UtmZone::of(&GeoPoint::new(lat, lon, 0.0)).map(|z| z.number);
+        assert_eq!(zone(60.0, 5.0), Some(32));
+        assert_eq!(zone(78.0, 15.0), Some(33));
+        assert_eq!(zone(0.0, 180.0), Some(1));
+        assert_eq!(zone(84.5, 15.0), None);
+        assert_eq!(zone(-80.5, 15.0), None);
+        let zone = UtmZone::of(&GeoPoint::new(35.0, 139.0, 0.0)).unwrap();
+        assert_eq!(zone.convergence(&GeoPoint::new(35.0, 141.0, 0.0)), 0.0);
+        assert!(zone.convergence(&GeoPoint::new(35.0, 143.0, 0.0)) > 1.1);
+        let zone = UtmZone::of(&GeoPoint::new(83.0, 15.0, 0.0)).unwrap();
+        assert_eq!(GeoPoint::new(85.0, 15.0, 0.0).to_utm(zone), None);
+    }
+
+    #[test]
+    fn test_distance_to() {
+        let a = GeoPoint::new(0.0, 0.0, 0.0);
+        assert!((a.distance_to(&GeoPoint::new(0.0, 1.0, 0.0)) - 111_195.08).abs() < 0.01);
+        let paris = GeoPoint::new(48.8566, 2.3522, 0.0);
+        let london = GeoPoint::new(51.5074, -0.1278, 0.0);
+        assert!((paris.distance_to(&london) - 343_556.0).abs() < 100.0);
+        assert_eq!(paris.distance_to(&paris), 0.0);
+    }
+
+    #[test]
+    fn test_vincenty_distance_to() {
+        // Flinders Peak to Buninyong, from Vincenty's paper.
+        let flinders = GeoPoint::new(-37.951_033_416_7, 144.424_867_888_9, 0.0);
+        let buninyong = GeoPoint::new(-37.652_821_138_9, 143.926_495_527_8, 0.0);
+        let d = flinders.vincenty_distance_to(&buninyong).unwrap();
+        assert!((d - 54_972.271).abs() < 0.01, "{}", d);
+        // One degree along the equator is an arc of the equatorial radius.
+        let a = GeoPoint::new(0.0, 0.0, 0.0);
+        let d = a
+            .vincenty_distance_to(&GeoPoint::new(0.0, 1.0, 0.0))
+            .unwrap();
+        assert!((d - EARTH_RADIUS * 1f64.to_radians()).abs() < 1e-6);
+        assert_eq!(a.vincenty_distance_to(&a), Some(0.0));
+        // Nearly antipodal points do not converge.
+        assert_eq!(
+            a.vincenty_distance_to(&GeoPoint::new(0.5, 179.7, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_bearing_to() {
+        let origin = GeoPoint::new(0.0, 0.0, 0.0);
+        assert!((origin.bearing_to(&GeoPoint::new(1.0, 0.0, 0.0))).abs() < 1e-9);
+        assert!((origin.bearing_to(&GeoPoint::new(0.0, 1.0, 0.0)) - 90.0).abs() < 1e-9);
+        assert!((origin.bearing_to(&GeoPoint::new(0.0, -1.0, 0.0)) - 270.0).abs() < 1e-9);
+    }
+}
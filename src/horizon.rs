@@ -0,0 +1,148 @@
+//! Per-frame roll and pitch for leveling the equirectangular horizon.
+//!
+//! The camera tilt comes from [`estimate_orientation`] when the gyroscope is
+//! recorded, so that accelerations while moving do not tip the horizon, and from
+//! the accelerometer alone otherwise. Angles are about the sensor axes.
+
+use serde::Serialize;
+
+use crate::{
+    fusion::{estimate_orientation, FusionConfig},
+    math::{interpolate_clamped, Quaternion, Vector3},
+    theta::ThetaMeta,
+    timeline::Timeline,
+};
+
+/// Camera tilt at one video frame, in degrees.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct HorizonCorrection {
+    pub frame: usize,
+    /// Seconds from video start.
+    pub time: f64,
+    /// Rotation about x.
+    pub roll: f64,
+    /// Rotation about y.
+    pub pitch: f64,
+    /// Rotation about z relative to the first gyroscope sample; zero without RDTB.
+    pub yaw: f64,
+}
+
+impl HorizonCorrection {
+    fn new(frame: usize, time: f64, (roll, pitch, yaw): (f64, f64, f64)) -> HorizonCorrection {
+        HorizonCorrection {
+            frame,
+            time,
+            roll: roll.to_degrees(),
+            pitch: pitch.to_degrees(),
+            yaw: yaw.to_degrees(),
+        }
+    }
+
+    /// Rotation taking sensor-frame directions to the leveled frame.
+    pub fn leveling(&self) -> Quaternion {
+        Quaternion::from_euler(self.roll.to_radians(), self.pitch.to_radians(), 0.0)
+    }
+}
+
+/// Tilt at every frame of `timeline`, or an empty series without RDTA.
+///
+/// The magnetometer is never used, since heading does not affect the horizon.
+pub fn horizon_corrections(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: FusionConfig,
+) -> Vec<HorizonCorrection> {
+    let Some(rdta) = &meta.rdta else {
+        return Vec::new();
+    };
+    let frames = timeline.frame_times().iter().copied().enumerate();
+
+    if let Some(rdtb) = &meta.rdtb {
+        let config = FusionConfig {
+            use_magnetometer: false,
+            ..config
+        };
+        let (times, orientations): (Vec<_>, Vec<_>) =
+            estimate_orientation(rdta, rdtb, None, timeline, config)
+                .into_iter()
+                .map(|s| (s.time, s.orientation))
+                .unzip();
+        return frames
+            .filter_map(|(frame, time)| {
+                let q = interpolate_clamped(&times, &orientations, time, Quaternion::slerp)?;
+                Some(HorizonCorrection::new(frame, time, q.to_euler()))
+            })
+            .collect();
+    }
+
+    let (times, gravity): (Vec<_>, Vec<_>) = rdta
+        .entries()
+        .iter()
+        .map(|e| {
+            let a = Vector3::from_f32(e.x, e.y, e.z);
+            (timeline.tick_to_seconds(e.timestamp), a)
+        })
+        .unzip();
+    frames
+        .filter_map(|(frame, time)| {
+            let a = interpolate_clamped(&times, &gravity, time, Vector3::lerp)?;
+            let roll = a.y.atan2(a.z);
+            let pitch = (-a.x).atan2((a.y * a.y + a.z * a.z).sqrt());
+            Some(HorizonCorrection::new(frame, time, (roll, pitch, 0.0)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn setup() -> ThetaMeta {
+        let ticks: Vec<u64> = (0..=100).map(|i| i * 10_000).collect();
+        // Rolled by 30 degrees.
+        let (sin, cos) = 30f32.to_radians().sin_cos();
+        let accel: Vec<_> = ticks.iter().map(|&t| (0.0, sin, cos, t)).collect();
+        ThetaMeta {
+            rdtg: Some(testing::rdtg(&[0, 500_000, 1_000_000])),
+            rdta: Some(testing::rdta(&accel)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 2.0, 3)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_horizon_from_accelerometer() {
+        let meta = setup();
+        let corrections = horizon_corrections(&meta, &Timeline::new(&meta), Default::default());
+        assert_eq!(corrections.len(), 3);
+        assert_eq!(corrections[1].frame, 1);
+        assert_eq!(corrections[1].time, 0.5);
+        assert!((corrections[1].roll - 30.0).abs() < 1e-4);
+        assert!(corrections[1].pitch.abs() < 1e-4);
+
+        let level = corrections[1].leveling();
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        let up = level.rotate(Vector3::new(0.0, sin, cos));
+        assert!((up - Vector3::new(0.0, 0.0, 1.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_horizon_with_gyroscope() {
+        let mut meta = setup();
+        let ticks: Vec<u64> = (0..=100).map(|i| i * 10_000).collect();
+        // Panning about the vertical, which is tilted in the sensor frame.
+        let (sin, cos) = 30f32.to_radians().sin_cos();
+        let gyro: Vec<_> = ticks.iter().map(|&t| (0.0, sin, cos, t)).collect();
+        meta.rdtb = Some(testing::rdtb(&gyro));
+        let corrections = horizon_corrections(&meta, &Timeline::new(&meta), Default::default());
+        assert_eq!(corrections.len(), 3);
+        for c in &corrections {
+            assert!((c.roll - 30.0).abs() < 0.5, "{:?}", c);
+            assert!(c.pitch.abs() < 0.5, "{:?}", c);
+        }
+        assert!(corrections[0].yaw.abs() < 1e-9);
+        assert!(corrections[2].yaw > 45.0, "{:?}", corrections[2]);
+    }
+}
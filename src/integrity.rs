@@ -0,0 +1,372 @@
+//! Integrity checks of the sensor streams.
+//!
+//! Each table's timestamps are compared with its nominal `sampling_rate` to find
+//! gaps, duplicates, backwards steps and jitter, and a line fitted through the
+//! timestamps gives the actual rate. Comparing those rates shows drift between
+//! the IMU and frame (RDTG) clocks.
+//!
+//! Drift between the camera and GPS clocks compares how long the recording took on
+//! each: the tick span of RDTG (or of the IMU tables, or else the `mvhd` duration)
+//! against the span of the GPS UTC timestamps, assuming the GPS records cover the
+//! recording. Either end is only known to one GPS interval, so short recordings
+//! give a coarse estimate.
+
+use serde::Serialize;
+
+use crate::{math::least_squares, theta::ThetaMeta, timeline::Timeline};
+
+/// Seconds from video start.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Gap {
+    pub start: f64,
+    pub end: f64,
+    /// Samples the nominal rate expects in between.
+    pub missing: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct StreamReport {
+    pub name: String,
+    pub entries: usize,
+    /// From the table header (the video frame rate for RDTG).
+    pub nominal_rate: Option<f64>,
+    pub measured_rate: Option<f64>,
+    /// `(measured / nominal - 1) * 1e6`.
+    pub rate_error_ppm: Option<f64>,
+    /// First and last sample, in seconds from video start.
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// Standard deviation of the sample interval outside gaps, in seconds.
+    pub jitter: Option<f64>,
+    pub gaps: Vec<Gap>,
+    pub duplicates: usize,
+    pub non_monotonic: usize,
+    /// Seconds of video after the last sample.
+    pub tail: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize)]
+pub struct ClockReport {
+    /// IMU rate error minus RDTG rate error.
+    pub imu_vs_frames_ppm: Option<f64>,
+    /// `(camera elapsed / GPS elapsed - 1) * 1e6` over the recording.
+    pub camera_vs_gps_ppm: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct IntegrityReport {
+    pub streams: Vec<StreamReport>,
+    pub clocks: ClockReport,
+    /// Human-readable findings; empty for a clean recording.
+    pub issues: Vec<String>,
+}
+
+/// `times` are seconds on the stream's own clock; adding `offset` gives video time.
+fn analyze(
+    name: &str,
+    times: &[f64],
+    offset: Option<f64>,
+    nominal_rate: Option<f64>,
+    video_duration: Option<f64>,
+) -> StreamReport {
+    let deltas: Vec<f64> = times.windows(2).map(|w| w[1] - w[0]).collect();
+    let mut positive: Vec<f64> = deltas.iter().copied().filter(|&d| d > 0.0).collect();
+    positive.sort_by(f64::total_cmp);
+    let interval = nominal_rate
+        .map(|rate| 1.0 / rate)
+        .or_else(|| positive.get(positive.len() / 2).copied());
+
+    let mut gaps = Vec::new();
+    let mut regular = Vec::new();
+    if let Some(interval) = interval {
+        for (i, &d) in deltas.iter().enumerate() {
+            if d > 1.5 * interval {
+                gaps.push(Gap {
+                    start: times[i] + offset.unwrap_or(0.0),
+                    end: times[i + 1] + offset.unwrap_or(0.0),
+                    missing: ((d / interval).round() as usize).saturating_sub(1),
+                });
+            } else if d > 0.0 {
+                regular.push(d);
+            }
+        }
+    }
+    let jitter = (regular.len() > 1).then(|| {
+        let mean = regular.iter().sum::<f64>() / regular.len() as f64;
+        let var = regular.iter().map(|d| (d - mean) * (d - mean)).sum::<f64>();
+        (var / regular.len() as f64).sqrt()
+    });
+
+    // Fit time against the sample's slot on the nominal grid, so gaps do not bias it.
+    let measured_rate = interval.and_then(|interval| {
+        let first = *times.first()?;
+        let mut last = f64::NEG_INFINITY;
+        let rows: Vec<_> = times
+            .iter()
+            .filter(|&&t| {
+                let increasing = t > last;
+                last = last.max(t);
+                increasing
+            })
+            .map(|&t| ([((t - first) / interval).round(), 1.0], t))
+            .collect();
+        if rows.len() < 3 {
+            return None;
+        }
+        let [slope, _] = least_squares(rows)?;
+        (slope > 0.0).then(|| 1.0 / slope)
+    });
+    let start = offset.and_then(|o| Some(times.first()? + o));
+    let end = offset.and_then(|o| Some(times.last()? + o));
+    StreamReport {
+        name: name.to_string(),
+        entries: times.len(),
+        nominal_rate,
+        measured_rate,
+        rate_error_ppm: nominal_rate
+            .zip(measured_rate)
+            .map(|(nominal, measured)| (measured / nominal - 1.0) * 1e6),
+        start,
+        end,
+        jitter,
+        gaps,
+        duplicates: deltas.iter().filter(|&&d| d == 0.0).count(),
+        non_monotonic: deltas.iter().filter(|&&d| d < 0.0).count(),
+        tail: video_duration
+            .zip(end)
+            .map(|(duration, end)| duration - end),
+    }
+}
+
+fn nominal(rate: u16) -> Option<f64> {
+    (rate > 0).then_some(rate as f64)
+}
+
+/// Seconds from the first sample to the end of the last, whose length is the
+/// stream's sample interval.
+fn elapsed(times: &[f64], report: &StreamReport) -> Option<f64> {
+    let first = times.iter().copied().reduce(f64::min)?;
+    let last = times.iter().copied().reduce(f64::max)?;
+    let rate = report.measured_rate.or(report.nominal_rate)?;
+    (times.len() > 1).then(|| last - first + 1.0 / rate)
+}
+
+impl IntegrityReport {
+    pub fn new(meta: &ThetaMeta, timeline: &Timeline) -> IntegrityReport {
+        let video_duration = meta
+            .video
+            .as_ref()
+            .filter(|v| v.timescale > 0)
+            .map(|v| v.duration as f64 / v.timescale as f64);
+        // Elapsed time of each stream on its own clock.
+        let mut spans = Vec::new();
+        let mut tick_stream = |name: &str, ticks: &mut dyn Iterator<Item = u64>, rate| {
+            let times: Vec<_> = ticks.map(|t| timeline.tick_to_seconds(t)).collect();
+            let report = analyze(name, &times, Some(0.0), rate, video_duration);
+            spans.extend(elapsed(&times, &report).map(|e| (report.name.clone(), e)));
+            report
+        };
+        let gps_offset = timeline.utc_to_seconds(0.0);
+
+        let mut streams = Vec::new();
+        if let Some(rdta) = &meta.rdta {
+            let ticks = &mut rdta.entries().iter().map(|e| e.timestamp);
+            streams.push(tick_stream(
+                "RDTA",
+                ticks,
+                nominal(rdta.header().sampling_rate),
+            ));
+        }
+        if let Some(rdtb) = &meta.rdtb {
+            let ticks = &mut rdtb.entries().iter().map(|e| e.timestamp);
+            streams.push(tick_stream(
+                "RDTB",
+                ticks,
+                nominal(rdtb.header().sampling_rate),
+            ));
+        }
+        if let Some(rdtc) = &meta.rdtc {
+            let ticks = &mut rdtc.entries().iter().map(|e| e.timestamp);
+            streams.push(tick_stream(
+                "RDTC",
+                ticks,
+                nominal(rdtc.header().sampling_rate),
+            ));
+        }
+        if let Some(rdtg) = &meta.rdtg {
+            let rate = match &meta.video {
+                Some(video) if video.frame_rate > 0.0 => Some(video.frame_rate),
+                _ => nominal(rdtg.header().sampling_rate),
+            };
+            let ticks = &mut rdtg.entries().iter().map(|e| e.timestamp);
+            streams.push(tick_stream("RDTG", ticks, rate));
+        }
+        if let Some(rdtl) = &meta.rdtl {
+            let times: Vec<_> = rdtl.entries().iter().map(|e| e.timestamp).collect();
+            let rate = nominal(rdtl.header().sampling_rate);
+            let report = analyze("RDTL", &times, gps_offset, rate, video_duration);
+            spans.extend(elapsed(&times, &report).map(|e| (report.name.clone(), e)));
+            streams.push(report);
+        }
+        if let Some(rdl2) = &meta.rdl2 {
+            let times: Vec<_> = rdl2.entries().iter().map(|e| e.timestamp).collect();
+            let rate = nominal(rdl2.header().sampling_rate);
+            let report = analyze("RDL2", &times, gps_offset, rate, video_duration);
+            spans.extend(elapsed(&times, &report).map(|e| (report.name.clone(), e)));
+            streams.push(report);
+        }
+
+        let ppm = |name: &str| {
+            streams
+                .iter()
+                .find(|s| s.name == name)
+                .and_then(|s| s.rate_error_ppm)
+        };
+        let span = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| spans.iter().find(|(s, _)| s == name))
+                .map(|&(_, elapsed)| elapsed)
+        };
+        // Frames span the whole recording; the IMU tables may stop early.
+        let camera_elapsed = span(&["RDTG", "RDTB", "RDTA", "RDTC"]).or(video_duration);
+        let gps_elapsed = span(&["RDL2", "RDTL"]);
+        let clocks = ClockReport {
+            imu_vs_frames_ppm: ppm("RDTB")
+                .or_else(|| ppm("RDTA"))
+                .zip(ppm("RDTG"))
+                .map(|(imu, frames)| imu - frames),
+            camera_vs_gps_ppm: camera_elapsed
+                .zip(gps_elapsed)
+                .map(|(camera, gps)| (camera / gps - 1.0) * 1e6),
+        };
+        let issues = streams.iter().flat_map(issues).collect();
+        IntegrityReport {
+            streams,
+            clocks,
+            issues,
+        }
+    }
+}
+
+fn issues(stream: &StreamReport) -> Vec<String> {
+    let mut issues = Vec::new();
+    if !stream.gaps.is_empty() {
+        let missing: usize = stream.gaps.iter().map(|g| g.missing).sum();
+        issues.push(format!(
+            "{}: {} gap(s), about {} sample(s) missing",
+            stream.name,
+            stream.gaps.len(),
+            missing
+        ));
+    }
+    if stream.duplicates > 0 {
+        issues.push(format!(
+            "{}: {} duplicate timestamp(s)",
+            stream.name, stream.duplicates
+        ));
+    }
+    if stream.non_monotonic > 0 {
+        issues.push(format!(
+            "{}: {} timestamp(s) going backwards",
+            stream.name, stream.non_monotonic
+        ));
+    }
+    let interval = stream.nominal_rate.map_or(0.0, |rate| 1.0 / rate);
+    if let Some(tail) = stream.tail.filter(|&tail| tail > 1.0 + 2.0 * interval) {
+        issues.push(format!(
+            "{}: stops {:.1} s before the end of the video",
+            stream.name, tail
+        ));
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    fn setup() -> ThetaMeta {
+        // 100 Hz for 2 s, with a duplicate and a step back.
+        let mut ticks: Vec<u64> = (0..200).map(|i| i * 10_000).collect();
+        ticks.insert(50, ticks[50]);
+        ticks.swap(120, 121);
+        let accel: Vec<_> = ticks.iter().map(|&t| (0.0, 0.0, 1.0, t)).collect();
+        // Frames 20 ppm slower than 30 fps.
+        let frames: Vec<u64> = (0..60).map(|i| i * 33_334).collect();
+        ThetaMeta {
+            rdtg: Some(testing::rdtg(&frames)),
+            rdta: Some(testing::rdta(&accel)),
+            rdl2: Some(testing::rdl2(&[
+                Fix::new(100.0, 35.0, 139.0),
+                Fix::new(101.0, 35.0, 139.0),
+                Fix::new(102.0, 35.0, 139.0),
+            ])),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 30.0, 60)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_integrity_report() {
+        let meta = setup();
+        let report = IntegrityReport::new(&meta, &Timeline::new(&meta));
+        assert_eq!(report.streams.len(), 3);
+
+        let rdta = &report.streams[0];
+        assert_eq!(rdta.name, "RDTA");
+        assert_eq!(rdta.duplicates, 1);
+        assert_eq!(rdta.non_monotonic, 1);
+
+        let rdtg = &report.streams[1];
+        assert_eq!(rdtg.nominal_rate, Some(30.0));
+        assert!(rdtg.gaps.is_empty());
+        assert!((rdtg.rate_error_ppm.unwrap() + 20.0).abs() < 1.0);
+        assert!(rdtg.jitter.unwrap() < 1e-6);
+
+        let rdl2 = &report.streams[2];
+        assert_eq!(rdl2.start, Some(0.0));
+        assert_eq!(rdl2.end, Some(2.0));
+        assert!(rdl2.rate_error_ppm.unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_camera_vs_gps_drift() {
+        // 100 s of GPS time at 1 Hz, while the frame ticks span 100.011 s: the
+        // camera clock runs 110 ppm fast.
+        let frames: Vec<u64> = (0..3000).map(|i| i * 33_337).collect();
+        let fixes: Vec<_> = (0..100)
+            .map(|i| Fix::new(1000.0 + i as f64, 35.0, 139.0))
+            .collect();
+        let meta = ThetaMeta {
+            rdtg: Some(testing::rdtg(&frames)),
+            rdl2: Some(testing::rdl2(&fixes)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(1000, 30.0, 3000)),
+            ..Default::default()
+        };
+        let report = IntegrityReport::new(&meta, &Timeline::new(&meta));
+        let ppm = report.clocks.camera_vs_gps_ppm.unwrap();
+        assert!((ppm - 110.0).abs() < 1e-3);
+        // The GPS stream alone keeps its nominal rate.
+        assert!(report.streams[1].rate_error_ppm.unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_analyze_gaps() {
+        let mut times: Vec<f64> = (0..100).map(|i| i as f64 * 0.01).collect();
+        times.retain(|&t| !(0.5..0.6).contains(&t));
+        let report = analyze("RDTB", &times, Some(1.0), Some(100.0), Some(5.0));
+        assert_eq!(report.gaps.len(), 1);
+        assert!((report.gaps[0].start - 1.49).abs() < 1e-9);
+        assert_eq!(report.gaps[0].missing, 10);
+        assert!((report.measured_rate.unwrap() - 100.0).abs() < 1e-6);
+        assert!((report.tail.unwrap() - 3.01).abs() < 1e-9);
+
+        let issues = issues(&report);
+        assert_eq!(issues.len(), 2);
+        assert!(issues[1].contains("stops 3.0 s before the end"));
+    }
+}
@@ -1,7 +1,47 @@
-use std::{fs::File, io::BufReader, path::Path};
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
 
+#[cfg(feature = "async")]
+mod async_reader;
+pub mod calibration;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod compass;
+pub mod coordinates;
+pub mod dead_reckoning;
+pub mod events;
+pub mod ffmpeg;
+pub mod frame;
+pub mod fusion;
+pub mod geo;
+pub mod horizon;
+pub mod integrity;
+pub mod mapillary;
+pub mod math;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "python")]
+mod python;
+pub mod quality;
+pub mod resample;
+pub mod segmentation;
+pub mod selection;
+pub mod sfm;
+pub mod smoothing;
+pub mod stats;
+#[cfg(test)]
+mod testing;
 pub mod theta;
-use theta::{rdl2, rdta, rdtb, rdtc, rdtg, rdtl, rthu, RawBox, ThetaMeta};
+pub mod timeline;
+pub mod wmm;
+#[cfg(feature = "async")]
+pub use async_reader::parse_async;
+#[cfg(feature = "mmap")]
+pub use mmap::parse_mmap;
+use theta::{rdl2, rdta, rdtb, rdtc, rdtg, rdtl, rthu, RawBox, ThetaMeta, VideoInfo};
 
 static ALWAYS_INCLUDED_BOXES: &[&str] = &["@mod", "@swr", "@day", "@xyz", "@mak", "manu", "modl"];
 
@@ -13,72 +53,250 @@ pub fn parse<P: AsRef<Path>>(
     let size = f.metadata().ok()?.len();
     let reader = BufReader::new(f);
     let mp4 = mp4::Mp4Reader::read_header(reader, size).ok()?;
-    let moov = &mp4.moov;
-    let udta = &moov.udta;
-
-    let mut theta_meta = ThetaMeta {
-        rthu: None,
-        rmkn: None,
-        rdt1_8: None,
-        rdt9: None,
-        rdta: None,
-        rdtb: None,
-        rdtc: None,
-        rdtd: None,
-        rdtg: None,
-        rdth: None,
-        rdti: None,
-        rdtl: None,
-        rdl2: None,
-        _mod: String::new(),
-        _swr: String::new(),
-        _day: String::new(),
-        _xyz: String::new(),
-        _mak: String::new(),
-        manu: String::new(),
-        modl: String::new(),
-    };
-    if let Some(udta_box) = udta {
-        for child in &udta_box.children {
-            if ALWAYS_INCLUDED_BOXES.contains(&child.name.as_str()) {
-                match_box(&child.name, &child.data, &mut theta_meta);
-            } else if let Some(targets) = target_boxes {
-                if targets.contains(&"all".to_string()) || targets.contains(&child.name) {
-                    match_box(&child.name, &child.data, &mut theta_meta);
-                }
-            }
+    let theta_meta = read_mp4(&mp4, target_boxes);
+    Some((mp4, theta_meta))
+}
+
+/// Borrows the raw payload of a udta child box, e.g. for [`theta::rdta::RdtaBox::view`].
+pub fn udta_payload<'a, R>(mp4: &'a mp4::Mp4Reader<R>, name: &str) -> Option<&'a [u8]> {
+    mp4.moov
+        .udta
+        .as_ref()?
+        .children
+        .iter()
+        .find(|child| child.name == name)
+        .map(|child| child.data.as_slice())
+}
+
+/// Why an MP4 could not be parsed.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the source failed.
+    Io(std::io::Error),
+    /// The source is not a valid MP4.
+    InvalidMp4(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "failed to read the MP4: {}", e),
+            Error::InvalidMp4(message) => write!(f, "invalid MP4: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::InvalidMp4(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+fn mp4_error(e: mp4::Error) -> Error {
+    match e {
+        mp4::Error::IoError(e) => Error::Io(e),
+        e => Error::InvalidMp4(e.to_string()),
+    }
+}
+
+/// Parses THETA metadata from any seekable source, starting at its current position.
+///
+/// Returns `None` for an MP4 without THETA metadata.
+pub fn parse_reader<R: Read + Seek>(
+    mut reader: R,
+    target_boxes: Option<&[String]>,
+) -> Result<Option<ThetaMeta>, Error> {
+    let start = reader.stream_position()?;
+    let size = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(start))?;
+    let mp4 = mp4::Mp4Reader::read_header(reader, size - start).map_err(mp4_error)?;
+    Ok(read_mp4(&mp4, target_boxes))
+}
+
+/// Parses THETA metadata from an in-memory MP4. See [`parse_reader`].
+pub fn parse_bytes(
+    data: &[u8],
+    target_boxes: Option<&[String]>,
+) -> Result<Option<ThetaMeta>, Error> {
+    parse_reader(Cursor::new(data), target_boxes)
+}
+
+fn read_mp4<R: Read + Seek>(
+    mp4: &mp4::Mp4Reader<R>,
+    target_boxes: Option<&[String]>,
+) -> Option<ThetaMeta> {
+    let mut theta_meta = read_udta(&mp4.moov, target_boxes)?;
+    theta_meta.video = mp4
+        .tracks()
+        .values()
+        .find(|track| matches!(track.track_type(), Ok(mp4::TrackType::Video)))
+        .map(|track| VideoInfo {
+            creation_time: mp4.moov.mvhd.creation_time,
+            timescale: mp4.moov.mvhd.timescale,
+            duration: mp4.moov.mvhd.duration,
+            frame_rate: track.frame_rate(),
+            frame_count: track.sample_count(),
+        });
+    Some(theta_meta)
+}
+
+fn read_udta(moov: &mp4::MoovBox, target_boxes: Option<&[String]>) -> Option<ThetaMeta> {
+    let children = moov
+        .udta
+        .iter()
+        .flat_map(|udta_box| udta_box.children.iter())
+        .map(|child| (child.name.as_str(), child.data.as_slice()));
+    read_theta_meta(children, target_boxes)
+}
+
+fn read_theta_meta<'a, I>(children: I, target_boxes: Option<&[String]>) -> Option<ThetaMeta>
+where
+    I: IntoIterator<Item = (&'a str, &'a [u8])>,
+{
+    let mut theta_meta = ThetaMeta::default();
+    for (name, data) in children {
+        if ALWAYS_INCLUDED_BOXES.contains(&name) || is_target(name, target_boxes) {
+            match_box(name, data, &mut theta_meta);
         }
     }
 
     if theta_meta.modl.contains("RICOH THETA") {
-        Some((mp4, Some(theta_meta)))
+        Some(theta_meta)
     } else {
-        Some((mp4, None))
+        None
     }
 }
 
-fn match_box(name: &String, data: &Vec<u8>, theta_meta: &mut ThetaMeta) {
-    match name.as_str() {
-        "RTHU" => theta_meta.rthu = Some(rthu::RthuBox { data: data.clone() }),
-        "RMKN" => theta_meta.rmkn = Some(RawBox { data: data.clone() }),
-        "RDT1-8" => theta_meta.rdt1_8 = Some(RawBox { data: data.clone() }),
-        "RDT9" => theta_meta.rdt9 = Some(RawBox { data: data.clone() }),
-        "RDTA" => theta_meta.rdta = Some(rdta::RdtaBox::read(&data)),
-        "RDTB" => theta_meta.rdtb = Some(rdtb::RdtbBox::read(&data)),
-        "RDTC" => theta_meta.rdtc = Some(rdtc::RdtcBox::read(&data)),
-        "RDTD" => theta_meta.rdtd = Some(RawBox { data: data.clone() }),
-        "RDTG" => theta_meta.rdtg = Some(rdtg::RdtgBox::read(&data)),
-        "RDTH" => theta_meta.rdth = Some(RawBox { data: data.clone() }),
-        "RDTI" => theta_meta.rdti = Some(RawBox { data: data.clone() }),
-        "RDTL" => theta_meta.rdtl = Some(rdtl::RdtlBox::read(&data)),
-        "RDL2" => theta_meta.rdl2 = Some(rdl2::Rdl2Box::read(&data)),
-        "@mod" => theta_meta._mod = String::from_utf8_lossy(&data).to_string(),
-        "@swr" => theta_meta._swr = String::from_utf8_lossy(&data).to_string(),
-        "@day" => theta_meta._day = String::from_utf8_lossy(&data).to_string(),
-        "@xyz" => theta_meta._xyz = String::from_utf8_lossy(&data).to_string(),
-        "@mak" => theta_meta._mak = String::from_utf8_lossy(&data).to_string(),
-        "manu" => theta_meta.manu = String::from_utf8_lossy(&data).to_string(),
-        "modl" => theta_meta.modl = String::from_utf8_lossy(&data).to_string(),
+fn is_target(name: &str, target_boxes: Option<&[String]>) -> bool {
+    target_boxes.is_some_and(|targets| targets.iter().any(|t| t == "all" || t == name))
+}
+
+fn match_box(name: &str, data: &[u8], theta_meta: &mut ThetaMeta) {
+    let raw = || RawBox {
+        data: data.to_vec(),
+    };
+    match name {
+        "RTHU" => {
+            theta_meta.rthu = Some(rthu::RthuBox {
+                data: data.to_vec(),
+            })
+        }
+        "RMKN" => theta_meta.rmkn = Some(raw()),
+        "RDT1-8" => theta_meta.rdt1_8 = Some(raw()),
+        "RDT9" => theta_meta.rdt9 = Some(raw()),
+        "RDTA" => theta_meta.rdta = Some(rdta::RdtaBox::read(data)),
+        "RDTB" => theta_meta.rdtb = Some(rdtb::RdtbBox::read(data)),
+        "RDTC" => theta_meta.rdtc = Some(rdtc::RdtcBox::read(data)),
+        "RDTD" => theta_meta.rdtd = Some(raw()),
+        "RDTG" => theta_meta.rdtg = Some(rdtg::RdtgBox::read(data)),
+        "RDTH" => theta_meta.rdth = Some(raw()),
+        "RDTI" => theta_meta.rdti = Some(raw()),
+        "RDTL" => theta_meta.rdtl = Some(rdtl::RdtlBox::read(data)),
+        "RDL2" => theta_meta.rdl2 = Some(rdl2::Rdl2Box::read(data)),
+        "@mod" => theta_meta._mod = String::from_utf8_lossy(data).to_string(),
+        "@swr" => theta_meta._swr = String::from_utf8_lossy(data).to_string(),
+        "@day" => theta_meta._day = String::from_utf8_lossy(data).to_string(),
+        "@xyz" => theta_meta._xyz = String::from_utf8_lossy(data).to_string(),
+        "@mak" => theta_meta._mak = String::from_utf8_lossy(data).to_string(),
+        "manu" => theta_meta.manu = String::from_utf8_lossy(data).to_string(),
+        "modl" => theta_meta.modl = String::from_utf8_lossy(data).to_string(),
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("modl", b"RICOH THETA X".to_vec()),
+            ("@mak", b"RICOH".to_vec()),
+            ("RTHU", vec![0xff, 0xd8, 0xff, 0xd9]),
+            (
+                "RDTG",
+                vec![
+                    0x01, 0x00, 0x00, 0x00, // number_of_entries
+                    0x01, 0x00, // sampling_rate
+                    0x08, 0x00, // sample_size
+                    0x23, 0x01, // endian (LE: 0x0123)
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // reserve (6 bytes)
+                    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // timestamp (1)
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_read_theta_meta() {
+        let children = setup();
+        let iter = children.iter().map(|(n, d)| (*n, d.as_slice()));
+        let targets = vec!["RDTG".to_string()];
+        let theta_meta = read_theta_meta(iter, Some(&targets)).unwrap();
+        assert_eq!(theta_meta.modl, "RICOH THETA X");
+        assert_eq!(theta_meta._mak, "RICOH");
+        assert!(theta_meta.rthu.is_none());
+        assert_eq!(theta_meta.rdtg.unwrap().get_entry()[0].timestamp, 1);
+
+        let iter = children.iter().map(|(n, d)| (*n, d.as_slice()));
+        let theta_meta = read_theta_meta(iter, None).unwrap();
+        assert!(theta_meta.rdtg.is_none());
+    }
+
+    #[test]
+    fn test_read_theta_meta_not_theta() {
+        let children = [("modl", b"OTHER".as_slice())];
+        assert!(read_theta_meta(children, None).is_none());
+    }
+
+    fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(name);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        // Version 0 mvhd: creation and modification time, timescale, duration.
+        let mut mvhd = vec![0u8; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        let udta = [
+            mp4_box(b"modl", b"RICOH THETA X"),
+            mp4_box(b"RTHU", &[0xff, 0xd8, 0xff, 0xd9]),
+        ]
+        .concat();
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(
+            b"moov",
+            &[mp4_box(b"mvhd", &mvhd), mp4_box(b"udta", &udta)].concat(),
+        ));
+
+        let targets = vec!["RTHU".to_string()];
+        let theta_meta = parse_bytes(&data, Some(&targets)).unwrap().unwrap();
+        assert_eq!(theta_meta.modl, "RICOH THETA X");
+        assert_eq!(theta_meta.rthu.unwrap().data, [0xff, 0xd8, 0xff, 0xd9]);
+
+        // Parsing starts at the current position of the reader.
+        let mut prefixed = b"prefix".to_vec();
+        prefixed.extend_from_slice(&data);
+        let mut reader = Cursor::new(prefixed);
+        reader.set_position(6);
+        let theta_meta = parse_reader(reader, None).unwrap().unwrap();
+        assert_eq!(theta_meta.modl, "RICOH THETA X");
+    }
+
+    #[test]
+    fn test_parse_bytes_invalid() {
+        assert!(parse_bytes(b"not an mp4", None).is_err());
+    }
+}
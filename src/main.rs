@@ -1,10 +1,37 @@
-use clap::Parser;
+// Diagnostics go through `writeln!(io::stderr(), ...)`, the CLI's convention.
+#![allow(clippy::explicit_write)]
+
+use clap::{ArgGroup, Parser};
 use std::{
     env,
-    io::{self, Write},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+use theta_mp4::{
+    calibration::CalibrationReport,
+    compass::{compass_headings, wmm_declination, CompassConfig, Declination},
+    coordinates::{CoordinateFrame, ProjectedTrack},
+    dead_reckoning::bridge_gaps,
+    events::{chapters, detect_events, EventConfig},
+    ffmpeg,
+    frame::FrameLookup,
+    horizon::horizon_corrections,
+    integrity::IntegrityReport,
+    mapillary, parse,
+    quality::GpsFilter,
+    resample::{Interpolation, ResampleConfig, Resampler, TargetRate},
+    segmentation::{segment_motion, trim_range},
+    selection::{frame_poses, select_frames, SelectedFrame, SelectionConfig},
+    sfm,
+    smoothing::{smooth_track, smoothed_rdl2},
+    stats::{DistanceFormula, StatsConfig, TrackStats},
+    theta::{SerializableThetaMeta, ThetaMeta},
+    timeline::Timeline,
 };
 
-use theta_mp4::parse;
+static FRAME_BOXES: &[&str] = &["RDTA", "RDTB", "RDTC", "RDTG", "RDTL", "RDL2"];
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -14,18 +41,188 @@ use theta_mp4::parse;
     about = env!("CARGO_PKG_DESCRIPTION"),
     arg_required_else_help = true,
 )]
+#[command(group(ArgGroup::new("mode").multiple(false)))]
+#[command(group(ArgGroup::new("export").multiple(false)))]
+#[command(group(ArgGroup::new("detect").multiple(false)))]
 struct Cli {
     filename: String,
 
     #[arg(short, long, value_name = "Target Box")]
     target: Option<String>,
+
+    /// Emit one JSON record per video frame (JSON Lines) with interpolated sensors
+    #[arg(long, group = "mode")]
+    frames: bool,
+
+    /// Emit the per-frame roll and pitch that level the horizon (JSON Lines)
+    #[arg(long, group = "mode")]
+    horizon: bool,
+
+    /// Write an ffmpeg sendcmd script leveling the video with v360 and print the command line
+    #[arg(long, value_name = "Script File", group = "mode")]
+    v360: Option<String>,
+
+    /// Also cancel camera yaw in the v360 script, keeping the initial heading
+    #[arg(long, requires = "v360")]
+    heading_lock: bool,
+
+    /// Emit the calibrated, tilt-compensated compass heading per video frame (JSON Lines)
+    #[arg(long, group = "mode")]
+    heading: bool,
+
+    /// Magnetic declination in degrees east, or `auto` for the World Magnetic Model at the first GPS fix, added to the headings of --heading, --select-frames, --opensfm and --mapillary
+    #[arg(long, value_name = "Degrees|auto", allow_negative_numbers = true)]
+    declination: Option<Declination>,
+
+    /// Print RDTA/RDTB/RDTC and GPS resampled to a rate in Hz or to `video` frames (JSON)
+    #[arg(long, value_name = "Rate", group = "mode")]
+    resample: Option<TargetRate>,
+
+    /// Interpolation for --resample: nearest, linear or cubic
+    #[arg(
+        long,
+        value_name = "Mode",
+        requires = "resample",
+        default_value = "linear"
+    )]
+    interpolation: Interpolation,
+
+    /// Longest gap in seconds that --resample interpolates across (default: 3x the median interval)
+    #[arg(long, value_name = "Seconds", requires = "resample", value_parser = positive_seconds)]
+    max_gap: Option<f64>,
+
+    /// Emit frames picked every given metres along the GPS path, skipping stops (JSON Lines)
+    #[arg(long, group = "mode", value_name = "Metres")]
+    select_frames: Option<f64>,
+
+    /// Also pick a frame for --select-frames whenever the yaw turns by this many degrees
+    #[arg(long, requires = "select_frames", value_name = "Degrees")]
+    heading_step: Option<f64>,
+
+    /// Print the GPS track with IMU dead-reckoned points through outages (JSON Lines)
+    #[arg(long, group = "mode")]
+    bridge_gaps: bool,
+
+    /// Print the GPS track in a coordinate frame: geodetic, ecef, enu or utm (JSON)
+    #[arg(long, group = "mode", value_name = "Frame")]
+    coordinates: Option<CoordinateFrame>,
+
+    /// Print --coordinates as CSV instead of JSON
+    #[arg(long, requires = "coordinates")]
+    csv: bool,
+
+    /// Write OpenSfM exif_overrides.json and gps_list.txt into a directory
+    #[arg(long, groups = ["mode", "export"], value_name = "Directory")]
+    opensfm: Option<String>,
+
+    /// Write COLMAP reference poses (NAME X Y Z QW QX QY QZ ACCURACY) to a file
+    #[arg(long, groups = ["mode", "export"], value_name = "File")]
+    colmap: Option<String>,
+
+    /// Write Mapillary image descriptions (JSON) for the exported frames to a file
+    #[arg(long, groups = ["mode", "export"], value_name = "File")]
+    mapillary: Option<String>,
+
+    /// Coordinate frame of --colmap: geodetic, ecef, enu or utm
+    #[arg(long, requires = "colmap", value_name = "Frame", default_value = "enu")]
+    colmap_frame: CoordinateFrame,
+
+    /// File name of exported frames, with %d or %0Nd for the video frame index
+    #[arg(
+        long,
+        requires = "export",
+        value_name = "Pattern",
+        default_value = "frame_%05d.jpg"
+    )]
+    frame_pattern: String,
+
+    /// Export frames every given metres along the GPS path instead of every frame
+    #[arg(long, requires = "export", value_name = "Metres")]
+    spacing: Option<f64>,
+
+    /// Print stationary, walking, driving and handheld segments and the range to keep (JSON)
+    #[arg(long, group = "mode")]
+    segments: bool,
+
+    /// Emit impacts, drops, jumps, free-fall and sharp turns detected from RDTA/RDTB (JSON Lines)
+    #[arg(long, groups = ["mode", "detect"])]
+    events: bool,
+
+    /// Write the detected events as ffmpeg chapters to a file and print the command line
+    #[arg(long, groups = ["mode", "detect"], value_name = "Metadata File")]
+    chapters: Option<String>,
+
+    /// Scale of --events and --chapters detection; above 1 reports weaker events
+    #[arg(
+        long,
+        requires = "detect",
+        value_name = "Factor",
+        default_value_t = 1.0
+    )]
+    sensitivity: f64,
+
+    /// Print a report of gaps, jitter and clock drift in the sensor streams (JSON)
+    #[arg(long, group = "mode")]
+    integrity: bool,
+
+    /// Print a gyroscope/accelerometer calibration report from stationary intervals
+    #[arg(long, group = "mode")]
+    calibrate: bool,
+
+    /// Correct RDTA and RDTB with the estimated calibration before any output
+    #[arg(long)]
+    apply_calibration: bool,
+
+    /// Reject RDL2 fixes below this fix type (2 = 2D, 3 = 3D) before any output
+    #[arg(long, value_name = "Fix Type")]
+    min_fix_type: Option<i16>,
+
+    /// Reject RDL2 fixes with a larger horizontal accuracy, in metres
+    #[arg(long, value_name = "Metres")]
+    max_accuracy: Option<f32>,
+
+    /// Reject RDL2 fixes with a larger vertical accuracy, in metres
+    #[arg(long, value_name = "Metres")]
+    max_vertical_accuracy: Option<f32>,
+
+    /// Reject RDL2 fixes with a larger speed accuracy, in m/s
+    #[arg(long, value_name = "Speed")]
+    max_speed_accuracy: Option<f32>,
+
+    /// Reject RDL2 fixes reporting a faster horizontal speed, in m/s
+    #[arg(long, value_name = "Speed")]
+    max_speed: Option<f64>,
+
+    /// Reject RDL2 fixes reporting a faster vertical speed, in m/s
+    #[arg(long, value_name = "Speed")]
+    max_vertical_speed: Option<f64>,
+
+    /// Reject RDL2 fixes reached from both neighbours faster than this, in m/s
+    #[arg(long, value_name = "Speed")]
+    max_jump_speed: Option<f64>,
+
+    /// Replace RDL2 with a Kalman/RTS-smoothed track before any output
+    #[arg(long)]
+    smooth_gps: bool,
+
+    /// Add trip statistics of the GPS track to the JSON output
+    #[arg(long)]
+    stats: bool,
+
+    /// Distance formula of --stats: haversine or vincenty
+    #[arg(
+        long,
+        value_name = "Formula",
+        requires = "stats",
+        default_value = "haversine"
+    )]
+    distance_formula: DistanceFormula,
 }
 
 fn main() {
     let cli = Cli::parse();
-    let target_boxes: Option<Vec<String>> = cli
-        .target
-        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect());
+    let gps_filter = gps_filter(&cli);
+    let target_boxes = target_boxes(&cli, gps_filter.is_some());
 
     match parse(&cli.filename, target_boxes.as_deref()) {
         Some((_mp4, theta_meta)) => {
@@ -33,14 +230,291 @@ fn main() {
                 writeln!(io::stderr(), "Metadata not found").unwrap();
                 std::process::exit(0);
             }
-            let meta = theta_meta.as_ref().unwrap();
+            let mut meta = theta_meta.unwrap();
+            if cli.calibrate || cli.apply_calibration {
+                let Some(report) = calibration_report(&meta) else {
+                    writeln!(io::stderr(), "RDTA and RDTB are required for calibration").unwrap();
+                    std::process::exit(1);
+                };
+                if cli.calibrate {
+                    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                    return;
+                }
+                meta.rdta = meta.rdta.map(|rdta| report.correct_accelerometer(&rdta));
+                meta.rdtb = meta.rdtb.map(|rdtb| report.correct_gyroscope(&rdtb));
+            }
+            if let (Some(filter), Some(rdl2)) = (&gps_filter, &meta.rdl2) {
+                let (filtered, report) = filter.apply(rdl2);
+                writeln!(
+                    io::stderr(),
+                    "RDL2: rejected {} of {} fixes {}",
+                    report.total - report.accepted,
+                    report.total,
+                    serde_json::to_string(&report.counts).unwrap()
+                )
+                .unwrap();
+                meta.rdl2 = Some(filtered);
+            }
+            if cli.smooth_gps {
+                let timeline = Timeline::new(&meta);
+                let Some(fixes) = smooth_track(&meta, &timeline, Default::default()) else {
+                    writeln!(
+                        io::stderr(),
+                        "RDL2 with at least one fix is required for smoothing"
+                    )
+                    .unwrap();
+                    std::process::exit(1);
+                };
+                meta.rdl2 = meta.rdl2.map(|rdl2| smoothed_rdl2(&rdl2, &fixes));
+            }
+            let meta = &meta;
+            if cli.frames {
+                let lookup = FrameLookup::new(meta, Timeline::new(meta));
+                print_lines(lookup.frames());
+                return;
+            }
+            if cli.horizon {
+                let timeline = Timeline::new(meta);
+                print_lines(horizon_corrections(meta, &timeline, Default::default()));
+                return;
+            }
+            if cli.heading {
+                let config = CompassConfig {
+                    declination: declination(&cli, meta),
+                    ..Default::default()
+                };
+                let Some(headings) = compass_headings(meta, &Timeline::new(meta), config) else {
+                    writeln!(
+                        io::stderr(),
+                        "RDTA and a calibratable RDTC are required for headings"
+                    )
+                    .unwrap();
+                    std::process::exit(1);
+                };
+                print_lines(headings.frames);
+                return;
+            }
+            if let Some(rate) = cli.resample {
+                let timeline = Timeline::new(meta);
+                let config = ResampleConfig {
+                    interpolation: cli.interpolation,
+                    max_gap: cli.max_gap,
+                };
+                let resampler = Resampler::new(meta, &timeline, config);
+                let resampled = serde_json::json!({
+                    "accelerometer": resampler.accelerometer(rate),
+                    "gyroscope": resampler.gyroscope(rate),
+                    "magnetometer": resampler.magnetometer(rate),
+                    "position": resampler.position(rate),
+                });
+                println!("{}", resampled);
+                return;
+            }
+            if let Some(spacing) = cli.select_frames {
+                let config = SelectionConfig {
+                    spacing: Some(spacing),
+                    heading_change: cli.heading_step,
+                    declination: declination(&cli, meta),
+                    ..Default::default()
+                };
+                print_lines(select_frames(meta, &Timeline::new(meta), config));
+                return;
+            }
+            if cli.opensfm.is_some() || cli.colmap.is_some() || cli.mapillary.is_some() {
+                // Mapillary records magnetic and true headings separately.
+                let frames = match cli.mapillary {
+                    Some(_) => export_frames(meta, cli.spacing, Declination::None),
+                    None => export_frames(meta, cli.spacing, declination(&cli, meta)),
+                };
+                let pattern = &cli.frame_pattern;
+                let written = if let Some(dir) = &cli.opensfm {
+                    let overrides = sfm::opensfm_exif_overrides(&frames, pattern);
+                    let dir = Path::new(dir);
+                    std::fs::create_dir_all(dir)
+                        .and_then(|_| {
+                            std::fs::write(
+                                dir.join("exif_overrides.json"),
+                                serde_json::to_string_pretty(&overrides).unwrap(),
+                            )
+                        })
+                        .and_then(|_| {
+                            std::fs::write(
+                                dir.join("gps_list.txt"),
+                                sfm::opensfm_gps_list(&frames, pattern),
+                            )
+                        })
+                        .map(|_| dir.display().to_string())
+                } else if let Some(path) = &cli.colmap {
+                    let priors = match sfm::colmap_pose_priors(&frames, pattern, cli.colmap_frame) {
+                        Ok(priors) => priors,
+                        Err(e) => {
+                            writeln!(io::stderr(), "{}", e).unwrap();
+                            std::process::exit(1);
+                        }
+                    };
+                    std::fs::write(path, priors).map(|_| path.to_string())
+                } else {
+                    let path = cli.mapillary.as_deref().unwrap_or_default();
+                    let uuid = mapillary::sequence_uuid(meta);
+                    let descriptions = mapillary::image_descriptions(
+                        meta,
+                        &frames,
+                        pattern,
+                        &uuid,
+                        declination(&cli, meta),
+                    );
+                    let issues = mapillary::validate_descriptions(&descriptions);
+                    if !issues.is_empty() {
+                        for issue in issues {
+                            writeln!(io::stderr(), "{}", issue).unwrap();
+                        }
+                        std::process::exit(1);
+                    }
+                    let json = serde_json::to_string_pretty(&descriptions).unwrap();
+                    std::fs::write(path, json).map(|_| path.to_string())
+                };
+                match written {
+                    Ok(path) => {
+                        writeln!(io::stderr(), "Wrote {} frames to {}", frames.len(), path).unwrap()
+                    }
+                    Err(e) => {
+                        writeln!(io::stderr(), "Failed to write the export: {}", e).unwrap();
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            if cli.bridge_gaps {
+                let Some(track) = bridge_gaps(meta, &Timeline::new(meta), Default::default())
+                else {
+                    writeln!(
+                        io::stderr(),
+                        "RDL2, RDTA and RDTB are required to bridge GPS gaps"
+                    )
+                    .unwrap();
+                    std::process::exit(1);
+                };
+                print_lines(track);
+                return;
+            }
+            if let Some(frame) = cli.coordinates {
+                let track = match ProjectedTrack::new(meta, frame) {
+                    Ok(track) => track,
+                    Err(e) => {
+                        writeln!(io::stderr(), "{}", e).unwrap();
+                        std::process::exit(1);
+                    }
+                };
+                if cli.csv {
+                    print!("{}", track.to_csv());
+                } else {
+                    println!("{}", serde_json::to_string_pretty(&track).unwrap());
+                }
+                return;
+            }
+            if cli.segments {
+                let timeline = Timeline::new(meta);
+                let Some(segments) = segment_motion(meta, &timeline, Default::default()) else {
+                    writeln!(io::stderr(), "RDTA and RDTB are required for segmentation").unwrap();
+                    std::process::exit(1);
+                };
+                let trim = trim_range(&segments);
+                let output = serde_json::json!({ "segments": segments, "trim": trim });
+                println!("{}", serde_json::to_string_pretty(&output).unwrap());
+                if let Some(trim) = trim {
+                    let output = Path::new(&cli.filename).with_extension("");
+                    let output = format!("{}_trim.mp4", output.display());
+                    writeln!(
+                        io::stderr(),
+                        "{}",
+                        ffmpeg::trim_command_line(&cli.filename, trim.start, trim.end, &output)
+                    )
+                    .unwrap();
+                }
+                return;
+            }
+            if cli.events || cli.chapters.is_some() {
+                let timeline = Timeline::new(meta);
+                let config = EventConfig {
+                    sensitivity: cli.sensitivity,
+                    ..Default::default()
+                };
+                let Some(events) = detect_events(meta, &timeline, config) else {
+                    writeln!(
+                        io::stderr(),
+                        "RDTA and RDTB are required for event detection"
+                    )
+                    .unwrap();
+                    std::process::exit(1);
+                };
+                let Some(metadata_path) = &cli.chapters else {
+                    print_lines(events);
+                    return;
+                };
+                let end = timeline
+                    .frame_count()
+                    .checked_sub(1)
+                    .and_then(|frame| timeline.frame_interval(frame))
+                    .map(|interval| interval.end)
+                    .or(events.last().map(|e| e.end))
+                    .unwrap_or_default();
+                let metadata = ffmpeg::chapters_metadata(&chapters(&events, end));
+                if let Err(e) = std::fs::write(metadata_path, metadata) {
+                    writeln!(io::stderr(), "Failed to write {}: {}", metadata_path, e).unwrap();
+                    std::process::exit(1);
+                }
+                let output = Path::new(&cli.filename).with_extension("");
+                let output = format!("{}_chapters.mp4", output.display());
+                writeln!(
+                    io::stderr(),
+                    "{}",
+                    ffmpeg::chapters_command_line(&cli.filename, metadata_path, &output)
+                )
+                .unwrap();
+                return;
+            }
+            if cli.integrity {
+                let report = IntegrityReport::new(meta, &Timeline::new(meta));
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                return;
+            }
+            if let Some(script_path) = &cli.v360 {
+                let timeline = Timeline::new(meta);
+                let corrections = horizon_corrections(meta, &timeline, Default::default());
+                let script = ffmpeg::sendcmd_script(&corrections, cli.heading_lock);
+                if let Err(e) = std::fs::write(script_path, script) {
+                    writeln!(io::stderr(), "Failed to write {}: {}", script_path, e).unwrap();
+                    std::process::exit(1);
+                }
+                let output = Path::new(&cli.filename)
+                    .with_extension("")
+                    .display()
+                    .to_string();
+                let output = format!("{}_level.mp4", output);
+                writeln!(
+                    io::stderr(),
+                    "{}",
+                    ffmpeg::command_line(&cli.filename, script_path, &output)
+                )
+                .unwrap();
+                return;
+            }
             if let Some(rthu_box) = &meta.rthu {
                 let _ = rthu_box.write_to_file(&cli.filename);
                 if let Err(e) = rthu_box.write_to_file(&cli.filename) {
                     eprintln!("Failed to write the RTHU: {}", e);
                 }
             }
-            let json_result = serde_json::to_string_pretty(&meta.to_serializable()).unwrap();
+            let mut output = meta.to_serializable();
+            retain_requested(&mut output, &output_boxes(&cli, gps_filter.is_some()));
+            if cli.stats {
+                let config = StatsConfig {
+                    distance: cli.distance_formula,
+                    ..Default::default()
+                };
+                output.stats = TrackStats::new(meta, config);
+            }
+            let json_result = serde_json::to_string_pretty(&output).unwrap();
             println!("{}", json_result);
         }
         None => {
@@ -49,3 +523,272 @@ fn main() {
         }
     }
 }
+
+/// Boxes named by `-t`.
+fn requested_boxes(cli: &Cli) -> Option<Vec<String>> {
+    cli.target
+        .as_ref()
+        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+}
+
+/// Boxes printed in the JSON output: those named by `-t`, or else the tables
+/// corrected by the calibration, GPS filter and smoothing flags.
+fn output_boxes(cli: &Cli, filter_gps: bool) -> Vec<String> {
+    requested_boxes(cli).unwrap_or_else(|| {
+        let mut boxes = Vec::new();
+        if cli.apply_calibration {
+            boxes.extend(["RDTA".to_string(), "RDTB".to_string()]);
+        }
+        if cli.smooth_gps || filter_gps {
+            boxes.push("RDL2".to_string());
+        }
+        boxes
+    })
+}
+
+/// Boxes to read besides the always-included ones; `None` keeps the default set.
+fn target_boxes(cli: &Cli, filter_gps: bool) -> Option<Vec<String>> {
+    let mut target_boxes = requested_boxes(cli);
+    if cli.frames
+        || cli.horizon
+        || cli.v360.is_some()
+        || cli.heading
+        || cli.resample.is_some()
+        || cli.bridge_gaps
+        || cli.select_frames.is_some()
+        || cli.opensfm.is_some()
+        || cli.colmap.is_some()
+        || cli.mapillary.is_some()
+        || cli.segments
+        || cli.events
+        || cli.chapters.is_some()
+        || cli.integrity
+        || cli.calibrate
+    {
+        let targets = target_boxes.get_or_insert_with(Vec::new);
+        targets.extend(FRAME_BOXES.iter().map(|s| s.to_string()));
+    } else if cli.apply_calibration {
+        let targets = target_boxes.get_or_insert_with(Vec::new);
+        targets.extend(["RDTA".to_string(), "RDTB".to_string()]);
+    }
+    if cli.coordinates.is_some() || cli.stats {
+        let targets = target_boxes.get_or_insert_with(Vec::new);
+        targets.extend(["RDTL".to_string(), "RDL2".to_string()]);
+    } else if cli.smooth_gps || filter_gps {
+        target_boxes
+            .get_or_insert_with(Vec::new)
+            .push("RDL2".to_string());
+    }
+    target_boxes
+}
+
+/// Leaves out the tables that were read only to serve other flags.
+fn retain_requested(output: &mut SerializableThetaMeta, requested: &[String]) {
+    let requested = |name: &str| requested.iter().any(|t| t == "all" || t == name);
+    if !requested("RDTA") {
+        output.rdta = None;
+    }
+    if !requested("RDTB") {
+        output.rdtb = None;
+    }
+    if !requested("RDTC") {
+        output.rdtc = None;
+    }
+    if !requested("RDTG") {
+        output.rdtg = None;
+    }
+    if !requested("RDTL") {
+        output.rdtl = None;
+    }
+    if !requested("RDL2") {
+        output.rdl2 = None;
+    }
+}
+
+fn positive_seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => Err(format!("expected a positive number of seconds: {}", s)),
+    }
+}
+
+fn gps_filter(cli: &Cli) -> Option<GpsFilter> {
+    (cli.min_fix_type.is_some()
+        || cli.max_accuracy.is_some()
+        || cli.max_vertical_accuracy.is_some()
+        || cli.max_speed_accuracy.is_some()
+        || cli.max_speed.is_some()
+        || cli.max_vertical_speed.is_some()
+        || cli.max_jump_speed.is_some())
+    .then(|| GpsFilter {
+        min_fix_type: cli
+            .min_fix_type
+            .unwrap_or(GpsFilter::default().min_fix_type),
+        max_horizontal_accuracy: cli.max_accuracy,
+        max_vertical_accuracy: cli.max_vertical_accuracy,
+        max_speed_accuracy: cli.max_speed_accuracy,
+        max_speed: cli.max_speed,
+        max_vertical_speed: cli.max_vertical_speed,
+        max_jump_speed: cli.max_jump_speed,
+    })
+}
+
+fn calibration_report(meta: &ThetaMeta) -> Option<CalibrationReport> {
+    let timeline = Timeline::new(meta);
+    let (rdta, rdtb) = (meta.rdta.as_ref()?, meta.rdtb.as_ref()?);
+    Some(CalibrationReport::new(
+        rdta,
+        rdtb,
+        &timeline,
+        Default::default(),
+    ))
+}
+
+/// Frames every `spacing` metres, or every frame.
+fn export_frames(
+    meta: &ThetaMeta,
+    spacing: Option<f64>,
+    declination: Declination,
+) -> Vec<SelectedFrame> {
+    let timeline = Timeline::new(meta);
+    match spacing {
+        Some(spacing) => {
+            let config = SelectionConfig {
+                spacing: Some(spacing),
+                declination,
+                ..Default::default()
+            };
+            select_frames(meta, &timeline, config)
+        }
+        None => frame_poses(meta, &timeline, declination),
+    }
+}
+
+fn declination(cli: &Cli, meta: &ThetaMeta) -> Declination {
+    let declination = cli.declination.unwrap_or(Declination::None);
+    if matches!(declination, Declination::Wmm) && wmm_declination(meta).is_none() {
+        writeln!(
+            io::stderr(),
+            "No GPS fix within the World Magnetic Model's years (2020 to 2030); reporting magnetic headings"
+        )
+        .unwrap();
+    }
+    declination
+}
+
+/// Writes one compact JSON record per line.
+fn print_lines<T: Serialize>(records: impl IntoIterator<Item = T>) {
+    let mut out = BufWriter::new(io::stdout().lock());
+    for record in records {
+        let line = serde_json::to_string(&record).unwrap();
+        if writeln!(out, "{}", line).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use theta_mp4::theta::{rdl2::Rdl2Box, rdta::RdtaBox};
+
+    use super::*;
+
+    fn targets(args: &[&str]) -> Option<Vec<String>> {
+        let cli = Cli::parse_from(["theta-mp4", "video.mp4"].iter().chain(args));
+        target_boxes(&cli, gps_filter(&cli).is_some())
+    }
+
+    #[test]
+    fn test_target_boxes() {
+        assert_eq!(targets(&[]), None);
+        assert_eq!(targets(&["-t", "RTHU"]), Some(vec!["RTHU".to_string()]));
+        for mode in ["--frames", "--integrity", "--calibrate", "--segments"] {
+            let boxes = targets(&[mode]).unwrap();
+            assert!(FRAME_BOXES.iter().all(|b| boxes.contains(&b.to_string())));
+        }
+        assert_eq!(targets(&["--smooth-gps"]), Some(vec!["RDL2".to_string()]));
+        assert_eq!(
+            targets(&["--apply-calibration"]),
+            Some(vec!["RDTA".to_string(), "RDTB".to_string()])
+        );
+        assert_eq!(
+            targets(&["--max-speed", "30"]),
+            Some(vec!["RDL2".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_retain_requested() {
+        // Empty tables with a little-endian header.
+        let header = [0, 0, 0, 0, 1, 0, 24, 0, 0x23, 0x01, 0, 0, 0, 0, 0, 0];
+        let meta = ThetaMeta {
+            rdta: RdtaBox::view(&header).map(RdtaBox::from_view),
+            rdl2: Rdl2Box::view(&header).map(Rdl2Box::from_view),
+            ..Default::default()
+        };
+
+        let output = |args: &[&str]| {
+            let cli = Cli::parse_from(["theta-mp4", "v.mp4"].iter().chain(args));
+            let filter_gps = gps_filter(&cli).is_some();
+            assert!(target_boxes(&cli, filter_gps)
+                .unwrap()
+                .contains(&"RDL2".to_string()));
+            let mut output = meta.to_serializable();
+            retain_requested(&mut output, &output_boxes(&cli, filter_gps));
+            output
+        };
+        let filtered = output(&["-t", "RDTA", "--max-speed", "30"]);
+        assert!(filtered.rdta.is_some());
+        assert!(filtered.rdl2.is_none());
+        assert!(!serde_json::to_string(&filtered).unwrap().contains("stats"));
+        assert!(output(&["--max-speed", "30"]).rdl2.is_some());
+        assert!(output(&["--stats"]).rdl2.is_none());
+
+        let mut output = meta.to_serializable();
+        retain_requested(&mut output, &["all".to_string()]);
+        assert!(output.rdl2.is_some());
+    }
+
+    #[test]
+    fn test_resample_args() {
+        let cli = Cli::parse_from([
+            "theta-mp4",
+            "v.mp4",
+            "--resample",
+            "100",
+            "--max-gap",
+            "0.5",
+        ]);
+        assert_eq!(cli.resample, Some(TargetRate::Hz(100.0)));
+        assert_eq!(cli.max_gap, Some(0.5));
+        for args in [
+            ["--resample", "100000", "--max-gap", "1"],
+            ["--resample", "100", "--max-gap", "0"],
+        ] {
+            let args = ["theta-mp4", "v.mp4"].into_iter().chain(args);
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_gps_filter() {
+        let cli = Cli::parse_from(["theta-mp4", "video.mp4"]);
+        assert!(gps_filter(&cli).is_none());
+        let cli = Cli::parse_from([
+            "theta-mp4",
+            "video.mp4",
+            "--max-vertical-accuracy",
+            "5",
+            "--max-speed-accuracy",
+            "2",
+            "--max-vertical-speed",
+            "4",
+        ]);
+        let filter = gps_filter(&cli).unwrap();
+        assert_eq!(filter.min_fix_type, 2);
+        assert_eq!(filter.max_vertical_accuracy, Some(5.0));
+        assert_eq!(filter.max_speed_accuracy, Some(2.0));
+        assert_eq!(filter.max_vertical_speed, Some(4.0));
+        assert_eq!(filter.max_horizontal_accuracy, None);
+    }
+}
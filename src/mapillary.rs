@@ -0,0 +1,276 @@
+//! Mapillary image description files for geotagged frame sequences.
+//!
+//! The records follow the description JSON read by `mapillary_tools`, so
+//! frames extracted from the video can be uploaded without writing EXIF.
+
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compass::Declination, selection::SelectedFrame, sfm::frame_filename, theta::ThetaMeta,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct CompassHeading {
+    #[serde(rename = "TrueHeading")]
+    pub true_heading: f64,
+    #[serde(rename = "MagneticHeading")]
+    pub magnetic_heading: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ImageDescription {
+    pub filename: String,
+    pub filetype: String,
+    #[serde(rename = "MAPLatitude")]
+    pub latitude: f64,
+    #[serde(rename = "MAPLongitude")]
+    pub longitude: f64,
+    #[serde(rename = "MAPAltitude", skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+    /// UTC, as `YYYY_MM_DD_HH_MM_SS_mmm`.
+    #[serde(rename = "MAPCaptureTime")]
+    pub capture_time: String,
+    #[serde(rename = "MAPCompassHeading", skip_serializing_if = "Option::is_none")]
+    pub compass_heading: Option<CompassHeading>,
+    #[serde(rename = "MAPSequenceUUID")]
+    pub sequence_uuid: String,
+    #[serde(rename = "MAPDeviceMake", skip_serializing_if = "Option::is_none")]
+    pub device_make: Option<String>,
+    #[serde(rename = "MAPDeviceModel", skip_serializing_if = "Option::is_none")]
+    pub device_model: Option<String>,
+}
+
+/// UNIX seconds as a Mapillary capture time.
+pub fn capture_time(utc: f64) -> String {
+    let millis = (utc * 1000.0).round() as i64;
+    let (days, ms) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    // Days since 1970-01-01 to a proleptic Gregorian date.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}_{:02}_{:02}_{:02}_{:02}_{:02}_{:03}",
+        year,
+        month,
+        day,
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// A UUID derived from the recording, so re-exports keep the same sequence.
+pub fn sequence_uuid(meta: &ThetaMeta) -> String {
+    let mut seed = format!("{}|{}|{}", meta._mak, meta.modl, meta._swr);
+    if let Some(video) = &meta.video {
+        let _ = write!(seed, "|{}|{}", video.creation_time, video.duration);
+    }
+    if let Some(first) = meta.rdl2.as_ref().and_then(|b| b.entries().first()) {
+        let _ = write!(seed, "|{}", first.timestamp);
+    }
+    // Two FNV-1a passes with different offsets fill 128 bits.
+    let fnv = |offset: u64| {
+        seed.bytes().fold(offset, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    };
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&fnv(0xcbf2_9ce4_8422_2325).to_be_bytes());
+    bytes[8..].copy_from_slice(&fnv(0x6c62_272e_07bb_0142).to_be_bytes());
+    // Version 8 (custom), RFC 4122 variant.
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// One description per frame with a position and a capture time.
+///
+/// `frames` carry magnetic headings (selected with [`Declination::None`]), and
+/// `declination` turns them into true ones.
+pub fn image_descriptions(
+    meta: &ThetaMeta,
+    frames: &[SelectedFrame],
+    pattern: &str,
+    sequence_uuid: &str,
+    declination: Declination,
+) -> Vec<ImageDescription> {
+    let declination = declination.degrees(meta);
+    let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+    frames
+        .iter()
+        .filter_map(|f| {
+            let (position, utc) = (f.position?, f.utc?);
+            Some(ImageDescription {
+                filename: frame_filename(pattern, f.frame),
+                filetype: "image".to_string(),
+                latitude: position.latitude,
+                longitude: position.longitude,
+                altitude: Some(position.altitude),
+                capture_time: capture_time(utc),
+                compass_heading: f.heading.map(|heading| CompassHeading {
+                    true_heading: (heading + declination).rem_euclid(360.0),
+                    magnetic_heading: heading,
+                }),
+                sequence_uuid: sequence_uuid.to_string(),
+                device_make: non_empty(&meta._mak).or_else(|| non_empty(&meta.manu)),
+                device_model: non_empty(&meta.modl),
+            })
+        })
+        .collect()
+}
+
+fn valid_capture_time(time: &str) -> bool {
+    let parts: Vec<&str> = time.split('_').collect();
+    let widths = [4, 2, 2, 2, 2, 2, 3];
+    parts.len() == widths.len()
+        && parts
+            .iter()
+            .zip(widths)
+            .all(|(part, width)| part.len() == width && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Problems that would make an upload fail or misplace images; empty when valid.
+pub fn validate_descriptions(descriptions: &[ImageDescription]) -> Vec<String> {
+    let mut issues = Vec::new();
+    if descriptions.is_empty() {
+        issues.push("no images".to_string());
+    }
+    let mut names = std::collections::HashSet::new();
+    let mut previous: Option<&ImageDescription> = None;
+    for d in descriptions {
+        let name = &d.filename;
+        if name.is_empty() {
+            issues.push("image without a filename".to_string());
+        } else if !names.insert(name) {
+            issues.push(format!("{}: duplicate filename", name));
+        }
+        if d.filetype != "image" {
+            issues.push(format!("{}: filetype is not `image`", name));
+        }
+        if !(d.latitude.is_finite() && d.latitude.abs() <= 90.0) {
+            issues.push(format!("{}: latitude out of range", name));
+        }
+        if !(d.longitude.is_finite() && d.longitude.abs() <= 180.0) {
+            issues.push(format!("{}: longitude out of range", name));
+        }
+        if !valid_capture_time(&d.capture_time) {
+            issues.push(format!("{}: malformed capture time", name));
+        }
+        if let Some(heading) = d.compass_heading {
+            let valid = |h: f64| (0.0..360.0).contains(&h);
+            if !valid(heading.true_heading) || !valid(heading.magnetic_heading) {
+                issues.push(format!("{}: compass heading out of range", name));
+            }
+        }
+        if d.sequence_uuid.is_empty() {
+            issues.push(format!("{}: missing sequence UUID", name));
+        }
+        // The fixed-width capture times sort chronologically as strings.
+        if let Some(p) = previous.filter(|p| p.sequence_uuid == d.sequence_uuid) {
+            if d.capture_time <= p.capture_time {
+                issues.push(format!("{}: capture time not after {}", name, p.filename));
+            }
+        }
+        previous = Some(d);
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geo::GeoPoint, testing};
+
+    fn setup() -> (ThetaMeta, Vec<SelectedFrame>) {
+        let meta = ThetaMeta {
+            _mak: "RICOH".to_string(),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(1_700_000_000, 30.0, 90)),
+            ..Default::default()
+        };
+        let frame = |frame: usize| SelectedFrame {
+            frame,
+            time: frame as f64 / 30.0,
+            utc: Some(1_700_000_000.0 + frame as f64 / 30.0),
+            distance: frame as f64,
+            position: Some(GeoPoint::new(35.0, 139.0, 10.0)),
+            horizontal_accuracy: None,
+            orientation: None,
+            roll: None,
+            pitch: None,
+            yaw: None,
+            heading: Some(355.0),
+        };
+        (meta, vec![frame(0), frame(30), frame(60)])
+    }
+
+    #[test]
+    fn test_capture_time() {
+        assert_eq!(capture_time(0.0), "1970_01_01_00_00_00_000");
+        assert_eq!(capture_time(1_700_000_000.25), "2023_11_14_22_13_20_250");
+        assert_eq!(capture_time(951_782_400.0), "2000_02_29_00_00_00_000");
+    }
+
+    #[test]
+    fn test_image_descriptions() {
+        let (meta, frames) = setup();
+        let uuid = sequence_uuid(&meta);
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "8");
+        assert_eq!(uuid, sequence_uuid(&meta));
+
+        let descriptions = image_descriptions(
+            &meta,
+            &frames,
+            "img_%03d.jpg",
+            &uuid,
+            Declination::Fixed(10.0),
+        );
+        assert_eq!(descriptions.len(), 3);
+        let d = &descriptions[1];
+        assert_eq!(d.filename, "img_030.jpg");
+        assert_eq!(d.capture_time, "2023_11_14_22_13_21_000");
+        assert_eq!(d.device_make.as_deref(), Some("RICOH"));
+        let heading = d.compass_heading.unwrap();
+        assert_eq!(heading.magnetic_heading, 355.0);
+        assert_eq!(heading.true_heading, 5.0);
+        assert!(validate_descriptions(&descriptions).is_empty());
+
+        let json = serde_json::to_value(d).unwrap();
+        assert_eq!(json["MAPLatitude"], 35.0);
+        assert_eq!(json["MAPCompassHeading"]["TrueHeading"], 5.0);
+        let back: ImageDescription = serde_json::from_value(json).unwrap();
+        assert_eq!(&back, d);
+    }
+
+    #[test]
+    fn test_validate_descriptions() {
+        let (meta, frames) = setup();
+        let mut descriptions = image_descriptions(&meta, &frames, "img.jpg", "", Declination::None);
+        descriptions[2].latitude = 91.0;
+        descriptions[2].capture_time = "2023-11-14".to_string();
+        let issues = validate_descriptions(&descriptions);
+        assert!(issues.contains(&"img.jpg: duplicate filename".to_string()));
+        assert!(issues.contains(&"img.jpg: missing sequence UUID".to_string()));
+        assert!(issues.contains(&"img.jpg: latitude out of range".to_string()));
+        assert!(issues.contains(&"img.jpg: malformed capture time".to_string()));
+        assert_eq!(validate_descriptions(&[]), vec!["no images".to_string()]);
+    }
+}
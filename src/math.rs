@@ -0,0 +1,525 @@
+use std::{
+    f64::consts::{PI, TAU},
+    ops::{Add, Mul, Neg, Sub},
+};
+
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vector3 {
+    pub const fn new(x: f64, y: f64, z: f64) -> Vector3 {
+        Vector3 { x, y, z }
+    }
+
+    pub fn from_f32(x: f32, y: f32, z: f32) -> Vector3 {
+        Vector3::new(x as f64, y as f64, z as f64)
+    }
+
+    pub fn dot(self, other: Vector3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vector3) -> Vector3 {
+        Vector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn norm(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// Unit vector in the same direction, or `None` for the zero vector.
+    pub fn normalize(self) -> Option<Vector3> {
+        let norm = self.norm();
+        (norm > 0.0 && norm.is_finite()).then(|| self * (1.0 / norm))
+    }
+
+    pub fn lerp(self, other: Vector3, t: f64) -> Vector3 {
+        self + (other - self) * t
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, k: f64) -> Vector3 {
+        Vector3::new(self.x * k, self.y * k, self.z * k)
+    }
+}
+
+impl Neg for Vector3 {
+    type Output = Vector3;
+
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Rotation quaternion `w + xi + yj + zk`.
+///
+/// [`Quaternion::rotate`] maps vectors from the sensor frame to the world frame.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+
+    pub const fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    /// Rotation of `angle` radians about `axis`, or the identity for a zero axis.
+    pub fn from_axis_angle(axis: Vector3, angle: f64) -> Quaternion {
+        match axis.normalize() {
+            Some(axis) => {
+                let v = axis * (angle / 2.0).sin();
+                Quaternion::new((angle / 2.0).cos(), v.x, v.y, v.z)
+            }
+            None => Quaternion::IDENTITY,
+        }
+    }
+
+    /// Shortest rotation taking the direction of `from` onto the direction of `to`.
+    pub fn from_rotation_arc(from: Vector3, to: Vector3) -> Option<Quaternion> {
+        let (from, to) = (from.normalize()?, to.normalize()?);
+        let dot = from.dot(to);
+        if dot < -1.0 + 1e-12 {
+            // Opposite directions: turn half way round any perpendicular axis.
+            let axis = from.cross(Vector3::new(1.0, 0.0, 0.0));
+            let axis = if axis.norm() < 1e-6 {
+                from.cross(Vector3::new(0.0, 1.0, 0.0))
+            } else {
+                axis
+            };
+            return Some(Quaternion::from_axis_angle(axis, PI));
+        }
+        let v = from.cross(to);
+        Quaternion::new(1.0 + dot, v.x, v.y, v.z).normalize()
+    }
+
+    /// Rotation `yaw` about z, then `pitch` about y, then `roll` about x, applied
+    /// in the body frame (radians).
+    pub fn from_euler(roll: f64, pitch: f64, yaw: f64) -> Quaternion {
+        Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), yaw)
+            * Quaternion::from_axis_angle(Vector3::new(0.0, 1.0, 0.0), pitch)
+            * Quaternion::from_axis_angle(Vector3::new(1.0, 0.0, 0.0), roll)
+    }
+
+    /// Inverse of [`Quaternion::from_euler`], as `(roll, pitch, yaw)` in radians.
+    pub fn to_euler(self) -> (f64, f64, f64) {
+        let Quaternion { w, x, y, z } = self;
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+        (roll, pitch, yaw)
+    }
+
+    pub fn vector(self) -> Vector3 {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn dot(self, other: Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn norm(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// Unit quaternion, or `None` for the zero quaternion.
+    pub fn normalize(self) -> Option<Quaternion> {
+        let norm = self.norm();
+        (norm > 0.0 && norm.is_finite()).then(|| self * (1.0 / norm))
+    }
+
+    /// Rotates `v` by this (unit) quaternion.
+    pub fn rotate(self, v: Vector3) -> Vector3 {
+        let u = self.vector();
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+
+    /// Spherical linear interpolation along the shorter arc.
+    pub fn slerp(self, other: Quaternion, t: f64) -> Quaternion {
+        let (other, dot) = match self.dot(other) {
+            dot if dot < 0.0 => (other * -1.0, -dot),
+            dot => (other, dot),
+        };
+        if dot > 1.0 - 1e-9 {
+            let q = self * (1.0 - t) + other * t;
+            return q.normalize().unwrap_or(self);
+        }
+        let omega = dot.min(1.0).acos();
+        let sin = omega.sin();
+        self * (((1.0 - t) * omega).sin() / sin) + other * ((t * omega).sin() / sin)
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, o: Quaternion) -> Quaternion {
+        Quaternion::new(self.w + o.w, self.x + o.x, self.y + o.y, self.z + o.z)
+    }
+}
+
+impl Sub for Quaternion {
+    type Output = Quaternion;
+
+    fn sub(self, o: Quaternion) -> Quaternion {
+        Quaternion::new(self.w - o.w, self.x - o.x, self.y - o.y, self.z - o.z)
+    }
+}
+
+impl Mul<f64> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, k: f64) -> Quaternion {
+        Quaternion::new(self.w * k, self.x * k, self.y * k, self.z * k)
+    }
+}
+
+/// Hamilton product: `a * b` applies `b` first, then `a`.
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, o: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        )
+    }
+}
+
+/// Row-major 3x3 matrix.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Matrix3(pub [[f64; 3]; 3]);
+
+impl Default for Matrix3 {
+    fn default() -> Self {
+        Matrix3::IDENTITY
+    }
+}
+
+impl Matrix3 {
+    pub const IDENTITY: Matrix3 = Matrix3([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+
+    pub fn transpose(self) -> Matrix3 {
+        let m = self.0;
+        Matrix3([0, 1, 2].map(|i| [m[0][i], m[1][i], m[2][i]]))
+    }
+
+    pub fn determinant(self) -> f64 {
+        let m = self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, by Jacobi rotations.
+    pub fn symmetric_eigen(self) -> ([f64; 3], Matrix3) {
+        let mut a = self.0;
+        let mut v = Matrix3::IDENTITY.0;
+        for _ in 0..50 {
+            if a[0][1].abs() + a[0][2].abs() + a[1][2].abs() < 1e-15 {
+                break;
+            }
+            for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for m in [&mut a, &mut v] {
+                    for row in m.iter_mut() {
+                        let (kp, kq) = (row[p], row[q]);
+                        row[p] = c * kp - s * kq;
+                        row[q] = s * kp + c * kq;
+                    }
+                }
+                let (rp, rq) = (a[p], a[q]);
+                for k in 0..3 {
+                    a[p][k] = c * rp[k] - s * rq[k];
+                    a[q][k] = s * rp[k] + c * rq[k];
+                }
+            }
+        }
+        ([a[0][0], a[1][1], a[2][2]], Matrix3(v))
+    }
+
+    /// Symmetric square root of a symmetric positive definite matrix.
+    pub fn symmetric_sqrt(self) -> Option<Matrix3> {
+        let (values, vectors) = self.symmetric_eigen();
+        if values.iter().any(|&v| v <= 0.0 || !v.is_finite()) {
+            return None;
+        }
+        let d = values.map(f64::sqrt);
+        let v = vectors.0;
+        let diagonal = Matrix3([[d[0], 0.0, 0.0], [0.0, d[1], 0.0], [0.0, 0.0, d[2]]]);
+        Some(Matrix3(v) * diagonal * Matrix3(v).transpose())
+    }
+}
+
+impl Mul for Matrix3 {
+    type Output = Matrix3;
+
+    fn mul(self, o: Matrix3) -> Matrix3 {
+        let (a, b) = (self.0, o.0);
+        Matrix3([0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum())))
+    }
+}
+
+impl Mul<Vector3> for Matrix3 {
+    type Output = Vector3;
+
+    fn mul(self, v: Vector3) -> Vector3 {
+        let m = self.0;
+        Vector3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul<f64> for Matrix3 {
+    type Output = Matrix3;
+
+    fn mul(self, k: f64) -> Matrix3 {
+        Matrix3(self.0.map(|row| row.map(|v| v * k)))
+    }
+}
+
+/// Interpolates a time-ordered series at `time`, or `None` outside its range.
+pub(crate) fn interpolate<T: Copy>(
+    times: &[f64],
+    values: &[T],
+    time: f64,
+    lerp: impl Fn(T, T, f64) -> T,
+) -> Option<T> {
+    let index = times.partition_point(|&t| t < time);
+    if index == times.len() {
+        return None;
+    }
+    if times[index] == time {
+        return Some(values[index]);
+    }
+    let prev = index.checked_sub(1)?;
+    let span = times[index] - times[prev];
+    if span <= 0.0 {
+        return Some(values[index]);
+    }
+    Some(lerp(
+        values[prev],
+        values[index],
+        (time - times[prev]) / span,
+    ))
+}
+
+/// Like [`interpolate`], but holds the first and last values outside the range.
+pub(crate) fn interpolate_clamped<T: Copy>(
+    times: &[f64],
+    values: &[T],
+    time: f64,
+    lerp: impl Fn(T, T, f64) -> T,
+) -> Option<T> {
+    match interpolate(times, values, time, lerp) {
+        Some(value) => Some(value),
+        None if times.first().is_some_and(|&first| time < first) => values.first().copied(),
+        None => values.last().copied(),
+    }
+}
+
+/// `angle` (radians) shifted by whole turns to lie within half a turn of `previous`.
+pub(crate) fn unwrap_angle(previous: f64, angle: f64) -> f64 {
+    previous + (angle - previous + PI).rem_euclid(TAU) - PI
+}
+
+/// Solves `a * x = b` by Gaussian elimination, or `None` when `a` is singular.
+pub(crate) fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..N {
+            let k = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (value, pivot) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= k * pivot;
+            }
+            b[row] -= k * b[col];
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N).map(|i| a[row][i] * x[i]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Least-squares solution of `rows * x = rhs` through the normal equations.
+pub(crate) fn least_squares<const N: usize>(
+    rows: impl IntoIterator<Item = ([f64; N], f64)>,
+) -> Option<[f64; N]> {
+    let mut ata = [[0.0; N]; N];
+    let mut atb = [0.0; N];
+    for (row, rhs) in rows {
+        for i in 0..N {
+            for j in 0..N {
+                ata[i][j] += row[i] * row[j];
+            }
+            atb[i] += row[i] * rhs;
+        }
+    }
+    solve(ata, atb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector3() {
+        let a = Vector3::new(1.0, 0.0, 0.0);
+        let b = Vector3::new(0.0, 2.0, 0.0);
+        assert_eq!(a.cross(b), Vector3::new(0.0, 0.0, 2.0));
+        assert_eq!(a.lerp(b, 0.5), Vector3::new(0.5, 1.0, 0.0));
+        assert_eq!(b.normalize(), Some(Vector3::new(0.0, 1.0, 0.0)));
+        assert_eq!(Vector3::default().normalize(), None);
+    }
+
+    fn assert_close(a: Vector3, b: Vector3) {
+        assert!((a - b).norm() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_quaternion() {
+        let z = Vector3::new(0.0, 0.0, 1.0);
+        let q = Quaternion::from_axis_angle(z, std::f64::consts::FRAC_PI_2);
+        assert_close(
+            q.rotate(Vector3::new(1.0, 0.0, 0.0)),
+            Vector3::new(0.0, 1.0, 0.0),
+        );
+        assert_close(
+            (q * q).rotate(Vector3::new(1.0, 0.0, 0.0)),
+            Vector3::new(-1.0, 0.0, 0.0),
+        );
+        assert_close(q.conjugate().rotate(q.rotate(z)), z);
+
+        let half = Quaternion::IDENTITY.slerp(q * q, 0.5);
+        assert_close(
+            half.rotate(Vector3::new(1.0, 0.0, 0.0)),
+            Vector3::new(0.0, 1.0, 0.0),
+        );
+
+        let a = Vector3::new(0.0, 1.0, 1.0);
+        let arc = Quaternion::from_rotation_arc(a, z).unwrap();
+        assert_close(arc.rotate(a.normalize().unwrap()), z);
+        let arc = Quaternion::from_rotation_arc(-z, z).unwrap();
+        assert_close(arc.rotate(-z), z);
+
+        let (roll, pitch, yaw) = Quaternion::from_euler(0.1, -0.2, 0.3).to_euler();
+        assert_close(Vector3::new(roll, pitch, yaw), Vector3::new(0.1, -0.2, 0.3));
+    }
+
+    #[test]
+    fn test_matrix3() {
+        let m = Matrix3([[4.0, 1.0, 0.5], [1.0, 3.0, 0.2], [0.5, 0.2, 2.0]]);
+        let root = m.symmetric_sqrt().unwrap();
+        let square = root * root;
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((square.0[i][j] - m.0[i][j]).abs() < 1e-9);
+            }
+        }
+        assert!((m.determinant() - 21.29).abs() < 1e-9);
+        assert_eq!(
+            Matrix3::IDENTITY * Vector3::new(1.0, 2.0, 3.0),
+            Vector3::new(1.0, 2.0, 3.0)
+        );
+        assert_eq!((m * -1.0).symmetric_sqrt(), None);
+    }
+
+    #[test]
+    fn test_solve() {
+        let a = [[2.0, 1.0, 0.0], [1.0, 3.0, 1.0], [0.0, 1.0, 4.0]];
+        let x = solve(a, [3.0, 5.0, 5.0]).unwrap();
+        assert_close(Vector3::new(x[0], x[1], x[2]), Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(solve([[1.0, 2.0], [2.0, 4.0]], [1.0, 2.0]), None);
+
+        // y = 2x + 1
+        let rows = (0..5).map(|i| ([i as f64, 1.0], 2.0 * i as f64 + 1.0));
+        let x = least_squares(rows).unwrap();
+        assert!((x[0] - 2.0).abs() < 1e-9 && (x[1] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unwrap_angle() {
+        assert!((unwrap_angle(3.0, -3.0) - (TAU - 3.0)).abs() < 1e-12);
+        assert!((unwrap_angle(-3.0, 3.0) - (3.0 - TAU)).abs() < 1e-12);
+        assert!((unwrap_angle(10.0, 0.5) - (0.5 + 2.0 * TAU)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let times = [0.0, 1.0, 3.0];
+        let values = [0.0, 10.0, 30.0];
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        assert_eq!(interpolate(&times, &values, 2.0, lerp), Some(20.0));
+        assert_eq!(interpolate(&times, &values, 0.0, lerp), Some(0.0));
+        assert_eq!(interpolate(&times, &values, 3.0, lerp), Some(30.0));
+        assert_eq!(interpolate(&times, &values, -0.5, lerp), None);
+        assert_eq!(interpolate(&times, &values, 3.5, lerp), None);
+        assert_eq!(interpolate_clamped(&times, &values, -0.5, lerp), Some(0.0));
+        assert_eq!(interpolate_clamped(&times, &values, 3.5, lerp), Some(30.0));
+        assert_eq!(interpolate_clamped(&[], &[], 1.0, lerp), None);
+    }
+}
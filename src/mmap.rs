@@ -0,0 +1,263 @@
+use std::{fs::File, path::Path};
+
+use memmap2::Mmap;
+
+use crate::{
+    theta::{
+        rdl2::Rdl2Box, rdt::Entry, rdt::TableView, rdta::RdtaBox, rdtb::RdtbBox, rdtc::RdtcBox,
+        rdtg::RdtgBox, rdtl::RdtlBox, ThetaMeta, VideoInfo,
+    },
+    Error,
+};
+
+// Decoded from table views over the map rather than by `crate::match_box`.
+static TABLE_BOXES: &[&str] = &["RDTA", "RDTB", "RDTC", "RDTG", "RDTL", "RDL2"];
+
+/// A memory-mapped MP4 whose udta payloads are handed out as borrowed slices.
+pub struct MappedMp4 {
+    mmap: Mmap,
+}
+
+impl MappedMp4 {
+    pub fn open<P: AsRef<Path>>(filename: &P) -> std::io::Result<MappedMp4> {
+        let file = File::open(filename)?;
+        // SAFETY: the mapping is read-only; the file must not be truncated while it is mapped.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MappedMp4 { mmap })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// Child boxes of `moov/udta` as `(name, payload)` pairs, in file order.
+    pub fn udta_children(&self) -> Vec<(String, &[u8])> {
+        udta_children(&self.mmap)
+    }
+
+    pub fn udta_payload(&self, name: &str) -> Option<&[u8]> {
+        self.udta_children()
+            .into_iter()
+            .find(|(child, _)| child == name)
+            .map(|(_, data)| data)
+    }
+
+    /// Borrowed table view over a udta box, e.g. `view::<rdta::DataEntry>("RDTA")`.
+    pub fn view<T: Entry>(&self, name: &str) -> Option<TableView<'_, T>> {
+        TableView::new(self.udta_payload(name)?)
+    }
+
+    /// Reads the THETA metadata like [`crate::parse_reader`], decoding the sensor
+    /// tables straight from the mapped payloads. A table with an unknown endian
+    /// marker is left out.
+    pub fn theta_meta(&self, target_boxes: Option<&[String]>) -> Option<ThetaMeta> {
+        let children = self.udta_children();
+        let mut theta_meta = crate::read_theta_meta(
+            children
+                .iter()
+                .filter(|(name, _)| !TABLE_BOXES.contains(&name.as_str()))
+                .map(|(name, data)| (name.as_str(), *data)),
+            target_boxes,
+        )?;
+        for (name, data) in children {
+            if !crate::is_target(&name, target_boxes) {
+                continue;
+            }
+            match name.as_str() {
+                "RDTA" => theta_meta.rdta = RdtaBox::view(data).map(RdtaBox::from_view),
+                "RDTB" => theta_meta.rdtb = RdtbBox::view(data).map(RdtbBox::from_view),
+                "RDTC" => theta_meta.rdtc = RdtcBox::view(data).map(RdtcBox::from_view),
+                "RDTG" => theta_meta.rdtg = RdtgBox::view(data).map(RdtgBox::from_view),
+                "RDTL" => theta_meta.rdtl = RdtlBox::view(data).map(RdtlBox::from_view),
+                "RDL2" => theta_meta.rdl2 = Rdl2Box::view(data).map(Rdl2Box::from_view),
+                _ => {}
+            }
+        }
+        theta_meta.video = video_info(&self.mmap);
+        Some(theta_meta)
+    }
+}
+
+/// Memory-mapped counterpart of [`crate::parse_reader`].
+pub fn parse_mmap<P: AsRef<Path>>(
+    filename: &P,
+    target_boxes: Option<&[String]>,
+) -> Result<Option<ThetaMeta>, Error> {
+    let mapped = MappedMp4::open(filename)?;
+    if find_box(mapped.as_bytes(), b"moov").is_none() {
+        return Err(Error::InvalidMp4("moov box not found".to_string()));
+    }
+    Ok(mapped.theta_meta(target_boxes))
+}
+
+/// Movie timing from `mvhd` and the frames of the first `vide` track, or `None`
+/// without a video track.
+fn video_info(data: &[u8]) -> Option<VideoInfo> {
+    let moov = find_box(data, b"moov")?;
+    let (creation_time, timescale, duration) = header_times(find_box(moov, b"mvhd")?)?;
+    let mdia = Boxes(moov)
+        .filter(|(name, _)| name == b"trak")
+        .filter_map(|(_, trak)| find_box(trak, b"mdia"))
+        .find(|mdia| find_box(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)) == Some(b"vide"))?;
+    let (_, track_timescale, track_duration) = header_times(find_box(mdia, b"mdhd")?)?;
+    let stsz = [b"minf", b"stbl", b"stsz"]
+        .iter()
+        .try_fold(mdia, |parent, name| find_box(parent, name))?;
+    let frame_count = u32::from_be_bytes(stsz.get(8..12)?.try_into().unwrap());
+    let frame_rate = if track_duration > 0 {
+        frame_count as f64 * track_timescale as f64 / track_duration as f64
+    } else {
+        0.0
+    };
+    Some(VideoInfo {
+        creation_time,
+        timescale,
+        duration,
+        frame_rate,
+        frame_count,
+    })
+}
+
+/// Creation time, timescale and duration of an `mvhd` or `mdhd` payload.
+fn header_times(data: &[u8]) -> Option<(u64, u32, u64)> {
+    let u32_at = |i: usize| Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().unwrap()));
+    let u64_at = |i: usize| Some(u64::from_be_bytes(data.get(i..i + 8)?.try_into().unwrap()));
+    match *data.first()? {
+        0 => Some((u32_at(4)? as u64, u32_at(12)?, u32_at(16)? as u64)),
+        1 => Some((u64_at(4)?, u32_at(20)?, u64_at(24)?)),
+        _ => None,
+    }
+}
+
+fn udta_children(data: &[u8]) -> Vec<(String, &[u8])> {
+    let udta = find_box(data, b"moov").and_then(|moov| find_box(moov, b"udta"));
+    Boxes(udta.unwrap_or_default())
+        .map(|(name, payload)| (box_name(name), payload))
+        .collect()
+}
+
+fn find_box<'a>(data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    Boxes(data)
+        .find(|(child, _)| child == name)
+        .map(|(_, payload)| payload)
+}
+
+// QuickTime `©xxx` boxes are reported with an `@` prefix, as in `@mod`.
+fn box_name(name: [u8; 4]) -> String {
+    name.iter()
+        .map(|&b| if b == 0xa9 { '@' } else { b as char })
+        .collect()
+}
+
+struct Boxes<'a>(&'a [u8]);
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.0;
+        if data.len() < 8 {
+            return None;
+        }
+        let name = [data[4], data[5], data[6], data[7]];
+        let (header_size, box_size) = match u32::from_be_bytes(data[0..4].try_into().unwrap()) {
+            0 => (8, data.len()),
+            1 if data.len() >= 16 => (
+                16,
+                u64::from_be_bytes(data[8..16].try_into().unwrap()) as usize,
+            ),
+            n => (8, n as usize),
+        };
+        if box_size < header_size || box_size > data.len() {
+            self.0 = &[];
+            return None;
+        }
+        self.0 = &data[box_size..];
+        Some((name, &data[header_size..box_size]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Vec<u8> {
+        vec![
+            0x00, 0x00, 0x00, 0x0c, b'f', b't', b'y', b'p', // ftyp (12 bytes)
+            b'i', b's', b'o', b'm', // major_brand
+            0x00, 0x00, 0x00, 0x3d, b'm', b'o', b'o', b'v', // moov (61 bytes)
+            0x00, 0x00, 0x00, 0x0c, b'm', b'v', b'h', b'd', // mvhd (12 bytes)
+            0x00, 0x00, 0x00, 0x00, // payload
+            0x00, 0x00, 0x00, 0x29, b'u', b'd', b't', b'a', // udta (41 bytes)
+            0x00, 0x00, 0x00, 0x15, b'm', b'o', b'd', b'l', // modl (21 bytes)
+            b'R', b'I', b'C', b'O', b'H', b' ', b'T', b'H', b'E', b'T', b'A', b' ',
+            b'X', // payload
+            0x00, 0x00, 0x00, 0x0c, 0xa9, b'm', b'a', b'k', // @mak (12 bytes)
+            b'R', b'I', b'C', b'O', // payload
+        ]
+    }
+
+    #[test]
+    fn test_udta_children() {
+        let data = setup();
+        let children = udta_children(&data);
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].0, "modl");
+        assert_eq!(children[0].1, b"RICOH THETA X");
+        assert_eq!(children[1].0, "@mak");
+        assert_eq!(children[1].1, b"RICO");
+    }
+
+    #[test]
+    fn test_udta_children_truncated() {
+        let data = setup();
+        assert!(udta_children(&data[..40]).is_empty());
+    }
+
+    fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(name);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn trak(handler: &[u8; 4]) -> Vec<u8> {
+        let mut hdlr = vec![0u8; 24];
+        hdlr[8..12].copy_from_slice(handler);
+        // Version 0 mdhd: 60 frames over 60060 ticks of 1/30000 s.
+        let mut mdhd = vec![0u8; 24];
+        mdhd[12..16].copy_from_slice(&30000u32.to_be_bytes());
+        mdhd[16..20].copy_from_slice(&60060u32.to_be_bytes());
+        let mut stsz = vec![0u8; 12];
+        stsz[8..12].copy_from_slice(&60u32.to_be_bytes());
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsz", &stsz));
+        let mdia = [
+            mp4_box(b"mdhd", &mdhd),
+            mp4_box(b"hdlr", &hdlr),
+            mp4_box(b"minf", &stbl),
+        ];
+        mp4_box(b"trak", &mp4_box(b"mdia", &mdia.concat()))
+    }
+
+    #[test]
+    fn test_video_info() {
+        // Version 1 mvhd: 64-bit creation and modification time and duration.
+        let mut mvhd = vec![0u8; 112];
+        mvhd[0] = 1;
+        mvhd[4..12].copy_from_slice(&3_800_000_000u64.to_be_bytes());
+        mvhd[20..24].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[24..32].copy_from_slice(&2002u64.to_be_bytes());
+        let moov = [mp4_box(b"mvhd", &mvhd), trak(b"soun"), trak(b"vide")].concat();
+        let data = mp4_box(b"moov", &moov);
+
+        let video = video_info(&data).unwrap();
+        assert_eq!(video.creation_time, 3_800_000_000);
+        assert_eq!(video.timescale, 1000);
+        assert_eq!(video.duration, 2002);
+        assert_eq!(video.frame_count, 60);
+        assert!((video.frame_rate - 30000.0 / 1001.0).abs() < 1e-9);
+
+        let moov = [mp4_box(b"mvhd", &mvhd), trak(b"soun")].concat();
+        assert!(video_info(&mp4_box(b"moov", &moov)).is_none());
+    }
+}
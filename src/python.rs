@@ -0,0 +1,272 @@
+//! Python bindings, built as the `theta_mp4` extension module.
+//!
+//! Sensor tables are returned as NumPy structured arrays created with
+//! `numpy.frombuffer` over the raw udta payload, so entries are never decoded in Rust.
+//! The payload is moved out of the parsed MP4 into a [`Payload`], which the array
+//! keeps alive as its `base` without copying it.
+
+use std::ffi::c_int;
+
+use pyo3::{
+    exceptions::{PyBufferError, PyIOError},
+    ffi,
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::theta::{rdl2, rdt::Entry, rdt::TableView, rdta, rdtg, rdtl};
+
+const TABLE_BOXES: &[&str] = &["RDTA", "RDTB", "RDTC", "RDTG", "RDTL", "RDL2"];
+
+fn dtype_fields(name: &str) -> &'static [(&'static str, &'static str)] {
+    match name {
+        "RDTA" | "RDTB" | "RDTC" => &[
+            ("x", "f4"),
+            ("y", "f4"),
+            ("z", "f4"),
+            ("reserve", "f4"),
+            ("timestamp", "u8"),
+        ],
+        "RDTG" => &[("timestamp", "u8")],
+        "RDTL" => &[
+            ("timestamp", "f8"),
+            ("latitude", "f8"),
+            ("longitude", "f8"),
+            ("altitude", "f8"),
+        ],
+        "RDL2" => &[
+            ("timestamp", "f8"),
+            ("gps_fix_type", "i2"),
+            ("latitude", "f8"),
+            ("longitude", "f8"),
+            ("altitude", "f4"),
+            ("horizontal_accuracy", "f4"),
+            ("vertical_accuracy", "f4"),
+            ("velocity_east", "f4"),
+            ("velocity_north", "f4"),
+            ("velocity_up", "f4"),
+            ("speed_accuracy", "f4"),
+        ],
+        _ => &[],
+    }
+}
+
+/// Read-only buffer over a udta payload, exported through the buffer protocol.
+#[pyclass(frozen)]
+struct Payload {
+    data: Vec<u8>,
+}
+
+#[pymethods]
+impl Payload {
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let data = &slf.get().data;
+        // Fills a one-dimensional byte view and takes a reference to `slf`,
+        // released with the view.
+        let filled = ffi::PyBuffer_FillInfo(
+            view,
+            slf.as_ptr(),
+            data.as_ptr() as *mut _,
+            data.len() as ffi::Py_ssize_t,
+            1,
+            flags,
+        );
+        if filled == 0 {
+            Ok(())
+        } else {
+            Err(PyErr::take(slf.py())
+                .unwrap_or_else(|| PyBufferError::new_err("cannot export the payload")))
+        }
+    }
+}
+
+/// Moves the payload of a udta child box out of `mp4`.
+fn take_payload<R>(mp4: &mut mp4::Mp4Reader<R>, name: &str) -> Option<Vec<u8>> {
+    mp4.moov
+        .udta
+        .as_mut()?
+        .children
+        .iter_mut()
+        .find(|child| child.name == name)
+        .map(|child| std::mem::take(&mut child.data))
+}
+
+// (big_endian, number of complete entries) of a table payload.
+fn table_layout<T: Entry>(data: &[u8]) -> Option<(bool, usize)> {
+    let view = TableView::<T>::new(data)?;
+    let available = (data.len() - 16) / T::SIZE;
+    Some((view.is_big_endian(), view.len().min(available)))
+}
+
+fn table_array<'py>(
+    py: Python<'py>,
+    numpy: &Bound<'py, PyModule>,
+    name: &str,
+    data: Vec<u8>,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let layout = match name {
+        "RDTA" | "RDTB" | "RDTC" => table_layout::<rdta::DataEntry>(&data),
+        "RDTG" => table_layout::<rdtg::DataEntry>(&data),
+        "RDTL" => table_layout::<rdtl::DataEntry>(&data),
+        "RDL2" => table_layout::<rdl2::DataEntry>(&data),
+        _ => None,
+    };
+    let Some((big_endian, count)) = layout else {
+        return Ok(None);
+    };
+    let order = if big_endian { ">" } else { "<" };
+    let fields: Vec<(&str, String)> = dtype_fields(name)
+        .iter()
+        .map(|(field, ty)| (*field, format!("{}{}", order, ty)))
+        .collect();
+    let dtype = numpy.getattr("dtype")?.call1((fields,))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dtype", dtype)?;
+    kwargs.set_item("count", count)?;
+    kwargs.set_item("offset", 16)?;
+    let array = numpy
+        .getattr("frombuffer")?
+        .call((Bound::new(py, Payload { data })?,), Some(&kwargs))?;
+    Ok(Some(array))
+}
+
+/// Returns a dict of THETA metadata, or None if the file has none.
+/// `target` selects the boxes to include, as in the CLI's `--target`.
+#[pyfunction]
+#[pyo3(signature = (filename, target=None))]
+fn parse<'py>(
+    py: Python<'py>,
+    filename: &str,
+    target: Option<Vec<String>>,
+) -> PyResult<Option<Bound<'py, PyDict>>> {
+    let Some((mut mp4, theta_meta)) = crate::parse(&filename, None) else {
+        return Err(PyIOError::new_err("Failed to parse the file"));
+    };
+    let Some(meta) = theta_meta else {
+        return Ok(None);
+    };
+    let wanted = |name: &str| {
+        target
+            .as_ref()
+            .is_some_and(|t| t.iter().any(|s| s == "all" || s == name))
+    };
+
+    let dict = PyDict::new(py);
+    dict.set_item("@mod", &meta._mod)?;
+    dict.set_item("@swr", &meta._swr)?;
+    dict.set_item("@day", &meta._day)?;
+    dict.set_item("@xyz", &meta._xyz)?;
+    dict.set_item("@mak", &meta._mak)?;
+    dict.set_item("manu", &meta.manu)?;
+    dict.set_item("modl", &meta.modl)?;
+
+    if wanted("RTHU") {
+        if let Some(data) = crate::udta_payload(&mp4, "RTHU") {
+            dict.set_item("RTHU", PyBytes::new(py, data))?;
+        }
+    }
+    let tables: Vec<&str> = TABLE_BOXES.iter().copied().filter(|n| wanted(n)).collect();
+    if tables.is_empty() {
+        return Ok(Some(dict));
+    }
+    let numpy = py.import("numpy")?;
+    for name in tables {
+        if let Some(data) = take_payload(&mut mp4, name) {
+            if let Some(array) = table_array(py, &numpy, name, data)? {
+                dict.set_item(name, array)?;
+            }
+        }
+    }
+    Ok(Some(dict))
+}
+
+#[pymodule]
+fn theta_mp4(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "python"))]
+mod tests {
+    use super::*;
+
+    fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(name);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    /// MP4 with a THETA X udta holding a thumbnail and two RDTA samples.
+    fn setup() -> Vec<u8> {
+        let mut rdta = 2u32.to_le_bytes().to_vec();
+        rdta.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x23, 0x01, 0, 0, 0, 0, 0, 0]);
+        for (x, timestamp) in [(0.5f32, 0u64), (1.5, 10_000)] {
+            rdta.extend([x, 0.0, 1.0, 0.0].map(f32::to_le_bytes).concat());
+            rdta.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        let mut mvhd = vec![0u8; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        let udta = [
+            mp4_box(b"modl", b"RICOH THETA X"),
+            mp4_box(b"RTHU", &[0xff, 0xd8, 0xff, 0xd9]),
+            mp4_box(b"RDTA", &rdta),
+        ]
+        .concat();
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(
+            b"moov",
+            &[mp4_box(b"mvhd", &mvhd), mp4_box(b"udta", &udta)].concat(),
+        ));
+        data
+    }
+
+    #[test]
+    fn test_parse() -> PyResult<()> {
+        let path =
+            std::env::temp_dir().join(format!("theta_mp4_python_{}.mp4", std::process::id()));
+        std::fs::write(&path, setup())?;
+        Python::initialize();
+        let result = Python::attach(|py| -> PyResult<()> {
+            let module = PyModule::new(py, "theta_mp4")?;
+            theta_mp4(&module)?;
+            let parse = module.getattr("parse")?;
+            let filename = path.to_str().unwrap();
+
+            let meta = parse.call1((filename, vec!["all"]))?;
+            assert_eq!(meta.get_item("modl")?.extract::<String>()?, "RICOH THETA X");
+            assert_eq!(
+                meta.get_item("RTHU")?.extract::<Vec<u8>>()?,
+                [0xff, 0xd8, 0xff, 0xd9]
+            );
+
+            let rdta = meta.get_item("RDTA")?;
+            let dtype = rdta.getattr("dtype")?;
+            assert_eq!(
+                dtype.getattr("names")?.extract::<Vec<String>>()?,
+                ["x", "y", "z", "reserve", "timestamp"]
+            );
+            assert_eq!(dtype.getattr("itemsize")?.extract::<usize>()?, 24);
+            assert_eq!(rdta.len()?, 2);
+            let x: Vec<f32> = rdta.get_item("x")?.call_method0("tolist")?.extract()?;
+            assert_eq!(x, [0.5, 1.5]);
+            let timestamps: Vec<u64> = rdta
+                .get_item("timestamp")?
+                .call_method0("tolist")?
+                .extract()?;
+            assert_eq!(timestamps, [0, 10_000]);
+            assert!(rdta.getattr("base")?.is_instance_of::<Payload>());
+
+            let meta = parse.call1((filename,))?;
+            assert!(!meta.contains("RDTA")?);
+            Ok(())
+        });
+        std::fs::remove_file(&path)?;
+        result
+    }
+}
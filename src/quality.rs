@@ -0,0 +1,234 @@
+//! Rejection of poor GPS fixes.
+//!
+//! Each RDL2 entry is checked on its own against the fix type, the reported
+//! accuracies and velocity limits. Fixes passing those checks are then compared
+//! with their neighbours: a fix that could only be reached from both sides at an
+//! implausible speed is rejected as a jump.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    geo::GeoPoint,
+    theta::rdl2::{DataEntry, Rdl2Box},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsFilter {
+    /// 2 for a 2D fix, 3 for a 3D fix.
+    pub min_fix_type: i16,
+    /// Metres.
+    pub max_horizontal_accuracy: Option<f32>,
+    pub max_vertical_accuracy: Option<f32>,
+    /// m/s.
+    pub max_speed_accuracy: Option<f32>,
+    /// Limit on the reported horizontal speed, in m/s.
+    pub max_speed: Option<f64>,
+    /// Limit on the reported vertical speed, in m/s.
+    pub max_vertical_speed: Option<f64>,
+    /// Limit on the speed implied by consecutive positions, in m/s.
+    pub max_jump_speed: Option<f64>,
+}
+
+impl Default for GpsFilter {
+    fn default() -> Self {
+        GpsFilter {
+            min_fix_type: 2,
+            max_horizontal_accuracy: None,
+            max_vertical_accuracy: None,
+            max_speed_accuracy: None,
+            max_speed: None,
+            max_vertical_speed: None,
+            max_jump_speed: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    NoFix,
+    /// Non-finite or out-of-range coordinates.
+    InvalidPosition,
+    HorizontalAccuracy,
+    VerticalAccuracy,
+    SpeedAccuracy,
+    Speed,
+    VerticalSpeed,
+    Jump,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct RejectedFix {
+    /// Index in the original RDL2 table.
+    pub index: usize,
+    pub timestamp: f64,
+    pub reason: Rejection,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct FilterReport {
+    pub total: usize,
+    pub accepted: usize,
+    pub counts: BTreeMap<Rejection, usize>,
+    pub rejected: Vec<RejectedFix>,
+}
+
+impl GpsFilter {
+    /// First per-fix check `entry` fails, without looking at its neighbours.
+    pub fn check(&self, entry: &DataEntry) -> Option<Rejection> {
+        let exceeds = |value: f32, limit: Option<f32>| limit.is_some_and(|limit| value > limit);
+        let within = |value: f64, limit: f64| value.is_finite() && value.abs() <= limit;
+        let speed = (entry.velocity_east as f64).hypot(entry.velocity_north as f64);
+        if entry.gps_fix_type < self.min_fix_type {
+            Some(Rejection::NoFix)
+        } else if !within(entry.latitude, 90.0)
+            || !within(entry.longitude, 180.0)
+            || !entry.altitude.is_finite()
+        {
+            Some(Rejection::InvalidPosition)
+        } else if exceeds(entry.horizontal_accuracy, self.max_horizontal_accuracy) {
+            Some(Rejection::HorizontalAccuracy)
+        } else if exceeds(entry.vertical_accuracy, self.max_vertical_accuracy) {
+            Some(Rejection::VerticalAccuracy)
+        } else if exceeds(entry.speed_accuracy, self.max_speed_accuracy) {
+            Some(Rejection::SpeedAccuracy)
+        } else if self.max_speed.is_some_and(|limit| speed > limit) {
+            Some(Rejection::Speed)
+        } else if self
+            .max_vertical_speed
+            .is_some_and(|limit| (entry.velocity_up as f64).abs() > limit)
+        {
+            Some(Rejection::VerticalSpeed)
+        } else {
+            None
+        }
+    }
+
+    /// Rejection reason of every entry, `None` for accepted ones.
+    fn reasons(&self, entries: &[DataEntry]) -> Vec<Option<Rejection>> {
+        let mut reasons: Vec<_> = entries.iter().map(|e| self.check(e)).collect();
+        let Some(limit) = self.max_jump_speed else {
+            return reasons;
+        };
+        let candidates: Vec<usize> = (0..entries.len())
+            .filter(|&i| reasons[i].is_none())
+            .collect();
+        let point = |i: usize| {
+            let e = &entries[i];
+            GeoPoint::new(e.latitude, e.longitude, e.altitude as f64)
+        };
+        let far = |i: usize, j: usize| {
+            let distance = point(i).distance_to(&point(j));
+            let dt = (entries[j].timestamp - entries[i].timestamp).abs();
+            distance > limit * dt
+        };
+        let mut prev: Option<usize> = None;
+        for (k, &i) in candidates.iter().enumerate() {
+            let (next, after) = (candidates.get(k + 1), candidates.get(k + 2));
+            let jump = match (prev, next) {
+                (Some(p), Some(&n)) => far(p, i) && far(i, n),
+                (Some(p), None) => far(p, i),
+                // Without a history, the two following fixes have to agree on it.
+                (None, Some(&n)) => far(i, n) && after.is_none_or(|&a| far(i, a)),
+                (None, None) => false,
+            };
+            if jump {
+                reasons[i] = Some(Rejection::Jump);
+            } else {
+                prev = Some(i);
+            }
+        }
+        reasons
+    }
+
+    /// RDL2 without the rejected fixes, and what was rejected.
+    pub fn apply(&self, rdl2: &Rdl2Box) -> (Rdl2Box, FilterReport) {
+        let entries = rdl2.entries();
+        let reasons = self.reasons(entries);
+        let mut report = FilterReport {
+            total: entries.len(),
+            ..Default::default()
+        };
+        for (index, (entry, reason)) in entries.iter().zip(&reasons).enumerate() {
+            if let Some(reason) = *reason {
+                *report.counts.entry(reason).or_default() += 1;
+                report.rejected.push(RejectedFix {
+                    index,
+                    timestamp: entry.timestamp,
+                    reason,
+                });
+            }
+        }
+        report.accepted = report.total - report.rejected.len();
+        let mut reasons = reasons.iter();
+        let filtered = rdl2.retain_entries(|_| reasons.next().is_some_and(Option::is_none));
+        (filtered, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    fn setup() -> Rdl2Box {
+        let mut fixes: Vec<_> = (0..10)
+            .map(|i| Fix::new(100.0 + i as f64, 35.0 + i as f64 * 1e-5, 139.0))
+            .collect();
+        fixes[2].gps_fix_type = 1;
+        fixes[4].horizontal_accuracy = 50.0;
+        fixes[6].latitude = 35.1;
+        fixes[8].velocity = [60.0, 0.0, 0.0];
+        testing::rdl2(&fixes)
+    }
+
+    #[test]
+    fn test_apply() {
+        let rdl2 = setup();
+        let filter = GpsFilter {
+            max_horizontal_accuracy: Some(10.0),
+            max_speed: Some(50.0),
+            max_jump_speed: Some(100.0),
+            ..Default::default()
+        };
+        let (filtered, report) = filter.apply(&rdl2);
+        assert_eq!(report.total, 10);
+        assert_eq!(report.accepted, 6);
+        assert_eq!(filtered.entries().len(), 6);
+        assert_eq!(filtered.header().number_of_entries, 6);
+        let reasons: Vec<_> = report
+            .rejected
+            .iter()
+            .map(|r| (r.index, r.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (2, Rejection::NoFix),
+                (4, Rejection::HorizontalAccuracy),
+                (6, Rejection::Jump),
+                (8, Rejection::Speed),
+            ]
+        );
+        assert_eq!(report.counts[&Rejection::Jump], 1);
+        assert!(filtered.entries().iter().all(|e| e.latitude < 35.01));
+    }
+
+    #[test]
+    fn test_jump_at_start() {
+        let mut fixes: Vec<_> = (0..4)
+            .map(|i| Fix::new(100.0 + i as f64, 35.0, 139.0))
+            .collect();
+        fixes[0].longitude = 140.0;
+        let filter = GpsFilter {
+            max_jump_speed: Some(100.0),
+            ..Default::default()
+        };
+        let (filtered, report) = filter.apply(&testing::rdl2(&fixes));
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].index, 0);
+        assert_eq!(filtered.entries()[0].timestamp, 101.0);
+    }
+}
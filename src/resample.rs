@@ -0,0 +1,354 @@
+//! Resampling of sensor tables onto a uniform clock or the video frames.
+//!
+//! The `sampling_rate` in each table header is nominal and the real timestamps
+//! jitter, so samples are interpolated at the requested times. Targets that fall
+//! inside a gap of the recording are left empty rather than bridged.
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+    frame::positions, fusion::vectors, geo::GeoPoint, math::Vector3, theta::ThetaMeta,
+    timeline::Timeline,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    Nearest,
+    #[default]
+    Linear,
+    /// Cubic Hermite with tangents from the neighbouring samples.
+    Cubic,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Interpolation::Nearest),
+            "linear" => Ok(Interpolation::Linear),
+            "cubic" => Ok(Interpolation::Cubic),
+            _ => Err(format!("unknown interpolation: {}", s)),
+        }
+    }
+}
+
+/// Fastest rate accepted for [`TargetRate::Hz`], well above the sensor rates.
+pub const MAX_RATE: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetRate {
+    /// Uniform rate over the span of the samples.
+    Hz(f64),
+    /// The presentation time of every video frame.
+    VideoFrames,
+}
+
+impl FromStr for TargetRate {
+    type Err = String;
+
+    /// `video`, or a rate in Hz up to [`MAX_RATE`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "video" => Ok(TargetRate::VideoFrames),
+            _ => match s.parse::<f64>() {
+                Ok(hz) if hz > 0.0 && hz <= MAX_RATE => Ok(TargetRate::Hz(hz)),
+                _ => Err(format!(
+                    "expected `video` or a rate above 0 and up to {} Hz: {}",
+                    MAX_RATE, s
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ResampleConfig {
+    pub interpolation: Interpolation,
+    /// Longest interval (seconds) between samples that is interpolated across.
+    /// Defaults to three times the median interval.
+    pub max_gap: Option<f64>,
+}
+
+/// Values that can be interpolated as weighted sums of samples.
+pub trait Resample: Copy {
+    /// `Σ weight * value`, with weights summing to one.
+    fn weighted_sum(terms: &[(f64, Self)]) -> Self;
+}
+
+impl Resample for f64 {
+    fn weighted_sum(terms: &[(f64, f64)]) -> f64 {
+        terms.iter().map(|&(w, v)| w * v).sum()
+    }
+}
+
+impl Resample for Vector3 {
+    fn weighted_sum(terms: &[(f64, Vector3)]) -> Vector3 {
+        terms
+            .iter()
+            .fold(Vector3::default(), |sum, &(w, v)| sum + v * w)
+    }
+}
+
+impl Resample for GeoPoint {
+    /// Longitudes are unwrapped around the first term to cross the antimeridian.
+    fn weighted_sum(terms: &[(f64, GeoPoint)]) -> GeoPoint {
+        let origin = terms.first().map_or(0.0, |(_, p)| p.longitude);
+        let unwrap = |lon: f64| origin + (lon - origin + 180.0).rem_euclid(360.0) - 180.0;
+        let sum = |f: &dyn Fn(&GeoPoint) -> f64| terms.iter().map(|(w, p)| w * f(p)).sum::<f64>();
+        let longitude = sum(&|p| unwrap(p.longitude));
+        GeoPoint::new(
+            sum(&|p| p.latitude),
+            (longitude + 180.0).rem_euclid(360.0) - 180.0,
+            sum(&|p| p.altitude),
+        )
+    }
+}
+
+/// Resampled series; `values[i]` is `None` where `times[i]` is outside the
+/// samples or inside a gap.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Resampled<T> {
+    pub times: Vec<f64>,
+    pub values: Vec<Option<T>>,
+}
+
+/// `start, start + 1/rate, ...` up to and including `end`.
+pub fn uniform_times(start: f64, end: f64, rate: f64) -> Vec<f64> {
+    if rate.is_nan() || rate <= 0.0 || end < start {
+        return Vec::new();
+    }
+    let count = ((end - start) * rate + 1e-9).floor() as usize + 1;
+    (0..count).map(|i| start + i as f64 / rate).collect()
+}
+
+fn median_interval(times: &[f64]) -> Option<f64> {
+    let mut deltas: Vec<_> = times.windows(2).map(|w| w[1] - w[0]).collect();
+    deltas.sort_by(f64::total_cmp);
+    deltas.get(deltas.len() / 2).copied()
+}
+
+/// Interpolates time-ordered `(times, values)` at each of `targets`.
+pub fn resample<T: Resample>(
+    times: &[f64],
+    values: &[T],
+    targets: &[f64],
+    config: ResampleConfig,
+) -> Vec<Option<T>> {
+    let max_gap = config
+        .max_gap
+        .or_else(|| median_interval(times).map(|m| m * 3.0))
+        .unwrap_or(f64::INFINITY);
+    targets
+        .iter()
+        .map(|&t| {
+            let next = times.partition_point(|&s| s < t);
+            if next < times.len() && times[next] == t {
+                return Some(values[next]);
+            }
+            let prev = next.checked_sub(1)?;
+            if next == times.len() || times[next] - times[prev] > max_gap {
+                return None;
+            }
+            let (t1, t2) = (times[prev], times[next]);
+            let u = (t - t1) / (t2 - t1);
+            Some(match config.interpolation {
+                Interpolation::Nearest if u < 0.5 => values[prev],
+                Interpolation::Nearest => values[next],
+                Interpolation::Linear => {
+                    T::weighted_sum(&[(1.0 - u, values[prev]), (u, values[next])])
+                }
+                Interpolation::Cubic => {
+                    // Neighbours across a gap do not shape the curve.
+                    let before = prev
+                        .checked_sub(1)
+                        .filter(|&i| t1 - times[i] <= max_gap)
+                        .unwrap_or(prev);
+                    let after = Some(next + 1)
+                        .filter(|&i| i < times.len() && times[i] - t2 <= max_gap)
+                        .unwrap_or(next);
+                    let (t0, t3) = (times[before], times[after]);
+                    let (h00, h10) = (
+                        2.0 * u.powi(3) - 3.0 * u * u + 1.0,
+                        u.powi(3) - 2.0 * u * u + u,
+                    );
+                    let (h01, h11) = (-2.0 * u.powi(3) + 3.0 * u * u, u.powi(3) - u * u);
+                    let dt = t2 - t1;
+                    let k1 = h10 * dt / (t2 - t0);
+                    let k2 = h11 * dt / (t3 - t1);
+                    T::weighted_sum(&[
+                        (-k1, values[before]),
+                        (h00 - k2, values[prev]),
+                        (h01 + k1, values[next]),
+                        (k2, values[after]),
+                    ])
+                }
+            })
+        })
+        .collect()
+}
+
+/// Resamples the tables of one recording.
+pub struct Resampler<'a> {
+    meta: &'a ThetaMeta,
+    timeline: &'a Timeline,
+    config: ResampleConfig,
+}
+
+macro_rules! imu_resampler {
+    ($name:ident, $field:ident) => {
+        pub fn $name(&self, rate: TargetRate) -> Option<Resampled<Vector3>> {
+            let entries = self.meta.$field.as_ref()?.entries();
+            let (times, values) = vectors(
+                entries.iter().map(|e| (e.x, e.y, e.z, e.timestamp)),
+                self.timeline,
+            );
+            Some(self.resample(&times, &values, rate))
+        }
+    };
+}
+
+impl<'a> Resampler<'a> {
+    pub fn new(meta: &'a ThetaMeta, timeline: &'a Timeline, config: ResampleConfig) -> Self {
+        Resampler {
+            meta,
+            timeline,
+            config,
+        }
+    }
+
+    /// Target times for samples spanning `times`.
+    pub fn target_times(&self, times: &[f64], rate: TargetRate) -> Vec<f64> {
+        match (rate, times.first(), times.last()) {
+            (TargetRate::VideoFrames, _, _) => self.timeline.frame_times().to_vec(),
+            (TargetRate::Hz(hz), Some(&start), Some(&end)) => uniform_times(start, end, hz),
+            (TargetRate::Hz(_), _, _) => Vec::new(),
+        }
+    }
+
+    fn resample<T: Resample>(&self, times: &[f64], values: &[T], rate: TargetRate) -> Resampled<T> {
+        let targets = self.target_times(times, rate);
+        Resampled {
+            values: resample(times, values, &targets, self.config),
+            times: targets,
+        }
+    }
+
+    imu_resampler!(accelerometer, rdta);
+    imu_resampler!(gyroscope, rdtb);
+    imu_resampler!(magnetometer, rdtc);
+
+    /// GPS positions from RDL2, or RDTL when RDL2 is absent.
+    pub fn position(&self, rate: TargetRate) -> Option<Resampled<GeoPoint>> {
+        let (times, values): (Vec<_>, Vec<_>) =
+            positions(self.meta, self.timeline).into_iter().unzip();
+        (!times.is_empty()).then(|| self.resample(&times, &values, rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    fn config(interpolation: Interpolation) -> ResampleConfig {
+        ResampleConfig {
+            interpolation,
+            max_gap: None,
+        }
+    }
+
+    #[test]
+    fn test_resample() {
+        let times = [0.0, 1.0, 2.0, 3.0, 10.0, 11.0];
+        let values = [0.0, 1.0, 4.0, 9.0, 100.0, 121.0];
+        let targets = [-1.0, 0.5, 1.5, 2.0, 5.0, 10.4, 12.0];
+
+        let linear = resample(&times, &values, &targets, config(Interpolation::Linear));
+        assert_eq!(
+            linear,
+            vec![
+                None,
+                Some(0.5),
+                Some(2.5),
+                Some(4.0),
+                None,
+                Some(108.4),
+                None
+            ]
+        );
+
+        let nearest = resample(&times, &values, &targets, config(Interpolation::Nearest));
+        assert_eq!(nearest[1..4], [Some(1.0), Some(4.0), Some(4.0)]);
+
+        // Cubic reproduces the parabola between interior samples.
+        let cubic = resample(&times, &values, &[1.5], config(Interpolation::Cubic));
+        assert!((cubic[0].unwrap() - 2.25).abs() < 1e-9);
+
+        let bridged = ResampleConfig {
+            max_gap: Some(10.0),
+            ..config(Interpolation::Linear)
+        };
+        let value = resample(&times, &values, &[5.0], bridged)[0].unwrap();
+        assert!((value - 35.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("cubic".parse(), Ok(Interpolation::Cubic));
+        assert!("spline".parse::<Interpolation>().is_err());
+        assert_eq!("video".parse(), Ok(TargetRate::VideoFrames));
+        assert_eq!("200".parse(), Ok(TargetRate::Hz(200.0)));
+        assert!("-1".parse::<TargetRate>().is_err());
+        assert_eq!("1000".parse(), Ok(TargetRate::Hz(1000.0)));
+        assert!("1e9".parse::<TargetRate>().is_err());
+        assert!("inf".parse::<TargetRate>().is_err());
+        assert!("NaN".parse::<TargetRate>().is_err());
+    }
+
+    #[test]
+    fn test_uniform_times() {
+        assert_eq!(
+            uniform_times(1.0, 2.0, 4.0),
+            vec![1.0, 1.25, 1.5, 1.75, 2.0]
+        );
+        assert_eq!(uniform_times(1.0, 0.0, 4.0), Vec::<f64>::new());
+        assert_eq!(uniform_times(0.0, 1.0, 0.0), Vec::<f64>::new());
+    }
+
+    #[test]
+    fn test_resampler() {
+        let meta = ThetaMeta {
+            rdtg: Some(testing::rdtg(&[0, 500_000, 1_000_000])),
+            rdta: Some(testing::rdta(&[
+                (0.0, 0.0, 0.0, 0),
+                (1.0, 0.0, 0.0, 400_000),
+                (2.0, 0.0, 0.0, 1_000_000),
+            ])),
+            rdl2: Some(testing::rdl2(&[
+                Fix::new(100.0, 35.0, 179.9995),
+                Fix::new(101.0, 35.0, -179.9995),
+            ])),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 2.0, 3)),
+            ..Default::default()
+        };
+        let timeline = Timeline::new(&meta);
+        let resampler = Resampler::new(&meta, &timeline, Default::default());
+
+        let accel = resampler.accelerometer(TargetRate::Hz(5.0)).unwrap();
+        assert_eq!(accel.times.len(), 6);
+        let x: Vec<_> = accel.values.iter().map(|v| v.unwrap().x).collect();
+        assert!((x[1] - 0.5).abs() < 1e-9 && (x[3] - 1.0 - 1.0 / 3.0).abs() < 1e-9);
+
+        let frames = resampler.accelerometer(TargetRate::VideoFrames).unwrap();
+        assert_eq!(frames.times, vec![0.0, 0.5, 1.0]);
+        assert!(resampler.gyroscope(TargetRate::VideoFrames).is_none());
+
+        let position = resampler.position(TargetRate::Hz(2.0)).unwrap();
+        let midpoint = position.values[1].unwrap();
+        assert!((midpoint.longitude.abs() - 180.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,394 @@
+//! Motion segmentation of a recording from IMU energy and GPS speed.
+//!
+//! The recording is cut into windows, each classified from the spread of the
+//! acceleration magnitude (RDTA), the spread of the angular rate (RDTB) and the
+//! RDL2 speed at its centre. Equal neighbours are merged, and segments shorter
+//! than the minimum duration are absorbed into their longer neighbour.
+//!
+//! Without RDL2, walking is told from handheld shaking by the accelerometer
+//! alone, and driving is not detected.
+
+use serde::Serialize;
+
+use crate::{
+    math::{interpolate, Vector3},
+    theta::ThetaMeta,
+    timeline::Timeline,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Motion {
+    Stationary,
+    Walking,
+    Driving,
+    /// Moved around in the hand without travelling, e.g. while starting or
+    /// stopping the recording.
+    Handheld,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentationConfig {
+    /// Window length in seconds.
+    pub window: f64,
+    /// Largest standard deviation of the acceleration magnitude (g) at rest.
+    pub stationary_accel: f64,
+    /// Largest standard deviation of the angular rate (rad/s) at rest.
+    pub stationary_gyro: f64,
+    /// Smallest standard deviation of the acceleration magnitude (g) of a gait,
+    /// used without GPS speed.
+    pub walking_accel: f64,
+    /// Slowest speed counted as travelling, in m/s.
+    pub walking_speed: f64,
+    /// Slowest speed counted as driving, in m/s.
+    pub driving_speed: f64,
+    /// Shortest segment kept, in seconds.
+    pub min_duration: f64,
+}
+
+impl Default for SegmentationConfig {
+    fn default() -> Self {
+        SegmentationConfig {
+            window: 1.0,
+            stationary_accel: 0.02,
+            stationary_gyro: 0.05,
+            walking_accel: 0.12,
+            walking_speed: 0.5,
+            driving_speed: 4.0,
+            min_duration: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Segment {
+    pub motion: Motion,
+    /// Seconds from video start.
+    pub start: f64,
+    pub end: f64,
+    /// First and last video frame within the segment.
+    pub start_frame: Option<usize>,
+    pub end_frame: Option<usize>,
+}
+
+impl Segment {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// Time range to keep when trimming a recording.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct TrimRange {
+    /// Seconds from video start.
+    pub start: f64,
+    pub end: f64,
+    pub start_frame: Option<usize>,
+    pub end_frame: Option<usize>,
+}
+
+fn std_dev(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let n = values.clone().count() as f64;
+    let mean = values.clone().sum::<f64>() / n;
+    (values.map(|v| (v - mean) * (v - mean)).sum::<f64>() / n).sqrt()
+}
+
+fn classify(
+    accel_spread: f64,
+    gyro_spread: f64,
+    speed: Option<f64>,
+    config: &SegmentationConfig,
+) -> Motion {
+    let travelling = speed.map(|speed| speed >= config.walking_speed);
+    if speed.is_some_and(|speed| speed >= config.driving_speed) {
+        Motion::Driving
+    } else if accel_spread <= config.stationary_accel && gyro_spread <= config.stationary_gyro {
+        match travelling {
+            // Smooth travel, e.g. on a gimbal or a bicycle.
+            Some(true) => Motion::Walking,
+            _ => Motion::Stationary,
+        }
+    } else {
+        match travelling {
+            Some(true) => Motion::Walking,
+            Some(false) => Motion::Handheld,
+            None if accel_spread >= config.walking_accel => Motion::Walking,
+            None => Motion::Handheld,
+        }
+    }
+}
+
+/// Merges equal neighbours, then absorbs short segments, shortest first.
+fn merge(mut segments: Vec<Segment>, min_duration: f64) -> Vec<Segment> {
+    loop {
+        segments.dedup_by(|next, prev| {
+            let equal = next.motion == prev.motion;
+            if equal {
+                prev.end = next.end;
+            }
+            equal
+        });
+        let shortest = segments
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.duration() < min_duration)
+            .min_by(|(_, a), (_, b)| a.duration().total_cmp(&b.duration()))
+            .map(|(i, _)| i);
+        let Some(i) = shortest.filter(|_| segments.len() > 1) else {
+            return segments;
+        };
+        let short = segments.remove(i);
+        let previous = i.checked_sub(1);
+        let next = (i < segments.len()).then_some(i);
+        match (previous, next) {
+            (Some(p), Some(n)) if segments[p].duration() >= segments[n].duration() => {
+                segments[p].end = short.end;
+            }
+            (_, Some(n)) => segments[n].start = short.start,
+            (Some(p), None) => segments[p].end = short.end,
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Segments covering the RDTA/RDTB recording, or `None` without either.
+pub fn segment_motion(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: SegmentationConfig,
+) -> Option<Vec<Segment>> {
+    let (rdta, rdtb) = (meta.rdta.as_ref()?, meta.rdtb.as_ref()?);
+    let accel: Vec<_> = rdta
+        .entries()
+        .iter()
+        .map(|e| {
+            (
+                timeline.tick_to_seconds(e.timestamp),
+                Vector3::from_f32(e.x, e.y, e.z),
+            )
+        })
+        .collect();
+    let gyro: Vec<_> = rdtb
+        .entries()
+        .iter()
+        .map(|e| {
+            (
+                timeline.tick_to_seconds(e.timestamp),
+                Vector3::from_f32(e.x, e.y, e.z),
+            )
+        })
+        .collect();
+    let (speed_times, speeds): (Vec<f64>, Vec<f64>) = meta
+        .rdl2
+        .iter()
+        .flat_map(|rdl2| rdl2.fixes())
+        .filter_map(|e| {
+            let speed = (e.velocity_east as f64).hypot(e.velocity_north as f64);
+            Some((timeline.utc_to_seconds(e.timestamp)?, speed))
+        })
+        .unzip();
+    let speed_at = |t| interpolate(&speed_times, &speeds, t, |a, b, t| a + (b - a) * t);
+
+    let (Some(first), Some(last)) = (accel.first(), accel.last()) else {
+        return Some(Vec::new());
+    };
+    if config.window <= 0.0 {
+        return Some(Vec::new());
+    }
+    let in_range = |series: &[(f64, Vector3)], start: f64, end: f64| {
+        let from = series.partition_point(|&(t, _)| t < start);
+        let to = series.partition_point(|&(t, _)| t < end);
+        series[from..to].iter().map(|&(_, v)| v).collect::<Vec<_>>()
+    };
+    let mut windows = Vec::new();
+    let mut start = first.0;
+    while start < last.0 {
+        let end = start + config.window;
+        let (a, g) = (in_range(&accel, start, end), in_range(&gyro, start, end));
+        if a.len() >= 2 && g.len() >= 2 {
+            let accel_spread = std_dev(a.iter().map(|v| v.norm()));
+            let gyro_spread = Vector3::new(
+                std_dev(g.iter().map(|v| v.x)),
+                std_dev(g.iter().map(|v| v.y)),
+                std_dev(g.iter().map(|v| v.z)),
+            )
+            .norm();
+            let speed = speed_at((start + end) / 2.0);
+            windows.push(Segment {
+                motion: classify(accel_spread, gyro_spread, speed, &config),
+                start,
+                end,
+                start_frame: None,
+                end_frame: None,
+            });
+        }
+        start = end;
+    }
+
+    let mut segments = merge(windows, config.min_duration);
+    for s in &mut segments {
+        s.start_frame = timeline.frame_at(s.start);
+        s.end_frame = timeline.frame_at((s.end - 1e-6).max(s.start));
+    }
+    Some(segments)
+}
+
+/// The range from the first to the last walking or driving segment, or of the
+/// stationary ones when the camera never travelled, dropping handheld fumbling
+/// at either end; `None` when nothing is worth keeping.
+pub fn trim_range(segments: &[Segment]) -> Option<TrimRange> {
+    let travelling = |s: &&Segment| matches!(s.motion, Motion::Walking | Motion::Driving);
+    let kept: Vec<&Segment> = if segments.iter().any(|s| travelling(&s)) {
+        segments.iter().filter(travelling).collect()
+    } else {
+        segments
+            .iter()
+            .filter(|s| s.motion == Motion::Stationary)
+            .collect()
+    };
+    let (first, last) = (kept.first()?, kept.last()?);
+    Some(TrimRange {
+        start: first.start,
+        end: last.end,
+        start_frame: first.start_frame,
+        end_frame: last.end_frame,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+    use std::f64::consts::TAU;
+
+    /// Handheld for 5 s, still for 5 s, walking at 1.2 m/s for 20 s, still for
+    /// 5 s and handheld for 5 s.
+    fn setup() -> ThetaMeta {
+        let ticks: Vec<u64> = (0..4000).map(|i| i * 10_000).collect();
+        let seconds = |tick: u64| tick as f64 / 1e6;
+        let handheld = |t: f64| !(5.0..35.0).contains(&t);
+        let walking = |t: f64| (10.0..30.0).contains(&t);
+        let accel: Vec<_> = ticks
+            .iter()
+            .map(|&tick| {
+                let t = seconds(tick);
+                let z = if walking(t) {
+                    1.0 + 0.3 * (TAU * 2.0 * t).sin()
+                } else if handheld(t) {
+                    1.0 + 0.03 * (TAU * 3.0 * t).sin()
+                } else {
+                    1.0
+                };
+                (0.0, 0.0, z as f32, tick)
+            })
+            .collect();
+        let gyro: Vec<_> = ticks
+            .iter()
+            .map(|&tick| {
+                let t = seconds(tick);
+                let x = if handheld(t) {
+                    (TAU * 0.7 * t).sin()
+                } else if walking(t) {
+                    0.2 * (TAU * t).sin()
+                } else {
+                    0.0
+                };
+                (x as f32, 0.0, 0.0, tick)
+            })
+            .collect();
+        let fixes: Vec<_> = (0..=40)
+            .map(|t| {
+                let mut fix = Fix::new(100.0 + t as f64, 35.0, 139.0);
+                if walking(t as f64) {
+                    fix.velocity = [0.0, 1.2, 0.0];
+                }
+                fix
+            })
+            .collect();
+        ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtb: Some(testing::rdtb(&gyro)),
+            rdl2: Some(testing::rdl2(&fixes)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 30.0, 1200)),
+            ..Default::default()
+        }
+    }
+
+    fn assert_segments(segments: &[Segment], expected: &[(Motion, f64, f64)]) {
+        assert_eq!(segments.len(), expected.len(), "{:?}", segments);
+        for (s, &(motion, start, end)) in segments.iter().zip(expected) {
+            assert_eq!(s.motion, motion, "{:?}", segments);
+            assert!((s.start - start).abs() <= 1.0, "{:?}", segments);
+            assert!((s.end - end).abs() <= 1.0, "{:?}", segments);
+        }
+    }
+
+    #[test]
+    fn test_segment_motion() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let segments = segment_motion(&meta, &timeline, Default::default()).unwrap();
+        assert_segments(
+            &segments,
+            &[
+                (Motion::Handheld, 0.0, 5.0),
+                (Motion::Stationary, 5.0, 10.0),
+                (Motion::Walking, 10.0, 30.0),
+                (Motion::Stationary, 30.0, 35.0),
+                (Motion::Handheld, 35.0, 40.0),
+            ],
+        );
+        assert_eq!(segments[0].start_frame, Some(0));
+        assert_eq!(segments[4].end_frame, Some(1199));
+
+        let trim = trim_range(&segments).unwrap();
+        assert_eq!(trim.start, segments[2].start);
+        assert_eq!(trim.end_frame, segments[2].end_frame);
+    }
+
+    #[test]
+    fn test_without_gps() {
+        let meta = ThetaMeta {
+            rdl2: None,
+            ..setup()
+        };
+        let timeline = Timeline::new(&meta);
+        let segments = segment_motion(&meta, &timeline, Default::default()).unwrap();
+        assert_eq!(segments[2].motion, Motion::Walking);
+        assert_eq!(segments[4].motion, Motion::Handheld);
+        assert_eq!(
+            segment_motion(
+                &ThetaMeta {
+                    rdtb: None,
+                    ..setup()
+                },
+                &timeline,
+                Default::default()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let segment = |motion, start, end| Segment {
+            motion,
+            start,
+            end,
+            start_frame: None,
+            end_frame: None,
+        };
+        let merged = merge(
+            vec![
+                segment(Motion::Walking, 0.0, 5.0),
+                segment(Motion::Handheld, 5.0, 6.0),
+                segment(Motion::Walking, 6.0, 8.0),
+                segment(Motion::Stationary, 8.0, 9.0),
+            ],
+            2.0,
+        );
+        assert_eq!(merged, vec![segment(Motion::Walking, 0.0, 9.0)]);
+        assert_eq!(trim_range(&[]), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! Selection of evenly spaced video frames for photogrammetry and mapping.
+//!
+//! Distance is accumulated along the GPS path between consecutive frames, but
+//! only while moving: RDL2's reported speed (or the speed implied by RDTL) must
+//! reach a threshold, so GPS jitter while standing still does not trigger
+//! captures. A frame is picked at every multiple of the spacing, and optionally
+//! whenever the camera yaw has turned by a given angle since the last pick.
+
+use serde::Serialize;
+
+use crate::{
+    compass::{compass_headings, CompassConfig, Declination},
+    frame::FrameLookup,
+    fusion::{estimate_orientation, FusionConfig},
+    geo::GeoPoint,
+    math::{interpolate, interpolate_clamped, unwrap_angle, Quaternion},
+    theta::ThetaMeta,
+    timeline::Timeline,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionConfig {
+    /// Metres along the path between picks.
+    pub spacing: Option<f64>,
+    /// Degrees of yaw change that also trigger a pick.
+    pub heading_change: Option<f64>,
+    /// Slowest speed counted as moving, in m/s.
+    pub min_speed: f64,
+    /// Correction added to the compass heading of each frame.
+    pub declination: Declination,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        SelectionConfig {
+            spacing: Some(5.0),
+            heading_change: None,
+            min_speed: 0.5,
+            declination: Declination::None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct SelectedFrame {
+    pub frame: usize,
+    /// Seconds from video start.
+    pub time: f64,
+    pub utc: Option<f64>,
+    /// Metres moved along the path since the start of the video.
+    pub distance: f64,
+    pub position: Option<GeoPoint>,
+    /// RDL2 horizontal accuracy at the frame, in metres.
+    pub horizontal_accuracy: Option<f64>,
+    /// Sensor-to-world rotation, from RDTA/RDTB (and RDTC when present).
+    pub orientation: Option<Quaternion>,
+    /// Roll, pitch and yaw of `orientation` in degrees.
+    pub roll: Option<f64>,
+    pub pitch: Option<f64>,
+    pub yaw: Option<f64>,
+    /// Calibrated, tilt-compensated compass heading of the sensor x axis in
+    /// degrees, from magnetic north plus the declination (see
+    /// [`compass_headings`]); `None` without RDTA and a calibratable RDTC.
+    pub heading: Option<f64>,
+}
+
+/// Every frame, before selection.
+struct Candidate {
+    selected: SelectedFrame,
+    moving: bool,
+    /// Unwrapped, in degrees.
+    yaw: Option<f64>,
+}
+
+fn candidates(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    min_speed: f64,
+    declination: Declination,
+) -> Vec<Candidate> {
+    let lookup = FrameLookup::new(meta, timeline.clone());
+    let config = CompassConfig {
+        declination,
+        ..Default::default()
+    };
+    let mut headings = vec![None; timeline.frame_count()];
+    for sample in compass_headings(meta, timeline, config).map_or_else(Vec::new, |c| c.frames) {
+        if let Some(heading) = sample.frame.and_then(|frame| headings.get_mut(frame)) {
+            *heading = Some(sample.heading);
+        }
+    }
+    let (fix_times, fixes): (Vec<f64>, Vec<(f64, f64)>) = meta
+        .rdl2
+        .iter()
+        .flat_map(|rdl2| rdl2.fixes())
+        .filter_map(|e| {
+            let speed = (e.velocity_east as f64).hypot(e.velocity_north as f64);
+            let accuracy = e.horizontal_accuracy as f64;
+            Some((timeline.utc_to_seconds(e.timestamp)?, (speed, accuracy)))
+        })
+        .unzip();
+    let fix_at = |time| {
+        interpolate(&fix_times, &fixes, time, |a, b, t| {
+            (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+        })
+    };
+    let (orientation_times, orientations): (Vec<f64>, Vec<Quaternion>) =
+        match (&meta.rdta, &meta.rdtb) {
+            (Some(rdta), Some(rdtb)) => estimate_orientation(
+                rdta,
+                rdtb,
+                meta.rdtc.as_ref(),
+                timeline,
+                FusionConfig::default(),
+            )
+            .into_iter()
+            .map(|s| (s.time, s.orientation))
+            .unzip(),
+            _ => Default::default(),
+        };
+
+    let mut distance = 0.0;
+    let mut prev: Option<(f64, GeoPoint)> = None;
+    let mut prev_yaw: Option<f64> = None;
+    lookup
+        .frames()
+        .map(|sensors| {
+            let time = sensors.time;
+            let step = sensors.position.zip(prev).map(|(p, (t, q))| {
+                let d = q.distance_to(&p);
+                (d, if time > t { d / (time - t) } else { 0.0 })
+            });
+            let fix = fix_at(time);
+            let speed = match (meta.rdl2.is_some(), step) {
+                (true, _) => fix.map(|(speed, _)| speed),
+                (false, step) => step.map(|(_, speed)| speed),
+            };
+            let moving = speed.is_some_and(|speed| speed >= min_speed);
+            if let (true, Some((d, _))) = (moving, step) {
+                distance += d;
+            }
+            let orientation =
+                interpolate_clamped(&orientation_times, &orientations, time, Quaternion::slerp);
+            let euler = orientation.map(|q| q.to_euler());
+            let yaw = euler.map(|(_, _, yaw)| prev_yaw.map_or(yaw, |prev| unwrap_angle(prev, yaw)));
+            prev_yaw = yaw.or(prev_yaw);
+            if let Some(position) = sensors.position {
+                prev = Some((time, position));
+            }
+            Candidate {
+                selected: SelectedFrame {
+                    frame: sensors.frame.unwrap_or_default(),
+                    time,
+                    utc: sensors.utc,
+                    distance,
+                    position: sensors.position,
+                    horizontal_accuracy: fix.map(|(_, accuracy)| accuracy),
+                    orientation,
+                    roll: euler.map(|(roll, _, _)| roll.to_degrees()),
+                    pitch: euler.map(|(_, pitch, _)| pitch.to_degrees()),
+                    yaw: euler.map(|(_, _, yaw)| yaw.to_degrees()),
+                    heading: sensors
+                        .frame
+                        .and_then(|frame| headings.get(frame).copied().flatten()),
+                },
+                moving,
+                yaw: yaw.map(f64::to_degrees),
+            }
+        })
+        .collect()
+}
+
+/// Every video frame, with the same fields as [`select_frames`].
+pub fn frame_poses(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    declination: Declination,
+) -> Vec<SelectedFrame> {
+    candidates(meta, timeline, 0.0, declination)
+        .into_iter()
+        .map(|c| c.selected)
+        .collect()
+}
+
+/// Frames picked every `spacing` metres and `heading_change` degrees of yaw,
+/// skipping stationary periods.
+pub fn select_frames(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: SelectionConfig,
+) -> Vec<SelectedFrame> {
+    // Tolerance on distances, against rounding when a target is hit exactly.
+    const EPSILON: f64 = 1e-6;
+    let candidates = candidates(meta, timeline, config.min_speed, config.declination);
+    let mut picks: Vec<usize> = Vec::new();
+    // Distance and yaw the next pick is measured from.
+    let mut reference: Option<(f64, Option<f64>)> = None;
+    for (i, c) in candidates.iter().enumerate() {
+        if !c.moving {
+            continue;
+        }
+        let distance = c.selected.distance;
+        let Some((from, from_yaw)) = reference else {
+            picks.push(i);
+            reference = Some((distance, c.yaw));
+            continue;
+        };
+        let target = config.spacing.map(|spacing| from + spacing);
+        let by_distance = target.is_some_and(|target| distance + EPSILON >= target);
+        let by_heading = config.heading_change.is_some_and(|limit| {
+            c.yaw
+                .zip(from_yaw)
+                .is_some_and(|(yaw, from)| (yaw - from).abs() >= limit)
+        });
+        if !by_distance && !by_heading {
+            continue;
+        }
+        // The previous frame may be closer to the target.
+        let pick = match (target, i.checked_sub(1)) {
+            (Some(target), Some(p))
+                if by_distance
+                    && picks.last() != Some(&p)
+                    && candidates[p].moving
+                    && target - candidates[p].selected.distance < distance - target =>
+            {
+                p
+            }
+            _ => i,
+        };
+        picks.push(pick);
+        reference = Some(match (by_distance && !by_heading, target) {
+            // Keep the grid regular.
+            (true, Some(target)) => (target, candidates[pick].yaw),
+            _ => (candidates[pick].selected.distance, candidates[pick].yaw),
+        });
+    }
+    picks
+        .into_iter()
+        .map(|i| candidates[i].selected.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::Vector3,
+        testing::{self, Fix},
+    };
+
+    /// North at 1 m/s for 22 s, 10 s standing with GPS jitter, then 14 s more.
+    fn setup() -> ThetaMeta {
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        let fixes: Vec<_> = (0..=46)
+            .map(|t| {
+                let north = (t.min(22) + (t - 32).max(0)) as f64;
+                let jitter = if (24..=30).contains(&t) { 3.0 } else { 0.0 };
+                let p = GeoPoint::from_local(&origin, Vector3::new(jitter, north, 0.0));
+                let mut fix = Fix::new(100.0 + t as f64, p.latitude, p.longitude);
+                let moving = t <= 22 || t >= 32;
+                fix.velocity = [0.0, if moving { 1.0 } else { 0.0 }, 0.0];
+                fix
+            })
+            .collect();
+        ThetaMeta {
+            rdl2: Some(testing::rdl2(&fixes)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 10.0, 461)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_frames() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let frames = select_frames(&meta, &timeline, Default::default());
+        let expected = [0.0, 5.0, 10.0, 15.0, 20.0, 35.0, 40.0, 45.0];
+        let times: Vec<_> = frames.iter().map(|f| f.time).collect();
+        assert_eq!(times.len(), expected.len(), "{:?}", times);
+        for (time, expected) in times.iter().zip(expected) {
+            assert!((time - expected).abs() < 0.01, "{:?}", times);
+        }
+        for (i, frame) in frames.iter().enumerate() {
+            assert!((frame.distance - 5.0 * i as f64).abs() < 0.1);
+        }
+        assert_eq!(frames[1].frame, 50);
+        assert!(frames[0].position.is_some());
+        assert_eq!(frames[0].horizontal_accuracy, Some(1.0));
+        assert_eq!(frames[0].orientation, None);
+        assert_eq!(frame_poses(&meta, &timeline, Declination::None).len(), 461);
+    }
+
+    #[test]
+    fn test_heading_change() {
+        let ticks: Vec<u64> = (0..=4600).map(|i| i * 10_000).collect();
+        let accel: Vec<_> = ticks.iter().map(|&t| (0.0, 0.0, 1.0, t)).collect();
+        let gyro: Vec<_> = ticks.iter().map(|&t| (0.0, 0.0, 0.5, t)).collect();
+        let meta = ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtb: Some(testing::rdtb(&gyro)),
+            ..setup()
+        };
+        let config = SelectionConfig {
+            spacing: None,
+            heading_change: Some(90.0),
+            ..Default::default()
+        };
+        let frames = select_frames(&meta, &Timeline::new(&meta), config);
+        // A quarter turn at 0.5 rad/s takes 3.14 s.
+        let times: Vec<_> = frames.iter().map(|f| f.time).collect();
+        assert_eq!(times[0], 0.0);
+        for w in times[..7].windows(2) {
+            assert!((3.1..=3.35).contains(&(w[1] - w[0])), "{:?}", times);
+        }
+        assert!(frames.iter().all(|f| f.time <= 22.5 || f.time >= 31.5));
+        assert!(frames[1].yaw.is_some());
+        assert_eq!(frames[1].heading, None);
+    }
+
+    #[test]
+    fn test_heading() {
+        // A 40 µT field seen from many directions, enough to calibrate.
+        let mag: Vec<_> = (0..132u64)
+            .map(|i| {
+                let lon = (i % 12) as f64 * 30f64.to_radians();
+                let lat = (i / 12 + 1) as f64 * 15f64.to_radians();
+                let m =
+                    Vector3::new(lat.sin() * lon.cos(), lat.sin() * lon.sin(), lat.cos()) * 40.0;
+                (m.x as f32, m.y as f32, m.z as f32, i * 10_000)
+            })
+            .collect();
+        let accel: Vec<_> = mag.iter().map(|&(_, _, _, t)| (0.0, 0.0, 1.0, t)).collect();
+        let meta = ThetaMeta {
+            rdta: Some(testing::rdta(&accel)),
+            rdtc: Some(testing::rdtc(&mag)),
+            ..setup()
+        };
+        let timeline = Timeline::new(&meta);
+        let magnetic = frame_poses(&meta, &timeline, Declination::None);
+        let corrected = frame_poses(&meta, &timeline, Declination::Fixed(10.0));
+        let (magnetic, corrected) = (magnetic[0].heading.unwrap(), corrected[0].heading.unwrap());
+        assert!(((corrected - magnetic).rem_euclid(360.0) - 10.0).abs() < 1e-6);
+    }
+}
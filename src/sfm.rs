@@ -0,0 +1,301 @@
+//! Pose priors for structure-from-motion tools.
+//!
+//! Frames are named by a printf-style pattern of the video frame index, as
+//! written by e.g. `ffmpeg -frame_pts 1 frame_%05d.jpg`.
+
+use std::fmt::Write;
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    coordinates::{CoordinateFrame, ProjectionError},
+    geo::{GeoPoint, UtmZone},
+    math::{Quaternion, Vector3},
+    selection::SelectedFrame,
+};
+
+/// `pattern` with `%d` or `%0Nd` replaced by `frame`, and `%%` by `%`.
+pub fn frame_filename(pattern: &str, frame: usize) -> String {
+    let mut name = String::with_capacity(pattern.len() + 8);
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            name.push(c);
+            continue;
+        }
+        let mut spec = String::new();
+        while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+            spec.push(d);
+            chars.next();
+        }
+        match chars.next() {
+            Some('d') => {
+                let width: usize = spec.parse().unwrap_or(0);
+                let _ = if spec.starts_with('0') {
+                    write!(name, "{:0width$}", frame)
+                } else {
+                    write!(name, "{:width$}", frame)
+                };
+            }
+            Some('%') if spec.is_empty() => name.push('%'),
+            other => {
+                name.push('%');
+                name.push_str(&spec);
+                name.extend(other);
+            }
+        }
+    }
+    name
+}
+
+/// OpenSfM `exif_overrides.json` for the frames with a position.
+pub fn opensfm_exif_overrides(frames: &[SelectedFrame], pattern: &str) -> Value {
+    let mut overrides = Map::new();
+    for f in frames {
+        let Some(p) = f.position else {
+            continue;
+        };
+        let mut gps = json!({
+            "latitude": p.latitude,
+            "longitude": p.longitude,
+            "altitude": p.altitude,
+        });
+        if let Some(accuracy) = f.horizontal_accuracy {
+            gps["dop"] = json!(accuracy);
+        }
+        let mut exif = json!({
+            "gps": gps,
+            "projection_type": "equirectangular",
+        });
+        if let Some(utc) = f.utc {
+            exif["capture_time"] = json!(utc);
+        }
+        if let Some(heading) = f.heading {
+            exif["compass"] = json!({ "angle": heading });
+        }
+        overrides.insert(frame_filename(pattern, f.frame), exif);
+    }
+    Value::Object(overrides)
+}
+
+/// OpenSfM-style GPS list: image name, latitude, longitude and altitude per line.
+pub fn opensfm_gps_list(frames: &[SelectedFrame], pattern: &str) -> String {
+    let mut list = String::new();
+    for f in frames {
+        if let Some(p) = f.position {
+            let name = frame_filename(pattern, f.frame);
+            let _ = writeln!(
+                list,
+                "{} {:.9} {:.9} {:.3}",
+                name, p.latitude, p.longitude, p.altitude
+            );
+        }
+    }
+    list
+}
+
+/// Sensor-to-ENU rotation, with the fused yaw replaced by the compass heading.
+fn enu_orientation(frame: &SelectedFrame) -> Option<Quaternion> {
+    let (q, heading) = (frame.orientation?, frame.heading?);
+    let x = q.rotate(Vector3::new(1.0, 0.0, 0.0));
+    let turn = (90.0 - heading).to_radians() - x.y.atan2(x.x);
+    Some(Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), turn) * q)
+}
+
+/// COLMAP reference poses (`NAME X Y Z QW QX QY QZ ACCURACY`) in `frame`.
+///
+/// `model_aligner` reads the name and position; geodetic output needs
+/// `--ref_is_gps 1`. ENU is anchored at the first positioned frame, and UTM uses
+/// its zone. The quaternion is COLMAP's world-to-camera rotation of the sensor
+/// axes (the local ENU axes stand in for geodetic), known when the frame has an
+/// orientation and a compass heading; the accuracy is RDL2's horizontal accuracy
+/// in metres. Unknown values are written as `nan`.
+pub fn colmap_pose_priors(
+    frames: &[SelectedFrame],
+    pattern: &str,
+    coordinates: CoordinateFrame,
+) -> Result<String, ProjectionError> {
+    let positioned: Vec<(&SelectedFrame, GeoPoint)> = frames
+        .iter()
+        .filter_map(|f| Some((f, f.position?)))
+        .collect();
+    let [x, y, z] = coordinates.axes();
+    let mut priors = format!("# NAME {} {} {} QW QX QY QZ ACCURACY\n", x, y, z);
+    let Some(&(_, origin)) = positioned.first() else {
+        return Ok(priors);
+    };
+    let zone = UtmZone::of(&origin);
+    match (coordinates, zone) {
+        (CoordinateFrame::Enu, _) => {
+            let _ = writeln!(
+                priors,
+                "# origin {:.9} {:.9} {:.3}",
+                origin.latitude, origin.longitude, origin.altitude
+            );
+        }
+        (CoordinateFrame::Utm, Some(zone)) => {
+            let hemisphere = if zone.north { "N" } else { "S" };
+            let _ = writeln!(priors, "# zone {}{}", zone.number, hemisphere);
+        }
+        (CoordinateFrame::Utm, None) => return Err(ProjectionError::OutsideUtm),
+        _ => {}
+    }
+    // Adding zero turns -0 into 0.
+    let number = |v: Option<f64>, digits: usize| {
+        v.map_or("nan".to_string(), |v| format!("{:.*}", digits, v + 0.0))
+    };
+    let z_axis = Vector3::new(0.0, 0.0, 1.0);
+    for (f, p) in positioned {
+        let name = frame_filename(pattern, f.frame);
+        let position = match coordinates {
+            CoordinateFrame::Geodetic => {
+                format!("{:.9} {:.9} {:.3}", p.latitude, p.longitude, p.altitude)
+            }
+            CoordinateFrame::Ecef | CoordinateFrame::Enu | CoordinateFrame::Utm => {
+                let v = match coordinates {
+                    CoordinateFrame::Ecef => p.to_ecef(),
+                    CoordinateFrame::Enu => p.to_enu(&origin),
+                    _ => zone
+                        .and_then(|zone| p.to_utm(zone))
+                        .ok_or(ProjectionError::OutsideUtm)?,
+                };
+                format!("{:.3} {:.3} {:.3}", v.x, v.y, v.z)
+            }
+        };
+        let rotation = enu_orientation(f).map(|q| {
+            let world = match (coordinates, zone) {
+                // ENU axes to ECEF at the frame position.
+                (CoordinateFrame::Ecef, _) => {
+                    Quaternion::from_axis_angle(z_axis, (p.longitude + 90.0).to_radians())
+                        * Quaternion::from_axis_angle(
+                            Vector3::new(1.0, 0.0, 0.0),
+                            (90.0 - p.latitude).to_radians(),
+                        )
+                        * q
+                }
+                // True north to grid north.
+                (CoordinateFrame::Utm, Some(zone)) => {
+                    Quaternion::from_axis_angle(z_axis, zone.convergence(&p).to_radians()) * q
+                }
+                _ => q,
+            };
+            world.conjugate()
+        });
+        let _ = writeln!(
+            priors,
+            "{} {} {} {} {} {} {}",
+            name,
+            position,
+            number(rotation.map(|q| q.w), 6),
+            number(rotation.map(|q| q.x), 6),
+            number(rotation.map(|q| q.y), 6),
+            number(rotation.map(|q| q.z), 6),
+            number(f.horizontal_accuracy, 3),
+        );
+    }
+    Ok(priors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Vec<SelectedFrame> {
+        let frame = |frame: usize, latitude: f64| SelectedFrame {
+            frame,
+            time: frame as f64 / 30.0,
+            utc: Some(1000.0 + frame as f64 / 30.0),
+            distance: 0.0,
+            position: Some(GeoPoint::new(latitude, 139.0, 10.0)),
+            horizontal_accuracy: Some(2.5),
+            orientation: None,
+            roll: None,
+            pitch: None,
+            yaw: None,
+            heading: Some(90.0),
+        };
+        vec![
+            SelectedFrame {
+                orientation: Some(Quaternion::IDENTITY),
+                heading: Some(0.0),
+                ..frame(0, 35.0)
+            },
+            SelectedFrame {
+                position: None,
+                ..frame(15, 35.0)
+            },
+            frame(30, 35.001),
+        ]
+    }
+
+    #[test]
+    fn test_frame_filename() {
+        assert_eq!(frame_filename("frame_%05d.jpg", 42), "frame_00042.jpg");
+        assert_eq!(frame_filename("%d.png", 7), "7.png");
+        assert_eq!(frame_filename("100%%_%3d", 7), "100%_  7");
+        assert_eq!(frame_filename("a%sb", 1), "a%sb");
+    }
+
+    #[test]
+    fn test_opensfm() {
+        let frames = setup();
+        let overrides = opensfm_exif_overrides(&frames, "frame_%05d.jpg");
+        let map = overrides.as_object().unwrap();
+        assert_eq!(map.len(), 2);
+        let exif = &map["frame_00030.jpg"];
+        assert_eq!(exif["gps"]["latitude"], 35.001);
+        assert_eq!(exif["gps"]["dop"], 2.5);
+        assert_eq!(exif["capture_time"], 1001.0);
+        assert_eq!(exif["projection_type"], "equirectangular");
+        assert_eq!(exif["compass"]["angle"], 90.0);
+
+        let list = opensfm_gps_list(&frames, "frame_%05d.jpg");
+        assert_eq!(
+            list.lines().next(),
+            Some("frame_00000.jpg 35.000000000 139.000000000 10.000")
+        );
+    }
+
+    #[test]
+    fn test_colmap_pose_priors() {
+        let frames = setup();
+        let priors = colmap_pose_priors(&frames, "%d.jpg", CoordinateFrame::Enu).unwrap();
+        let lines: Vec<_> = priors.lines().collect();
+        assert_eq!(lines[0], "# NAME east north up QW QX QY QZ ACCURACY");
+        assert!(lines[1].starts_with("# origin 35.000000000"));
+        // Sensor x faces north: a quarter turn from east.
+        assert_eq!(
+            lines[2],
+            "0.jpg 0.000 0.000 0.000 0.707107 0.000000 0.000000 -0.707107 2.500"
+        );
+        let values: Vec<f64> = lines[3]
+            .split(' ')
+            .skip(1)
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert!(values[0].abs() < 1e-3);
+        assert!((values[1] - 110.941).abs() < 0.01);
+        assert!(values[3..7].iter().all(|v| v.is_nan()));
+        assert_eq!(values[7], 2.5);
+        assert_eq!(lines.len(), 4);
+
+        let priors = colmap_pose_priors(&frames, "%d.jpg", CoordinateFrame::Utm).unwrap();
+        assert!(priors.contains("# zone 54N"));
+
+        // The sensor z axis points up, away from the earth centre.
+        let priors = colmap_pose_priors(&frames, "%d.jpg", CoordinateFrame::Ecef).unwrap();
+        let values: Vec<f64> = priors
+            .lines()
+            .nth(1)
+            .unwrap()
+            .split(' ')
+            .skip(1)
+            .map(|v| v.parse().unwrap())
+            .collect();
+        let q = Quaternion::new(values[3], values[4], values[5], values[6]);
+        let up = q.conjugate().rotate(Vector3::new(0.0, 0.0, 1.0));
+        let (lat, lon) = (35f64.to_radians(), 139f64.to_radians());
+        let expected = Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+        assert!((up - expected).norm() < 1e-6, "{:?}", up);
+    }
+}
@@ -0,0 +1,345 @@
+//! GPS track smoothing with a constant-velocity Kalman filter and RTS smoother.
+//!
+//! Every RDL2 fix contributes its position, weighted by the horizontal and
+//! vertical accuracies, and its east/north/up velocity, weighted by the speed
+//! accuracy. East, north and up are filtered independently in a local frame
+//! around the first fix. Optionally the accelerometer, rotated into the world
+//! frame by [`estimate_orientation`], drives the prediction between fixes.
+
+use serde::Serialize;
+
+use crate::{
+    fusion::{estimate_orientation, vectors, FusionConfig},
+    geo::GeoPoint,
+    math::{interpolate, Vector3},
+    theta::{rdl2, rdl2::Rdl2Box, ThetaMeta},
+    timeline::Timeline,
+};
+
+/// Standard gravity, for accelerometer readings in g.
+const GRAVITY: f64 = 9.80665;
+/// Prior variance of the first state, effectively uninformed.
+const PRIOR_VARIANCE: f64 = 1e6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothingConfig {
+    /// Spectral density of unmodelled acceleration, in m²/s³.
+    pub process_noise: f64,
+    /// Predict with the world-frame accelerometer; needs RDTA, RDTB and RDTC.
+    pub fuse_accelerometer: bool,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        SmoothingConfig {
+            process_noise: 1.0,
+            fuse_accelerometer: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct SmoothedFix {
+    /// UTC seconds, as in RDL2.
+    pub timestamp: f64,
+    pub position: GeoPoint,
+    /// East, north and up in m/s.
+    pub velocity: Vector3,
+    /// Posterior standard deviations in metres.
+    pub horizontal_accuracy: f64,
+    pub vertical_accuracy: f64,
+}
+
+type State = ([f64; 2], [[f64; 2]; 2]);
+
+/// Position and velocity along one axis.
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    x: [f64; 2],
+    p: [[f64; 2]; 2],
+}
+
+impl Axis {
+    fn predict(&mut self, dt: f64, accel: f64, q: f64) {
+        let [pos, vel] = self.x;
+        self.x = [pos + vel * dt + 0.5 * accel * dt * dt, vel + accel * dt];
+        let [[a, b], [_, d]] = self.p;
+        let (p00, p01, p11) = (a + 2.0 * dt * b + dt * dt * d, b + dt * d, d);
+        self.p = [
+            [p00 + q * dt.powi(3) / 3.0, p01 + q * dt * dt / 2.0],
+            [p01 + q * dt * dt / 2.0, p11 + q * dt],
+        ];
+    }
+
+    /// Measures component `i` (0 position, 1 velocity) with variance `r`.
+    fn update(&mut self, i: usize, value: f64, r: f64) {
+        let s = self.p[i][i] + r;
+        let k = [self.p[0][i] / s, self.p[1][i] / s];
+        let innovation = value - self.x[i];
+        self.x = [self.x[0] + k[0] * innovation, self.x[1] + k[1] * innovation];
+        let row = self.p[i];
+        for (r, k) in self.p.iter_mut().zip(k) {
+            r[0] -= k * row[0];
+            r[1] -= k * row[1];
+        }
+    }
+
+    fn state(&self) -> State {
+        (self.x, self.p)
+    }
+}
+
+/// Rauch-Tung-Striebel backward pass over `(dt, predicted, filtered)` steps.
+fn rts(steps: &[(f64, State, State)]) -> Vec<State> {
+    let mut smoothed: Vec<State> = steps.iter().map(|&(_, _, filtered)| filtered).collect();
+    for k in (0..steps.len().saturating_sub(1)).rev() {
+        let (dt, (xp, pp), _) = steps[k + 1];
+        let (xf, pf) = steps[k].2;
+        let (xs, ps) = smoothed[k + 1];
+        let det = pp[0][0] * pp[1][1] - pp[0][1] * pp[1][0];
+        if det.abs() < 1e-18 {
+            continue;
+        }
+        let inv = [
+            [pp[1][1] / det, -pp[0][1] / det],
+            [-pp[1][0] / det, pp[0][0] / det],
+        ];
+        // C = Pf F^T Pp^-1 with F = [[1, dt], [0, 1]].
+        let pf_ft = [
+            [pf[0][0] + dt * pf[0][1], pf[0][1]],
+            [pf[1][0] + dt * pf[1][1], pf[1][1]],
+        ];
+        let c = mul(pf_ft, inv);
+        let dx = [xs[0] - xp[0], xs[1] - xp[1]];
+        let x = [
+            xf[0] + c[0][0] * dx[0] + c[0][1] * dx[1],
+            xf[1] + c[1][0] * dx[0] + c[1][1] * dx[1],
+        ];
+        let dp = [
+            [ps[0][0] - pp[0][0], ps[0][1] - pp[0][1]],
+            [ps[1][0] - pp[1][0], ps[1][1] - pp[1][1]],
+        ];
+        let correction = mul(mul(c, dp), [[c[0][0], c[1][0]], [c[0][1], c[1][1]]]);
+        let p = [
+            [pf[0][0] + correction[0][0], pf[0][1] + correction[0][1]],
+            [pf[1][0] + correction[1][0], pf[1][1] + correction[1][1]],
+        ];
+        smoothed[k] = (x, p);
+    }
+    smoothed
+}
+
+fn mul(a: [[f64; 2]; 2], b: [[f64; 2]; 2]) -> [[f64; 2]; 2] {
+    [0, 1].map(|i| [0, 1].map(|j| a[i][0] * b[0][j] + a[i][1] * b[1][j]))
+}
+
+/// World-frame acceleration (east, north, up) in m/s², in seconds from video start.
+fn world_acceleration(meta: &ThetaMeta, timeline: &Timeline) -> Option<(Vec<f64>, Vec<Vector3>)> {
+    let (rdta, rdtb, rdtc) = (
+        meta.rdta.as_ref()?,
+        meta.rdtb.as_ref()?,
+        meta.rdtc.as_ref()?,
+    );
+    let (times, accel) = vectors(
+        rdta.entries().iter().map(|e| (e.x, e.y, e.z, e.timestamp)),
+        timeline,
+    );
+    let orientation =
+        estimate_orientation(rdta, rdtb, Some(rdtc), timeline, FusionConfig::default());
+    let up = Vector3::new(0.0, 0.0, 1.0);
+    Some(
+        orientation
+            .iter()
+            .filter_map(|s| {
+                let a = interpolate(&times, &accel, s.time, Vector3::lerp)?;
+                // The fusion frame is north, west, up.
+                let w = (s.orientation.rotate(a) - up) * GRAVITY;
+                Some((s.time, Vector3::new(-w.y, w.x, w.z)))
+            })
+            .unzip(),
+    )
+}
+
+fn usable(value: f32, floor: f64) -> Option<f64> {
+    (value.is_finite() && value > 0.0).then(|| (value as f64).max(floor))
+}
+
+/// Smooths RDL2, returning one estimate per entry, or `None` without a fix.
+pub fn smooth_track(
+    meta: &ThetaMeta,
+    timeline: &Timeline,
+    config: SmoothingConfig,
+) -> Option<Vec<SmoothedFix>> {
+    let entries = meta.rdl2.as_ref()?.entries();
+    let first = entries.iter().find(|e| e.has_fix())?;
+    let origin = GeoPoint::new(first.latitude, first.longitude, first.altitude as f64);
+    let accel = config
+        .fuse_accelerometer
+        .then(|| world_acceleration(meta, timeline))
+        .flatten();
+    let mean_accel = |from: f64, to: f64| -> Vector3 {
+        let (Some((times, values)), Some(from), Some(to)) = (
+            &accel,
+            timeline.utc_to_seconds(from),
+            timeline.utc_to_seconds(to),
+        ) else {
+            return Vector3::default();
+        };
+        let range = times.partition_point(|&t| t < from)..times.partition_point(|&t| t < to);
+        match range.len() {
+            0 => Vector3::default(),
+            n => values[range].iter().fold(Vector3::default(), |s, &v| s + v) * (1.0 / n as f64),
+        }
+    };
+
+    let mut axes = [Axis {
+        x: [0.0, 0.0],
+        p: [[PRIOR_VARIANCE, 0.0], [0.0, PRIOR_VARIANCE]],
+    }; 3];
+    let mut steps: [Vec<(f64, State, State)>; 3] = Default::default();
+    let mut prev: Option<f64> = None;
+    for e in entries {
+        let dt = prev.map_or(0.0, |prev| (e.timestamp - prev).max(0.0));
+        let a = match prev {
+            Some(prev) if dt > 0.0 => mean_accel(prev, e.timestamp),
+            _ => Vector3::default(),
+        };
+        prev = Some(e.timestamp);
+        for (axis, a) in axes.iter_mut().zip([a.x, a.y, a.z]) {
+            axis.predict(dt, a, config.process_noise);
+        }
+        let predicted = axes.map(|a| a.state());
+
+        if e.has_fix() {
+            let local = GeoPoint::new(e.latitude, e.longitude, e.altitude as f64).to_local(&origin);
+            let velocity = [e.velocity_east, e.velocity_north, e.velocity_up];
+            let accuracy = [
+                e.horizontal_accuracy,
+                e.horizontal_accuracy,
+                e.vertical_accuracy,
+            ];
+            // A 2D fix says nothing about altitude.
+            let axis_count = if e.gps_fix_type >= 3 { 3 } else { 2 };
+            for (i, axis) in axes.iter_mut().enumerate().take(axis_count) {
+                if let Some(sigma) = usable(accuracy[i], 0.1) {
+                    axis.update(0, [local.x, local.y, local.z][i], sigma * sigma);
+                }
+                if let Some(sigma) = usable(e.speed_accuracy, 0.05) {
+                    axis.update(1, velocity[i] as f64, sigma * sigma);
+                }
+            }
+        }
+        for (i, axis) in axes.iter().enumerate() {
+            steps[i].push((dt, predicted[i], axis.state()));
+        }
+    }
+
+    let [east, north, up] = steps.map(|s| rts(&s));
+    Some(
+        entries
+            .iter()
+            .enumerate()
+            .map(|(k, e)| {
+                let (xe, pe) = east[k];
+                let (xn, pn) = north[k];
+                let (xu, pu) = up[k];
+                SmoothedFix {
+                    timestamp: e.timestamp,
+                    position: GeoPoint::from_local(&origin, Vector3::new(xe[0], xn[0], xu[0])),
+                    velocity: Vector3::new(xe[1], xn[1], xu[1]),
+                    horizontal_accuracy: ((pe[0][0] + pn[0][0]) / 2.0).sqrt(),
+                    vertical_accuracy: pu[0][0].sqrt(),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// RDL2 with positions, velocities and accuracies replaced by `fixes`, which must
+/// come from [`smooth_track`] over the same table.
+pub fn smoothed_rdl2(rdl2: &Rdl2Box, fixes: &[SmoothedFix]) -> Rdl2Box {
+    let mut fixes = fixes.iter();
+    rdl2.map_entries(|e| match fixes.next() {
+        Some(fix) => rdl2::DataEntry {
+            latitude: fix.position.latitude,
+            longitude: fix.position.longitude,
+            altitude: fix.position.altitude as f32,
+            horizontal_accuracy: fix.horizontal_accuracy as f32,
+            vertical_accuracy: fix.vertical_accuracy as f32,
+            velocity_east: fix.velocity.x as f32,
+            velocity_north: fix.velocity.y as f32,
+            velocity_up: fix.velocity.z as f32,
+            ..e.clone()
+        },
+        None => e.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Fix};
+
+    /// Walking east at 1 m/s, with positions zig-zagging 5 m north and south.
+    fn setup() -> ThetaMeta {
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        let fixes: Vec<_> = (0..60)
+            .map(|i| {
+                let zig = if i % 2 == 0 { 5.0 } else { -5.0 };
+                let p = GeoPoint::from_local(&origin, Vector3::new(i as f64, zig, 0.0));
+                let mut fix = Fix::new(100.0 + i as f64, p.latitude, p.longitude);
+                fix.horizontal_accuracy = 5.0;
+                fix.velocity = [1.0, 0.0, 0.0];
+                fix.speed_accuracy = 0.1;
+                fix
+            })
+            .collect();
+        ThetaMeta {
+            rdl2: Some(testing::rdl2(&fixes)),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(testing::video(100, 30.0, 1800)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_smooth_track() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let fixes = smooth_track(&meta, &timeline, Default::default()).unwrap();
+        assert_eq!(fixes.len(), 60);
+
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        for (i, fix) in fixes.iter().enumerate().skip(5).take(50) {
+            let local = fix.position.to_local(&origin);
+            assert!(local.y.abs() < 1.0, "{} {:?}", i, local);
+            assert!((local.x - i as f64).abs() < 1.0, "{} {:?}", i, local);
+            assert!((fix.velocity.x - 1.0).abs() < 0.1);
+        }
+        assert!(fixes[30].horizontal_accuracy < 5.0);
+    }
+
+    #[test]
+    fn test_smoothed_rdl2() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let fixes = smooth_track(&meta, &timeline, Default::default()).unwrap();
+        let rdl2 = smoothed_rdl2(meta.rdl2.as_ref().unwrap(), &fixes);
+        let entry = &rdl2.entries()[30];
+        assert_eq!(entry.latitude, fixes[30].position.latitude);
+        assert_eq!(entry.timestamp, 130.0);
+        assert_eq!(entry.gps_fix_type, 3);
+    }
+
+    #[test]
+    fn test_no_fix() {
+        let mut fix = Fix::new(100.0, 35.0, 139.0);
+        fix.gps_fix_type = 0;
+        let meta = ThetaMeta {
+            rdl2: Some(testing::rdl2(&[fix])),
+            ..Default::default()
+        };
+        let timeline = Timeline::new(&meta);
+        assert_eq!(smooth_track(&meta, &timeline, Default::default()), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Trip statistics of the GPS track.
+//!
+//! Distances are haversine distances between consecutive fixes, or Vincenty
+//! distances on the WGS84 ellipsoid with [`DistanceFormula::Vincenty`], which
+//! differ by up to 0.5%. With RDL2 the
+//! speed over a segment is the mean of the reported speeds at its ends, which is
+//! far less noisy than the speed implied by the positions; RDTL only has the
+//! latter. Elevation changes smaller than a hysteresis threshold are ignored.
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+    geo::{self, GeoPoint},
+    theta::ThetaMeta,
+};
+
+/// How the distance between consecutive fixes is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceFormula {
+    /// Great circle on a sphere of the mean Earth radius.
+    #[default]
+    Haversine,
+    /// Geodesic on the WGS84 ellipsoid, falling back to haversine where it does
+    /// not converge.
+    Vincenty,
+}
+
+impl FromStr for DistanceFormula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "haversine" => Ok(DistanceFormula::Haversine),
+            "vincenty" => Ok(DistanceFormula::Vincenty),
+            _ => Err(format!("unknown distance formula: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsConfig {
+    /// Slowest speed counted as moving, in m/s.
+    pub moving_speed: f64,
+    /// Smallest climb or descent counted towards gain and loss, in metres.
+    pub elevation_hysteresis: f64,
+    pub distance: DistanceFormula,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            moving_speed: 0.5,
+            elevation_hysteresis: 3.0,
+            distance: DistanceFormula::Haversine,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub min_longitude: f64,
+    pub max_latitude: f64,
+    pub max_longitude: f64,
+}
+
+/// Distances in metres, times in seconds (UTC for `start_time`/`end_time`),
+/// speeds in m/s.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TrackStats {
+    pub points: usize,
+    pub distance: f64,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub duration: f64,
+    pub moving_time: f64,
+    pub stopped_time: f64,
+    pub max_speed: f64,
+    /// Over the whole duration.
+    pub average_speed: f64,
+    /// Over the moving time only.
+    pub moving_speed: f64,
+    pub elevation_gain: f64,
+    pub elevation_loss: f64,
+    pub min_altitude: f64,
+    pub max_altitude: f64,
+    pub bounds: BoundingBox,
+    pub start: GeoPoint,
+    pub end: GeoPoint,
+}
+
+/// Timestamp, position and reported horizontal speed of each usable fix.
+fn fixes(meta: &ThetaMeta) -> Vec<(f64, GeoPoint, Option<f64>)> {
+    geo::track(meta)
+        .into_iter()
+        .map(|fix| (fix.timestamp, fix.position, fix.speed))
+        .collect()
+}
+
+/// Gain and loss of `altitudes`, ignoring changes below `hysteresis`.
+fn elevation_change(altitudes: impl Iterator<Item = f64>, hysteresis: f64) -> (f64, f64) {
+    let (mut gain, mut loss) = (0.0, 0.0);
+    let mut reference: Option<f64> = None;
+    for altitude in altitudes.filter(|a| a.is_finite()) {
+        let r = *reference.get_or_insert(altitude);
+        if altitude - r >= hysteresis {
+            gain += altitude - r;
+            reference = Some(altitude);
+        } else if r - altitude >= hysteresis {
+            loss += r - altitude;
+            reference = Some(altitude);
+        }
+    }
+    (gain, loss)
+}
+
+impl TrackStats {
+    /// Statistics of RDL2 (fixes only), or of RDTL when RDL2 is absent.
+    pub fn new(meta: &ThetaMeta, config: StatsConfig) -> Option<TrackStats> {
+        let fixes = fixes(meta);
+        let (first, last) = (fixes.first()?, fixes.last()?);
+
+        let (mut distance, mut moving_distance, mut moving_time) = (0.0, 0.0, 0.0);
+        let mut max_speed = fixes.iter().filter_map(|f| f.2).fold(0.0, f64::max);
+        for w in fixes.windows(2) {
+            let ((t0, p0, v0), (t1, p1, v1)) = (w[0], w[1]);
+            let d = match config.distance {
+                DistanceFormula::Haversine => p0.distance_to(&p1),
+                DistanceFormula::Vincenty => p0
+                    .vincenty_distance_to(&p1)
+                    .unwrap_or_else(|| p0.distance_to(&p1)),
+            };
+            let dt = t1 - t0;
+            distance += d;
+            if dt <= 0.0 {
+                continue;
+            }
+            let speed = match (v0, v1) {
+                (Some(v0), Some(v1)) => (v0 + v1) / 2.0,
+                _ => {
+                    max_speed = max_speed.max(d / dt);
+                    d / dt
+                }
+            };
+            if speed >= config.moving_speed {
+                moving_time += dt;
+                moving_distance += d;
+            }
+        }
+
+        let duration = last.0 - first.0;
+        let altitudes = || fixes.iter().map(|f| f.1.altitude);
+        let (elevation_gain, elevation_loss) =
+            elevation_change(altitudes(), config.elevation_hysteresis);
+        let bounds = fixes.iter().fold(
+            BoundingBox {
+                min_latitude: f64::INFINITY,
+                min_longitude: f64::INFINITY,
+                max_latitude: f64::NEG_INFINITY,
+                max_longitude: f64::NEG_INFINITY,
+            },
+            |b, (_, p, _)| BoundingBox {
+                min_latitude: b.min_latitude.min(p.latitude),
+                min_longitude: b.min_longitude.min(p.longitude),
+                max_latitude: b.max_latitude.max(p.latitude),
+                max_longitude: b.max_longitude.max(p.longitude),
+            },
+        );
+        Some(TrackStats {
+            points: fixes.len(),
+            distance,
+            start_time: first.0,
+            end_time: last.0,
+            duration,
+            moving_time,
+            stopped_time: (duration - moving_time).max(0.0),
+            max_speed,
+            average_speed: if duration > 0.0 {
+                distance / duration
+            } else {
+                0.0
+            },
+            moving_speed: if moving_time > 0.0 {
+                moving_distance / moving_time
+            } else {
+                0.0
+            },
+            elevation_gain,
+            elevation_loss,
+            min_altitude: altitudes().fold(f64::INFINITY, f64::min),
+            max_altitude: altitudes().fold(f64::NEG_INFINITY, f64::max),
+            bounds,
+            start: first.1,
+            end: last.1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::Vector3,
+        testing::{self, Fix},
+    };
+
+    /// 60 s walking north at 1.5 m/s, 30 s standing, with a 10 m climb and noise.
+    fn setup() -> ThetaMeta {
+        let origin = GeoPoint::new(35.0, 139.0, 10.0);
+        let fixes: Vec<_> = (0..=90)
+            .map(|i| {
+                let t = i.min(60) as f64;
+                let noise = if i % 2 == 0 { 1.0 } else { -1.0 };
+                let p = GeoPoint::from_local(&origin, Vector3::new(0.0, 1.5 * t, 0.0));
+                let mut fix = Fix::new(1000.0 + i as f64, p.latitude, p.longitude);
+                fix.altitude = (10.0 + t / 6.0 + noise) as f32;
+                fix.velocity = [0.0, if i < 60 { 1.5 } else { 0.0 }, 0.0];
+                fix
+            })
+            .collect();
+        ThetaMeta {
+            rdl2: Some(testing::rdl2(&fixes)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_track_stats() {
+        let stats = TrackStats::new(&setup(), Default::default()).unwrap();
+        assert_eq!(stats.points, 91);
+        assert!((stats.distance - 90.0).abs() < 0.5);
+        assert_eq!(stats.duration, 90.0);
+        assert_eq!(stats.moving_time, 60.0);
+        assert_eq!(stats.stopped_time, 30.0);
+        assert!((stats.max_speed - 1.5).abs() < 1e-6);
+        assert!((stats.moving_speed - 1.5).abs() < 0.01);
+        assert!((stats.average_speed - 1.0).abs() < 0.01);
+        // The ±1 m noise stays below the hysteresis.
+        assert!((stats.elevation_gain - 10.0).abs() < 2.5);
+        assert!(stats.elevation_loss < 2.5);
+        assert_eq!(stats.start_time, 1000.0);
+        assert!(stats.bounds.max_latitude > stats.bounds.min_latitude);
+        assert_eq!(stats.bounds.max_latitude, stats.end.latitude);
+    }
+
+    #[test]
+    fn test_track_stats_vincenty() {
+        let meta = setup();
+        let haversine = TrackStats::new(&meta, Default::default()).unwrap();
+        let config = StatsConfig {
+            distance: DistanceFormula::Vincenty,
+            ..Default::default()
+        };
+        let vincenty = TrackStats::new(&meta, config).unwrap();
+        // Meridian arcs at 35°N are slightly shorter on the ellipsoid.
+        assert!(vincenty.distance < haversine.distance);
+        assert!((vincenty.distance - haversine.distance).abs() < 0.005 * haversine.distance);
+        assert_eq!(vincenty.moving_time, haversine.moving_time);
+    }
+
+    #[test]
+    fn test_elevation_change() {
+        let altitudes = [0.0, 2.0, 0.0, 5.0, 4.0, 9.0, 3.0];
+        assert_eq!(elevation_change(altitudes.into_iter(), 3.0), (9.0, 6.0));
+        assert_eq!(elevation_change(altitudes.into_iter(), 0.0), (12.0, 9.0));
+    }
+
+    #[test]
+    fn test_no_fixes() {
+        assert_eq!(
+            TrackStats::new(&ThetaMeta::default(), Default::default()),
+            None
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Builders for synthetic RDT payloads shared by unit tests.
+
+use crate::{
+    theta::{rdl2::Rdl2Box, rdta::RdtaBox, rdtb::RdtbBox, rdtc::RdtcBox, rdtg::RdtgBox, VideoInfo},
+    timeline::MP4_EPOCH_OFFSET,
+};
+
+/// Little-endian RDT payload with the given entries.
+pub(crate) fn table(entries: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = (entries.len() as u32).to_le_bytes().to_vec();
+    data.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x23, 0x01, 0, 0, 0, 0, 0, 0]);
+    entries.iter().for_each(|e| data.extend_from_slice(e));
+    data
+}
+
+pub(crate) fn imu_entry(x: f32, y: f32, z: f32, timestamp: u64) -> Vec<u8> {
+    let mut entry = [x, y, z, 0.0].map(f32::to_le_bytes).concat();
+    entry.extend_from_slice(&timestamp.to_le_bytes());
+    entry
+}
+
+pub(crate) struct Fix {
+    pub timestamp: f64,
+    pub gps_fix_type: i16,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f32,
+    pub horizontal_accuracy: f32,
+    pub vertical_accuracy: f32,
+    pub velocity: [f32; 3],
+    pub speed_accuracy: f32,
+}
+
+impl Fix {
+    pub(crate) fn new(timestamp: f64, latitude: f64, longitude: f64) -> Fix {
+        Fix {
+            timestamp,
+            gps_fix_type: 3,
+            latitude,
+            longitude,
+            altitude: 10.0,
+            horizontal_accuracy: 1.0,
+            vertical_accuracy: 1.0,
+            velocity: [0.0; 3],
+            speed_accuracy: 0.5,
+        }
+    }
+
+    pub(crate) fn entry(&self) -> Vec<u8> {
+        let mut entry = self.timestamp.to_le_bytes().to_vec();
+        entry.extend_from_slice(&self.gps_fix_type.to_le_bytes());
+        entry.extend_from_slice(&self.latitude.to_le_bytes());
+        entry.extend_from_slice(&self.longitude.to_le_bytes());
+        for v in [
+            self.altitude,
+            self.horizontal_accuracy,
+            self.vertical_accuracy,
+            self.velocity[0],
+            self.velocity[1],
+            self.velocity[2],
+            self.speed_accuracy,
+        ] {
+            entry.extend_from_slice(&v.to_le_bytes());
+        }
+        entry
+    }
+}
+
+pub(crate) fn rdta(samples: &[(f32, f32, f32, u64)]) -> RdtaBox {
+    let entries: Vec<_> = samples
+        .iter()
+        .map(|&(x, y, z, t)| imu_entry(x, y, z, t))
+        .collect();
+    RdtaBox::read(&table(&entries))
+}
+
+pub(crate) fn rdtb(samples: &[(f32, f32, f32, u64)]) -> RdtbBox {
+    let entries: Vec<_> = samples
+        .iter()
+        .map(|&(x, y, z, t)| imu_entry(x, y, z, t))
+        .collect();
+    RdtbBox::read(&table(&entries))
+}
+
+pub(crate) fn rdtc(samples: &[(f32, f32, f32, u64)]) -> RdtcBox {
+    let entries: Vec<_> = samples
+        .iter()
+        .map(|&(x, y, z, t)| imu_entry(x, y, z, t))
+        .collect();
+    RdtcBox::read(&table(&entries))
+}
+
+pub(crate) fn rdtg(timestamps: &[u64]) -> RdtgBox {
+    let entries: Vec<_> = timestamps
+        .iter()
+        .map(|t| t.to_le_bytes().to_vec())
+        .collect();
+    RdtgBox::read(&table(&entries))
+}
+
+pub(crate) fn rdl2(fixes: &[Fix]) -> Rdl2Box {
+    let entries: Vec<_> = fixes.iter().map(Fix::entry).collect();
+    Rdl2Box::read(&table(&entries))
+}
+
+/// Video starting at UNIX time `start_utc`.
+pub(crate) fn video(start_utc: u64, frame_rate: f64, frame_count: u32) -> VideoInfo {
+    VideoInfo {
+        creation_time: MP4_EPOCH_OFFSET + start_utc,
+        timescale: 1000,
+        duration: (frame_count as f64 / frame_rate * 1000.0) as u64,
+        frame_rate,
+        frame_count,
+    }
+}
@@ -9,6 +9,8 @@ pub mod rthu;
 
 use serde::Serialize;
 
+use crate::stats::TrackStats;
+
 #[derive(Debug)]
 pub struct RawBox {
     pub data: Vec<u8>,
@@ -37,8 +39,12 @@ pub struct SerializableThetaMeta {
     pub manu: String,
     #[serde(rename = "modl")]
     pub modl: String,
+    /// Trip statistics of RDL2, or RDTL; left out unless filled in, as by the
+    /// CLI's `--stats`, since computing them walks the whole track.
+    #[serde(rename = "stats", skip_serializing_if = "Option::is_none")]
+    pub stats: Option<TrackStats>,
 }
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ThetaMeta {
     pub rthu: Option<rthu::RthuBox>,
     pub rmkn: Option<RawBox>,
@@ -60,9 +66,49 @@ pub struct ThetaMeta {
     pub _mak: String,
     pub manu: String,
     pub modl: String,
+    pub video: Option<VideoInfo>,
+}
+
+/// Timing of the MP4 movie and its video track, for [`crate::timeline::Timeline`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct VideoInfo {
+    /// `mvhd` creation time, in seconds since 1904-01-01 UTC.
+    pub creation_time: u64,
+    /// `mvhd` timescale, in ticks per second.
+    pub timescale: u32,
+    /// `mvhd` duration, in `timescale` ticks.
+    pub duration: u64,
+    pub frame_rate: f64,
+    pub frame_count: u32,
 }
 
 impl ThetaMeta {
+    pub fn has_box(&self, name: &str) -> bool {
+        match name {
+            "RTHU" => self.rthu.is_some(),
+            "RMKN" => self.rmkn.is_some(),
+            "RDT1-8" => self.rdt1_8.is_some(),
+            "RDT9" => self.rdt9.is_some(),
+            "RDTA" => self.rdta.is_some(),
+            "RDTB" => self.rdtb.is_some(),
+            "RDTC" => self.rdtc.is_some(),
+            "RDTD" => self.rdtd.is_some(),
+            "RDTG" => self.rdtg.is_some(),
+            "RDTH" => self.rdth.is_some(),
+            "RDTI" => self.rdti.is_some(),
+            "RDTL" => self.rdtl.is_some(),
+            "RDL2" => self.rdl2.is_some(),
+            "@mod" => !self._mod.is_empty(),
+            "@swr" => !self._swr.is_empty(),
+            "@day" => !self._day.is_empty(),
+            "@xyz" => !self._xyz.is_empty(),
+            "@mak" => !self._mak.is_empty(),
+            "manu" => !self.manu.is_empty(),
+            "modl" => !self.modl.is_empty(),
+            _ => false,
+        }
+    }
+
     pub fn to_serializable(&self) -> SerializableThetaMeta {
         SerializableThetaMeta {
             rdta: self.rdta.clone(),
@@ -78,6 +124,7 @@ impl ThetaMeta {
             _mak: self._mak.clone(),
             manu: self.manu.clone(),
             modl: self.modl.clone(),
+            stats: None,
         }
     }
 }
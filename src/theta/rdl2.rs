@@ -1,4 +1,4 @@
-use super::rdt::RdtBox;
+use super::rdt::{read_table, Entry, EntryReader, RdtBox, TableView};
 use serde::ser::{Serialize, Serializer};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -22,166 +22,92 @@ pub struct DataEntry {
     pub speed_accuracy: f32,
 }
 
+pub type Rdl2View<'a> = TableView<'a, DataEntry>;
+
 impl Rdl2Box {
     pub fn get_entry(&self) -> Vec<DataEntry> {
         self.data_table.clone()
     }
 
-    pub(crate) fn read(data: &[u8]) -> Rdl2Box {
-        let base = RdtBox::read(data);
-        let number_of_entries = base.number_of_entries;
-        let offset = base._size;
-        let mut data_table = Vec::new();
-        for i in 0..number_of_entries as usize {
-            let entry_offset = offset + i * 54;
-            let (
-                timestamp,
-                gps_fix_type,
-                latitude,
-                longitude,
-                altitude,
-                horizontal_accuracy,
-                vertical_accuracy,
-                velocity_east,
-                velocity_north,
-                velocity_up,
-                speed_accuracy,
-            ) = match base.endian {
-                0x0123 => (
-                    f64::from_le_bytes(
-                        data[entry_offset as usize..(entry_offset + 8) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // timestamp
-                    i16::from_le_bytes(
-                        data[entry_offset as usize + 8..entry_offset as usize + 10]
-                            .try_into()
-                            .unwrap(),
-                    ), // gps_fix_type
-                    f64::from_le_bytes(
-                        data[entry_offset as usize + 10..entry_offset as usize + 18]
-                            .try_into()
-                            .unwrap(),
-                    ), // latitude
-                    f64::from_le_bytes(
-                        data[entry_offset as usize + 18..entry_offset as usize + 26]
-                            .try_into()
-                            .unwrap(),
-                    ), // longitude
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 26..entry_offset as usize + 30]
-                            .try_into()
-                            .unwrap(),
-                    ), // altitude
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 30..entry_offset as usize + 34]
-                            .try_into()
-                            .unwrap(),
-                    ), // horizontal_accuracy
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 34..entry_offset as usize + 38]
-                            .try_into()
-                            .unwrap(),
-                    ), // vertical_accuracy
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 38..entry_offset as usize + 42]
-                            .try_into()
-                            .unwrap(),
-                    ), // velocity_east
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 42..entry_offset as usize + 46]
-                            .try_into()
-                            .unwrap(),
-                    ), // velocity_north
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 46..entry_offset as usize + 50]
-                            .try_into()
-                            .unwrap(),
-                    ), // velocity_up
-                    f32::from_le_bytes(
-                        data[entry_offset as usize + 50..entry_offset as usize + 54]
-                            .try_into()
-                            .unwrap(),
-                    ), // speed_accuracy
-                ),
-                0x3210 => (
-                    f64::from_be_bytes(
-                        data[entry_offset as usize..(entry_offset + 8) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // timestamp
-                    i16::from_be_bytes(
-                        data[entry_offset as usize + 8..entry_offset as usize + 10]
-                            .try_into()
-                            .unwrap(),
-                    ), // gps_fix_type
-                    f64::from_be_bytes(
-                        data[entry_offset as usize + 10..entry_offset as usize + 18]
-                            .try_into()
-                            .unwrap(),
-                    ), // latitude
-                    f64::from_be_bytes(
-                        data[entry_offset as usize + 18..entry_offset as usize + 26]
-                            .try_into()
-                            .unwrap(),
-                    ), // longitude
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 26..entry_offset as usize + 30]
-                            .try_into()
-                            .unwrap(),
-                    ), // altitude
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 30..entry_offset as usize + 34]
-                            .try_into()
-                            .unwrap(),
-                    ), // horizontal_accuracy
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 34..entry_offset as usize + 38]
-                            .try_into()
-                            .unwrap(),
-                    ), // vertical_accuracy
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 38..entry_offset as usize + 42]
-                            .try_into()
-                            .unwrap(),
-                    ), // velocity_east
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 42..entry_offset as usize + 46]
-                            .try_into()
-                            .unwrap(),
-                    ), // velocity_north
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 46..entry_offset as usize + 50]
-                            .try_into()
-                            .unwrap(),
-                    ), // velocity_up
-                    f32::from_be_bytes(
-                        data[entry_offset as usize + 50..entry_offset as usize + 54]
-                            .try_into()
-                            .unwrap(),
-                    ), // speed_accuracy
-                ),
-                _ => panic!("Unknown endian type"),
-            };
-            data_table.push(DataEntry {
-                timestamp,
-                gps_fix_type,
-                latitude,
-                longitude,
-                altitude,
-                horizontal_accuracy,
-                vertical_accuracy,
-                velocity_east,
-                velocity_north,
-                velocity_up,
-                speed_accuracy,
-            });
+    pub fn entries(&self) -> &[DataEntry] {
+        &self.data_table
+    }
+
+    pub fn header(&self) -> &RdtBox {
+        &self.base
+    }
+
+    /// Copy of this table with every entry replaced by `f(entry)`.
+    pub fn map_entries(&self, f: impl FnMut(&DataEntry) -> DataEntry) -> Rdl2Box {
+        Rdl2Box {
+            base: self.base.clone(),
+            data_table: self.data_table.iter().map(f).collect(),
+        }
+    }
+
+    /// Copy of this table keeping only the entries for which `f` returns true.
+    pub fn retain_entries(&self, f: impl FnMut(&&DataEntry) -> bool) -> Rdl2Box {
+        let data_table: Vec<_> = self.data_table.iter().filter(f).cloned().collect();
+        Rdl2Box {
+            base: RdtBox {
+                number_of_entries: data_table.len() as u32,
+                ..self.base.clone()
+            },
+            data_table,
         }
+    }
 
+    /// Entries with at least a 2D fix.
+    pub fn fixes(&self) -> impl Iterator<Item = &DataEntry> {
+        self.data_table.iter().filter(|e| e.has_fix())
+    }
+
+    pub fn view(data: &[u8]) -> Option<Rdl2View<'_>> {
+        TableView::new(data)
+    }
+
+    /// Decodes every entry of `view` into an owned table.
+    pub fn from_view(view: Rdl2View<'_>) -> Rdl2Box {
+        Rdl2Box {
+            base: view.header(),
+            data_table: view.to_vec(),
+        }
+    }
+
+    pub(crate) fn read(data: &[u8]) -> Rdl2Box {
+        let (base, data_table) = read_table(data);
         Rdl2Box { base, data_table }
     }
 }
 
+impl DataEntry {
+    /// Whether the receiver reported at least a 2D fix.
+    pub fn has_fix(&self) -> bool {
+        self.gps_fix_type >= 2
+    }
+}
+
+impl Entry for DataEntry {
+    const SIZE: usize = 54;
+
+    fn decode(data: &[u8], big_endian: bool) -> Self {
+        let mut reader = EntryReader::new(data, big_endian);
+        DataEntry {
+            timestamp: reader.f64(),
+            gps_fix_type: reader.i16(),
+            latitude: reader.f64(),
+            longitude: reader.f64(),
+            altitude: reader.f32(),
+            horizontal_accuracy: reader.f32(),
+            vertical_accuracy: reader.f32(),
+            velocity_east: reader.f32(),
+            velocity_north: reader.f32(),
+            velocity_up: reader.f32(),
+            speed_accuracy: reader.f32(),
+        }
+    }
+}
+
 impl Serialize for Rdl2Box {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -1,4 +1,4 @@
-use std::convert::TryInto;
+use std::{convert::TryInto, marker::PhantomData};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct RdtBox {
@@ -37,4 +37,192 @@ impl RdtBox {
             endian,
         }
     }
+
+    /// Byte order of the data table, or `None` for an unknown endian marker.
+    pub fn is_big_endian(&self) -> Option<bool> {
+        match self.endian {
+            0x0123 => Some(false),
+            0x3210 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// A fixed-size record of an RDT data table.
+pub trait Entry: Sized {
+    /// Size of one record in bytes.
+    const SIZE: usize;
+    /// Whether the table header itself is stored big-endian.
+    const BIG_ENDIAN_HEADER: bool = false;
+
+    /// Decodes one record from exactly `SIZE` bytes.
+    fn decode(data: &[u8], big_endian: bool) -> Self;
+}
+
+pub(crate) struct EntryReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    big_endian: bool,
+}
+
+macro_rules! entry_reader_fn {
+    ($name:ident, $ty:ty) => {
+        pub(crate) fn $name(&mut self) -> $ty {
+            const N: usize = std::mem::size_of::<$ty>();
+            let bytes: [u8; N] = self.data[self.offset..self.offset + N].try_into().unwrap();
+            self.offset += N;
+            if self.big_endian {
+                <$ty>::from_be_bytes(bytes)
+            } else {
+                <$ty>::from_le_bytes(bytes)
+            }
+        }
+    };
+}
+
+impl<'a> EntryReader<'a> {
+    pub(crate) fn new(data: &'a [u8], big_endian: bool) -> Self {
+        EntryReader {
+            data,
+            offset: 0,
+            big_endian,
+        }
+    }
+
+    entry_reader_fn!(i16, i16);
+    entry_reader_fn!(u64, u64);
+    entry_reader_fn!(f32, f32);
+    entry_reader_fn!(f64, f64);
+}
+
+fn read_header<T: Entry>(data: &[u8]) -> RdtBox {
+    if T::BIG_ENDIAN_HEADER {
+        RdtBox::read_be(data)
+    } else {
+        RdtBox::read(data)
+    }
+}
+
+pub(crate) fn read_table<T: Entry>(data: &[u8]) -> (RdtBox, Vec<T>) {
+    let base = read_header::<T>(data);
+    let big_endian = base.is_big_endian().expect("Unknown endian type");
+    let data_table = (0..base.number_of_entries as usize)
+        .map(|i| {
+            let entry_offset = base._size + i * T::SIZE;
+            T::decode(&data[entry_offset..entry_offset + T::SIZE], big_endian)
+        })
+        .collect();
+    (base, data_table)
+}
+
+/// Borrowed view over an RDT data table that decodes entries on demand.
+#[derive(Debug)]
+pub struct TableView<'a, T> {
+    number_of_entries: u32,
+    sampling_rate: u16,
+    sample_size: u16,
+    endian: u16,
+    big_endian: bool,
+    table: &'a [u8],
+    _entry: PhantomData<T>,
+}
+
+impl<T> Clone for TableView<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TableView<'_, T> {}
+
+impl<'a, T: Entry> TableView<'a, T> {
+    /// Returns `None` if `data` is too short for the header or the endian marker is unknown.
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 16 {
+            return None;
+        }
+        let base = read_header::<T>(data);
+        Some(TableView {
+            number_of_entries: base.number_of_entries,
+            sampling_rate: base.sampling_rate,
+            sample_size: base.sample_size,
+            endian: base.endian,
+            big_endian: base.is_big_endian()?,
+            table: &data[base._size..],
+            _entry: PhantomData,
+        })
+    }
+
+    /// Number of entries declared in the `RdtBox` header.
+    pub fn len(&self) -> usize {
+        self.number_of_entries as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.number_of_entries == 0
+    }
+
+    pub fn sampling_rate(&self) -> u16 {
+        self.sampling_rate
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    /// The `RdtBox` header the view was read from.
+    pub fn header(&self) -> RdtBox {
+        RdtBox {
+            _size: 16,
+            number_of_entries: self.number_of_entries,
+            sampling_rate: self.sampling_rate,
+            sample_size: self.sample_size,
+            endian: self.endian,
+        }
+    }
+
+    /// Decodes entry `i`, or `None` if it is out of range or truncated.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len() {
+            return None;
+        }
+        let start = i.checked_mul(T::SIZE)?;
+        let bytes = self.table.get(start..start + T::SIZE)?;
+        Some(T::decode(bytes, self.big_endian))
+    }
+
+    pub fn iter(&self) -> TableIter<'a, T> {
+        TableIter {
+            view: *self,
+            index: 0,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<'a, T: Entry> IntoIterator for TableView<'a, T> {
+    type Item = T;
+    type IntoIter = TableIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct TableIter<'a, T> {
+    view: TableView<'a, T>,
+    index: usize,
+}
+
+impl<T: Entry> Iterator for TableIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let entry = self.view.get(self.index)?;
+        self.index += 1;
+        Some(entry)
+    }
 }
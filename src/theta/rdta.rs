@@ -1,4 +1,4 @@
-use super::rdt::RdtBox;
+use super::rdt::{read_table, Entry, EntryReader, RdtBox, TableView};
 use serde::ser::{Serialize, Serializer};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -16,75 +16,62 @@ pub struct DataEntry {
     pub timestamp: u64,
 }
 
+pub type RdtaView<'a> = TableView<'a, DataEntry>;
+
 impl RdtaBox {
     pub fn get_entry(&self) -> Vec<DataEntry> {
         self.data_table.clone()
     }
 
-    pub(crate) fn read(data: &[u8]) -> RdtaBox {
-        let base = RdtBox::read(data);
-        let number_of_entries = base.number_of_entries;
-        let offset = base._size;
-        let mut data_table = Vec::new();
-        for i in 0..number_of_entries as usize {
-            let entry_offset = offset + i * 24;
-            let (x, y, z, reserve, timestamp) = match base.endian {
-                0x0123 => (
-                    f32::from_le_bytes(data[entry_offset..entry_offset + 4].try_into().unwrap()), // x
-                    f32::from_le_bytes(
-                        data[entry_offset + 4..entry_offset + 8].try_into().unwrap(),
-                    ), // y
-                    f32::from_le_bytes(
-                        data[entry_offset + 8..entry_offset + 12]
-                            .try_into()
-                            .unwrap(),
-                    ), // z
-                    f32::from_le_bytes(
-                        data[entry_offset + 12..entry_offset + 16]
-                            .try_into()
-                            .unwrap(),
-                    ), // reserve
-                    u64::from_le_bytes(
-                        data[entry_offset + 16..entry_offset + 24]
-                            .try_into()
-                            .unwrap(),
-                    ), // timestamp
-                ),
-                0x3210 => (
-                    f32::from_be_bytes(data[entry_offset..entry_offset + 4].try_into().unwrap()), // x
-                    f32::from_be_bytes(
-                        data[entry_offset + 4..entry_offset + 8].try_into().unwrap(),
-                    ), // y
-                    f32::from_be_bytes(
-                        data[entry_offset + 8..entry_offset + 12]
-                            .try_into()
-                            .unwrap(),
-                    ), // z
-                    f32::from_be_bytes(
-                        data[entry_offset + 12..entry_offset + 16]
-                            .try_into()
-                            .unwrap(),
-                    ), // reserve
-                    u64::from_be_bytes(
-                        data[entry_offset + 16..entry_offset + 24]
-                            .try_into()
-                            .unwrap(),
-                    ), // timestamp
-                ),
-                _ => panic!("Unknown endian type"),
-            };
-            data_table.push(DataEntry {
-                x,
-                y,
-                z,
-                reserve,
-                timestamp,
-            });
+    pub fn entries(&self) -> &[DataEntry] {
+        &self.data_table
+    }
+
+    pub fn header(&self) -> &RdtBox {
+        &self.base
+    }
+
+    /// Copy of this table with every entry replaced by `f(entry)`.
+    pub fn map_entries(&self, f: impl FnMut(&DataEntry) -> DataEntry) -> RdtaBox {
+        RdtaBox {
+            base: self.base.clone(),
+            data_table: self.data_table.iter().map(f).collect(),
         }
+    }
+
+    pub fn view(data: &[u8]) -> Option<RdtaView<'_>> {
+        TableView::new(data)
+    }
+
+    /// Decodes every entry of `view` into an owned table.
+    pub fn from_view(view: RdtaView<'_>) -> RdtaBox {
+        RdtaBox {
+            base: view.header(),
+            data_table: view.to_vec(),
+        }
+    }
+
+    pub(crate) fn read(data: &[u8]) -> RdtaBox {
+        let (base, data_table) = read_table(data);
         RdtaBox { base, data_table }
     }
 }
 
+impl Entry for DataEntry {
+    const SIZE: usize = 24;
+
+    fn decode(data: &[u8], big_endian: bool) -> Self {
+        let mut reader = EntryReader::new(data, big_endian);
+        DataEntry {
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            reserve: reader.f32(),
+            timestamp: reader.u64(),
+        }
+    }
+}
+
 impl Serialize for RdtaBox {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -165,4 +152,20 @@ mod tests {
         let actual_json: serde_json::Value = serde_json::from_str(&json_output).unwrap();
         assert_eq!(actual_json, expected_json);
     }
+
+    #[test]
+    fn test_rdta_view() {
+        let data: Vec<u8> = setup();
+        let view = RdtaBox::view(&data).unwrap();
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.get(1).unwrap().x, 20.0);
+        assert_eq!(view.get(2), None);
+        assert_eq!(view.to_vec(), RdtaBox::read(&data).get_entry());
+
+        let timestamps: Vec<u64> = view.iter().map(|entry| entry.timestamp).collect();
+        assert_eq!(timestamps, vec![1, 2]);
+
+        assert!(RdtaBox::view(&data[..24]).unwrap().get(0).is_none());
+        assert!(RdtaBox::view(&data[..8]).is_none());
+    }
 }
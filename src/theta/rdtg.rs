@@ -1,4 +1,4 @@
-use super::rdt::RdtBox;
+use super::rdt::{read_table, Entry, EntryReader, RdtBox, TableView};
 use serde::ser::{Serialize, Serializer};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -12,37 +12,50 @@ pub struct DataEntry {
     pub timestamp: u64,
 }
 
+pub type RdtgView<'a> = TableView<'a, DataEntry>;
+
 impl RdtgBox {
     pub fn get_entry(&self) -> Vec<DataEntry> {
         self.data_table.clone()
     }
 
-    pub(crate) fn read(data: &[u8]) -> RdtgBox {
-        let base = RdtBox::read(data);
-        let number_of_entries = base.number_of_entries;
-        let offset = base._size;
-        let mut data_table = Vec::new();
-        for i in 0..number_of_entries as usize {
-            let entry_offset = offset + i * 8;
-            let timestamp = match base.endian {
-                0x0123 => u64::from_le_bytes(
-                    data[entry_offset as usize..(entry_offset + 8) as usize]
-                        .try_into()
-                        .unwrap(),
-                ),
-                0x3210 => u64::from_be_bytes(
-                    data[entry_offset as usize..(entry_offset + 8) as usize]
-                        .try_into()
-                        .unwrap(),
-                ),
-                _ => panic!("Unknown endian type"),
-            };
-            data_table.push(DataEntry { timestamp });
+    pub fn entries(&self) -> &[DataEntry] {
+        &self.data_table
+    }
+
+    pub fn header(&self) -> &RdtBox {
+        &self.base
+    }
+
+    pub fn view(data: &[u8]) -> Option<RdtgView<'_>> {
+        TableView::new(data)
+    }
+
+    /// Decodes every entry of `view` into an owned table.
+    pub fn from_view(view: RdtgView<'_>) -> RdtgBox {
+        RdtgBox {
+            base: view.header(),
+            data_table: view.to_vec(),
         }
+    }
+
+    pub(crate) fn read(data: &[u8]) -> RdtgBox {
+        let (base, data_table) = read_table(data);
         RdtgBox { base, data_table }
     }
 }
 
+impl Entry for DataEntry {
+    const SIZE: usize = 8;
+
+    fn decode(data: &[u8], big_endian: bool) -> Self {
+        let mut reader = EntryReader::new(data, big_endian);
+        DataEntry {
+            timestamp: reader.u64(),
+        }
+    }
+}
+
 impl Serialize for RdtgBox {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
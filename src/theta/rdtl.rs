@@ -1,4 +1,4 @@
-use super::rdt::RdtBox;
+use super::rdt::{read_table, Entry, EntryReader, RdtBox, TableView};
 use serde::ser::{Serialize, Serializer};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -15,76 +15,54 @@ pub struct DataEntry {
     pub altitude: f64,
 }
 
+pub type RdtlView<'a> = TableView<'a, DataEntry>;
+
 impl RdtlBox {
     pub fn get_entry(&self) -> Vec<DataEntry> {
         self.data_table.clone()
     }
 
-    pub(crate) fn read(data: &[u8]) -> RdtlBox {
-        let base = RdtBox::read_be(data);
-        let number_of_entries = base.number_of_entries;
-        let offset = base._size;
-        let mut data_table = Vec::new();
-        for i in 0..number_of_entries as usize {
-            let entry_offset = offset + i * 32;
-            let (timestamp, latitude, longitude, altitude) = match base.endian {
-                0x0123 => (
-                    f64::from_le_bytes(
-                        data[entry_offset as usize..(entry_offset + 8) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // timestamp
-                    f64::from_le_bytes(
-                        data[(entry_offset + 8) as usize..(entry_offset + 16) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // latitude
-                    f64::from_le_bytes(
-                        data[(entry_offset + 16) as usize..(entry_offset + 24) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // longitude
-                    f64::from_le_bytes(
-                        data[(entry_offset + 24) as usize..(entry_offset + 32) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // altitude
-                ),
-                0x3210 => (
-                    f64::from_be_bytes(
-                        data[entry_offset as usize..(entry_offset + 8) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // timestamp
-                    f64::from_be_bytes(
-                        data[(entry_offset + 8) as usize..(entry_offset + 16) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // latitude
-                    f64::from_be_bytes(
-                        data[(entry_offset + 16) as usize..(entry_offset + 24) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // longitude
-                    f64::from_be_bytes(
-                        data[(entry_offset + 24) as usize..(entry_offset + 32) as usize]
-                            .try_into()
-                            .unwrap(),
-                    ), // altitude
-                ),
-                _ => panic!("Unknown endian type"),
-            };
-            data_table.push(DataEntry {
-                timestamp,
-                latitude,
-                longitude,
-                altitude,
-            });
+    pub fn entries(&self) -> &[DataEntry] {
+        &self.data_table
+    }
+
+    pub fn header(&self) -> &RdtBox {
+        &self.base
+    }
+
+    pub fn view(data: &[u8]) -> Option<RdtlView<'_>> {
+        TableView::new(data)
+    }
+
+    /// Decodes every entry of `view` into an owned table.
+    pub fn from_view(view: RdtlView<'_>) -> RdtlBox {
+        RdtlBox {
+            base: view.header(),
+            data_table: view.to_vec(),
         }
+    }
+
+    pub(crate) fn read(data: &[u8]) -> RdtlBox {
+        let (base, data_table) = read_table(data);
         RdtlBox { base, data_table }
     }
 }
 
+impl Entry for DataEntry {
+    const SIZE: usize = 32;
+    const BIG_ENDIAN_HEADER: bool = true;
+
+    fn decode(data: &[u8], big_endian: bool) -> Self {
+        let mut reader = EntryReader::new(data, big_endian);
+        DataEntry {
+            timestamp: reader.f64(),
+            latitude: reader.f64(),
+            longitude: reader.f64(),
+            altitude: reader.f64(),
+        }
+    }
+}
+
 impl Serialize for RdtlBox {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -163,4 +141,13 @@ mod tests {
         let actual_json: serde_json::Value = serde_json::from_str(&json_output).unwrap();
         assert_eq!(actual_json, expected_json);
     }
+
+    #[test]
+    fn test_rdtl_view() {
+        let data: Vec<u8> = setup();
+        let view = RdtlBox::view(&data).unwrap();
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.sampling_rate(), 1);
+        assert_eq!(view.get(1).unwrap().altitude, 131.1);
+    }
 }
@@ -0,0 +1,428 @@
+//! A common clock for all sensor tables and the video track.
+//!
+//! THETA records two kinds of timestamps:
+//!
+//! - RDTA, RDTB, RDTC and RDTG carry `u64` ticks of the camera's free-running clock,
+//!   whose origin is arbitrary. RDTG holds the tick at which each video frame was
+//!   captured, so its first entry anchors tick time to the start of the video.
+//! - RDTL and RDL2 carry `f64` UNIX time in seconds (UTC) from the GPS receiver. They
+//!   are anchored to the video through the `mvhd` creation time, which can be
+//!   overridden with [`Timeline::with_video_start_utc`] when the camera clock was off.
+//!
+//! The tick unit is inferred by comparing the RDTG spacing with the video frame rate,
+//! or else the span of RDTG (or of the IMU tables) with the video duration. Only
+//! without any of these does [`ClockModel::for_model`] fall back to a per-model
+//! guess; RICOH publishes no specification of the tick unit, so the table below
+//! merely records the units observed in sample files:
+//!
+//! | `modl`                      | Tick unit |
+//! |-----------------------------|-----------|
+//! | RICOH THETA X, Z1, SC2, V   | 1 µs      |
+//! | other RICOH THETA           | 1 ms      |
+
+use std::ops::Range;
+
+use crate::theta::{rdl2, rdta, rdtb, rdtc, rdtl, ThetaMeta};
+
+/// Seconds between the MP4 epoch (1904-01-01) and the UNIX epoch (1970-01-01).
+pub(crate) const MP4_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ClockModel {
+    /// Seconds per tick of the `u64` sensor and frame timestamps.
+    pub tick_seconds: f64,
+}
+
+impl ClockModel {
+    pub const MILLISECONDS: ClockModel = ClockModel { tick_seconds: 1e-3 };
+    pub const MICROSECONDS: ClockModel = ClockModel { tick_seconds: 1e-6 };
+    pub const NANOSECONDS: ClockModel = ClockModel { tick_seconds: 1e-9 };
+
+    /// Unverified per-model default; see the module documentation.
+    pub fn for_model(modl: &str) -> ClockModel {
+        const MICROSECOND_MODELS: &[&str] = &["THETA X", "THETA Z1", "THETA SC2", "THETA V"];
+        if MICROSECOND_MODELS.iter().any(|m| modl.contains(m)) {
+            ClockModel::MICROSECONDS
+        } else {
+            ClockModel::MILLISECONDS
+        }
+    }
+
+    /// Infers the tick unit from RDTG frame timestamps, snapped to ms, µs or ns.
+    pub fn infer(frame_timestamps: &[u64], frame_rate: f64) -> Option<ClockModel> {
+        if frame_rate <= 0.0 || !frame_rate.is_finite() {
+            return None;
+        }
+        let mut deltas: Vec<u64> = frame_timestamps
+            .windows(2)
+            .map(|w| w[1].saturating_sub(w[0]))
+            .filter(|&d| d > 0)
+            .collect();
+        if deltas.is_empty() {
+            return None;
+        }
+        deltas.sort_unstable();
+        let median = deltas[deltas.len() / 2] as f64;
+        Some(ClockModel::snap(1.0 / frame_rate / median))
+    }
+
+    /// Infers the tick unit from `ticks` elapsed over `seconds` of video, snapped to
+    /// ms, µs or ns.
+    pub fn from_span(ticks: u64, seconds: f64) -> Option<ClockModel> {
+        if ticks == 0 || seconds <= 0.0 || !seconds.is_finite() {
+            return None;
+        }
+        Some(ClockModel::snap(seconds / ticks as f64))
+    }
+
+    /// The standard unit closest to `tick_seconds` on a log scale.
+    fn snap(tick_seconds: f64) -> ClockModel {
+        [
+            ClockModel::MILLISECONDS,
+            ClockModel::MICROSECONDS,
+            ClockModel::NANOSECONDS,
+        ]
+        .into_iter()
+        .min_by(|a, b| {
+            let distance = |c: &ClockModel| (c.tick_seconds / tick_seconds).log10().abs();
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or(ClockModel::MILLISECONDS)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    clock: ClockModel,
+    origin: u64,
+    video_start_utc: Option<f64>,
+    frame_times: Vec<f64>,
+}
+
+/// Sensor samples captured during one video frame, borrowed from [`ThetaMeta`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FrameSamples<'a> {
+    pub accelerometer: &'a [rdta::DataEntry],
+    pub gyroscope: &'a [rdtb::DataEntry],
+    pub magnetometer: &'a [rdtc::DataEntry],
+    pub location: &'a [rdtl::DataEntry],
+    pub gps: &'a [rdl2::DataEntry],
+}
+
+impl Timeline {
+    pub fn new(meta: &ThetaMeta) -> Timeline {
+        let frame_ticks = frame_ticks(meta);
+        let clock = meta
+            .video
+            .as_ref()
+            .and_then(|video| {
+                let seconds = video.duration as f64 / video.timescale as f64;
+                let span = |ticks: &[u64]| Some(ticks.last()? - ticks.first()?);
+                ClockModel::infer(&frame_ticks, video.frame_rate)
+                    .or_else(|| ClockModel::from_span(span(&frame_ticks)?, seconds))
+                    .or_else(|| ClockModel::from_span(imu_span(meta)?, seconds))
+            })
+            .unwrap_or_else(|| ClockModel::for_model(&meta.modl));
+        Timeline::with_clock(meta, clock)
+    }
+
+    pub fn with_clock(meta: &ThetaMeta, clock: ClockModel) -> Timeline {
+        let frame_ticks = frame_ticks(meta);
+        // Without RDTG, the earliest IMU sample stands in for the start of the video.
+        let origin = frame_ticks.first().copied().or_else(|| {
+            [
+                meta.rdta
+                    .as_ref()
+                    .and_then(|b| b.entries().first())
+                    .map(|e| e.timestamp),
+                meta.rdtb
+                    .as_ref()
+                    .and_then(|b| b.entries().first())
+                    .map(|e| e.timestamp),
+                meta.rdtc
+                    .as_ref()
+                    .and_then(|b| b.entries().first())
+                    .map(|e| e.timestamp),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+        });
+        let mut timeline = Timeline {
+            clock,
+            origin: origin.unwrap_or(0),
+            video_start_utc: meta
+                .video
+                .as_ref()
+                .and_then(|video| video.creation_time.checked_sub(MP4_EPOCH_OFFSET))
+                .map(|t| t as f64),
+            frame_times: Vec::new(),
+        };
+        timeline.frame_times = if !frame_ticks.is_empty() {
+            frame_ticks
+                .iter()
+                .map(|&t| timeline.tick_to_seconds(t))
+                .collect()
+        } else if let Some(video) = meta.video.as_ref().filter(|v| v.frame_rate > 0.0) {
+            (0..video.frame_count)
+                .map(|i| i as f64 / video.frame_rate)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        timeline
+    }
+
+    /// Overrides the UTC time (UNIX seconds) of the first video frame.
+    pub fn with_video_start_utc(mut self, utc: f64) -> Timeline {
+        self.video_start_utc = Some(utc);
+        self
+    }
+
+    pub fn clock(&self) -> ClockModel {
+        self.clock
+    }
+
+    pub fn video_start_utc(&self) -> Option<f64> {
+        self.video_start_utc
+    }
+
+    /// Seconds from video start of a `u64` sensor or frame timestamp.
+    pub fn tick_to_seconds(&self, tick: u64) -> f64 {
+        let ticks = if tick >= self.origin {
+            (tick - self.origin) as f64
+        } else {
+            -((self.origin - tick) as f64)
+        };
+        ticks * self.clock.tick_seconds
+    }
+
+    /// Seconds from video start of an RDTL/RDL2 UTC timestamp.
+    pub fn utc_to_seconds(&self, utc: f64) -> Option<f64> {
+        Some(utc - self.video_start_utc?)
+    }
+
+    /// UTC (UNIX seconds) of a time in seconds from video start.
+    pub fn seconds_to_utc(&self, seconds: f64) -> Option<f64> {
+        Some(self.video_start_utc? + seconds)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_times.len()
+    }
+
+    /// Presentation time of every frame, in seconds from video start.
+    pub fn frame_times(&self) -> &[f64] {
+        &self.frame_times
+    }
+
+    pub fn frame_time(&self, frame: usize) -> Option<f64> {
+        self.frame_times.get(frame).copied()
+    }
+
+    /// Time span `[start, end)` covered by a frame, ending at the next frame.
+    pub fn frame_interval(&self, frame: usize) -> Option<Range<f64>> {
+        let start = self.frame_time(frame)?;
+        let end = match self.frame_time(frame + 1) {
+            Some(next) => next,
+            None => start + self.frame_period()?,
+        };
+        Some(start..end)
+    }
+
+    /// The frame being displayed at `seconds` from video start.
+    pub fn frame_at(&self, seconds: f64) -> Option<usize> {
+        let index = self.frame_times.partition_point(|&t| t <= seconds);
+        let frame = index.checked_sub(1)?;
+        self.frame_interval(frame)?
+            .contains(&seconds)
+            .then_some(frame)
+    }
+
+    /// Sensor samples whose timestamps fall within the interval of `frame`.
+    pub fn frame_samples<'a>(&self, meta: &'a ThetaMeta, frame: usize) -> Option<FrameSamples<'a>> {
+        let interval = self.frame_interval(frame)?;
+        let ticks = |t: u64| Some(self.tick_to_seconds(t));
+        let utc = |t: f64| self.utc_to_seconds(t);
+        Some(FrameSamples {
+            accelerometer: samples_in(meta.rdta.as_ref().map(|b| b.entries()), &interval, |e| {
+                ticks(e.timestamp)
+            }),
+            gyroscope: samples_in(meta.rdtb.as_ref().map(|b| b.entries()), &interval, |e| {
+                ticks(e.timestamp)
+            }),
+            magnetometer: samples_in(meta.rdtc.as_ref().map(|b| b.entries()), &interval, |e| {
+                ticks(e.timestamp)
+            }),
+            location: samples_in(meta.rdtl.as_ref().map(|b| b.entries()), &interval, |e| {
+                utc(e.timestamp)
+            }),
+            gps: samples_in(meta.rdl2.as_ref().map(|b| b.entries()), &interval, |e| {
+                utc(e.timestamp)
+            }),
+        })
+    }
+
+    fn frame_period(&self) -> Option<f64> {
+        let n = self.frame_times.len();
+        if n < 2 {
+            return None;
+        }
+        Some((self.frame_times[n - 1] - self.frame_times[0]) / (n - 1) as f64)
+    }
+}
+
+fn frame_ticks(meta: &ThetaMeta) -> Vec<u64> {
+    meta.rdtg
+        .as_ref()
+        .map(|b| b.entries().iter().map(|e| e.timestamp).collect())
+        .unwrap_or_default()
+}
+
+/// Ticks between the first and last sample of the first IMU table with samples.
+fn imu_span(meta: &ThetaMeta) -> Option<u64> {
+    let span = |first: Option<u64>, last: Option<u64>| last?.checked_sub(first?);
+    let rdta = meta.rdta.as_ref().map(|b| b.entries()).unwrap_or_default();
+    let rdtb = meta.rdtb.as_ref().map(|b| b.entries()).unwrap_or_default();
+    let rdtc = meta.rdtc.as_ref().map(|b| b.entries()).unwrap_or_default();
+    [
+        span(
+            rdta.first().map(|e| e.timestamp),
+            rdta.last().map(|e| e.timestamp),
+        ),
+        span(
+            rdtb.first().map(|e| e.timestamp),
+            rdtb.last().map(|e| e.timestamp),
+        ),
+        span(
+            rdtc.first().map(|e| e.timestamp),
+            rdtc.last().map(|e| e.timestamp),
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|&ticks| ticks > 0)
+}
+
+// Entries are assumed to be in time order, as recorded.
+fn samples_in<'a, T>(
+    entries: Option<&'a [T]>,
+    interval: &Range<f64>,
+    time: impl Fn(&T) -> Option<f64>,
+) -> &'a [T] {
+    let Some(entries) = entries else {
+        return &[];
+    };
+    let start = entries.partition_point(|e| time(e).is_some_and(|t| t < interval.start));
+    let end = entries.partition_point(|e| time(e).is_some_and(|t| t < interval.end));
+    &entries[start..end.max(start)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theta::{rdl2::Rdl2Box, rdta::RdtaBox, rdtg::RdtgBox, VideoInfo};
+
+    fn table(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = (entries.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x23, 0x01, 0, 0, 0, 0, 0, 0]);
+        entries.iter().for_each(|e| data.extend_from_slice(e));
+        data
+    }
+
+    fn imu(x: f32, timestamp: u64) -> Vec<u8> {
+        let mut entry = [x, 0.0, 0.0, 0.0].map(f32::to_le_bytes).concat();
+        entry.extend_from_slice(&timestamp.to_le_bytes());
+        entry
+    }
+
+    fn gps(timestamp: f64) -> Vec<u8> {
+        let mut entry = timestamp.to_le_bytes().to_vec();
+        entry.resize(54, 0);
+        entry
+    }
+
+    fn setup() -> ThetaMeta {
+        let frames: Vec<Vec<u8>> = [1_000_000u64, 1_500_000, 2_000_000]
+            .iter()
+            .map(|t| t.to_le_bytes().to_vec())
+            .collect();
+        ThetaMeta {
+            rdtg: Some(RdtgBox::read(&table(&frames))),
+            rdta: Some(RdtaBox::read(&table(&[
+                imu(1.0, 900_000),
+                imu(2.0, 1_100_000),
+                imu(3.0, 1_600_000),
+                imu(4.0, 1_700_000),
+                imu(5.0, 2_100_000),
+            ]))),
+            rdl2: Some(Rdl2Box::read(&table(&[gps(1000.2), gps(1000.6)]))),
+            modl: "RICOH THETA X".to_string(),
+            video: Some(VideoInfo {
+                creation_time: MP4_EPOCH_OFFSET + 1000,
+                timescale: 1000,
+                duration: 1500,
+                frame_rate: 2.0,
+                frame_count: 3,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clock_model_infer() {
+        let frames = [0u64, 33_333, 66_667, 100_000];
+        assert_eq!(
+            ClockModel::infer(&frames, 30.0),
+            Some(ClockModel::MICROSECONDS)
+        );
+        let frames = [0u64, 33, 67, 100];
+        assert_eq!(
+            ClockModel::infer(&frames, 30.0),
+            Some(ClockModel::MILLISECONDS)
+        );
+        assert_eq!(ClockModel::infer(&[0], 30.0), None);
+    }
+
+    #[test]
+    fn test_clock_model_from_span() {
+        assert_eq!(
+            ClockModel::from_span(59_966_667, 60.0),
+            Some(ClockModel::MICROSECONDS)
+        );
+        assert_eq!(
+            ClockModel::from_span(59_967, 60.0),
+            Some(ClockModel::MILLISECONDS)
+        );
+        assert_eq!(ClockModel::from_span(0, 60.0), None);
+        assert_eq!(ClockModel::from_span(1000, 0.0), None);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        assert_eq!(timeline.clock(), ClockModel::MICROSECONDS);
+        assert_eq!(timeline.frame_times(), &[0.0, 0.5, 1.0]);
+        assert!((timeline.tick_to_seconds(900_000) + 0.1).abs() < 1e-9);
+        assert!((timeline.utc_to_seconds(1000.6).unwrap() - 0.6).abs() < 1e-9);
+        assert_eq!(timeline.seconds_to_utc(0.5), Some(1000.5));
+        assert_eq!(timeline.frame_at(0.7), Some(1));
+        assert_eq!(timeline.frame_at(1.49), Some(2));
+        assert_eq!(timeline.frame_at(1.5), None);
+        assert_eq!(timeline.frame_at(-0.1), None);
+    }
+
+    #[test]
+    fn test_frame_samples() {
+        let meta = setup();
+        let timeline = Timeline::new(&meta);
+        let samples = timeline.frame_samples(&meta, 1).unwrap();
+        let x: Vec<f32> = samples.accelerometer.iter().map(|e| e.x).collect();
+        assert_eq!(x, vec![3.0, 4.0]);
+        assert_eq!(samples.gps.len(), 1);
+        assert_eq!(samples.gps[0].timestamp, 1000.6);
+        assert!(samples.gyroscope.is_empty());
+        assert!(timeline.frame_samples(&meta, 3).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+//! World Magnetic Model (WMM) of the Earth's main magnetic field.
+//!
+//! The degree-12 spherical harmonic coefficients of WMM2020 (valid from 2020.0 to
+//! 2025.0) and WMM2025 (2025.0 to 2030.0), as published by NOAA NCEI and the
+//! British Geological Survey, are evaluated with linear secular variation from the
+//! model epoch. Outside those years there is no model, and crustal and external
+//! fields are not modelled at all, so local anomalies of a degree or more remain.
+
+use crate::geo::{GeoPoint, EARTH_RADIUS, FLATTENING};
+
+/// Geomagnetic reference radius in metres.
+const REFERENCE_RADIUS: f64 = 6_371_200.0;
+const DEGREE: usize = 12;
+/// Seconds in a mean Gregorian year.
+const YEAR_SECONDS: f64 = 365.2425 * 86_400.0;
+
+/// Gauss coefficients `(n, m, g, h, g_dot, h_dot)`, in nT and nT/year.
+type Coefficients = [(usize, usize, f64, f64, f64, f64)];
+
+struct Model {
+    epoch: f64,
+    coefficients: &'static Coefficients,
+}
+
+const MODELS: [Model; 2] = [
+    Model {
+        epoch: 2020.0,
+        coefficients: WMM2020,
+    },
+    Model {
+        epoch: 2025.0,
+        coefficients: WMM2025,
+    },
+];
+
+/// Field in nT along the local geodetic north, east and down directions.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MagneticField {
+    pub north: f64,
+    pub east: f64,
+    pub down: f64,
+}
+
+impl MagneticField {
+    /// Degrees east of true north.
+    pub fn declination(&self) -> f64 {
+        self.east.atan2(self.north).to_degrees()
+    }
+
+    /// Degrees below the horizontal.
+    pub fn inclination(&self) -> f64 {
+        self.down.atan2(self.north.hypot(self.east)).to_degrees()
+    }
+
+    pub fn intensity(&self) -> f64 {
+        (self.north * self.north + self.east * self.east + self.down * self.down).sqrt()
+    }
+}
+
+/// Decimal year of a UNIX time, counting years of 365.2425 days.
+pub fn decimal_year(utc: f64) -> f64 {
+    1970.0 + utc / YEAR_SECONDS
+}
+
+/// Main field at `point`, whose altitude is in metres above the WGS84 ellipsoid,
+/// in decimal `year`; `None` outside 2020.0 to 2030.0 or at the geographic poles.
+pub fn magnetic_field(point: &GeoPoint, year: f64) -> Option<MagneticField> {
+    let model = MODELS
+        .iter()
+        .rev()
+        .find(|model| year >= model.epoch && year <= model.epoch + 5.0)?;
+    let dt = year - model.epoch;
+
+    // Geodetic to geocentric spherical coordinates.
+    let (lat, lon) = (point.latitude.to_radians(), point.longitude.to_radians());
+    let e2 = FLATTENING * (2.0 - FLATTENING);
+    // Prime vertical radius of curvature.
+    let radius = EARTH_RADIUS / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    let p = (radius + point.altitude) * lat.cos();
+    let z = (radius * (1.0 - e2) + point.altitude) * lat.sin();
+    let r = p.hypot(z);
+    let geocentric = (z / r).asin();
+    let (sin, cos) = geocentric.sin_cos();
+    if cos < 1e-10 {
+        return None;
+    }
+
+    // Schmidt semi-normalized associated Legendre functions of sin(latitude) and
+    // their derivatives with respect to latitude.
+    let mut legendre = [[0.0; DEGREE + 1]; DEGREE + 1];
+    let mut derivative = [[0.0; DEGREE + 1]; DEGREE + 1];
+    legendre[0][0] = 1.0;
+    for n in 1..=DEGREE {
+        for m in 0..=n {
+            if n == m {
+                let k = if n == 1 {
+                    1.0
+                } else {
+                    ((2 * n - 1) as f64 / (2 * n) as f64).sqrt()
+                };
+                let (p, dp) = (legendre[n - 1][m - 1], derivative[n - 1][m - 1]);
+                legendre[n][m] = k * cos * p;
+                derivative[n][m] = k * (cos * dp - sin * p);
+            } else {
+                let (nf, mf) = (n as f64, m as f64);
+                let k1 = (2.0 * nf - 1.0) / (nf * nf - mf * mf).sqrt();
+                let k2 = (((nf - 1.0) * (nf - 1.0) - mf * mf) / (nf * nf - mf * mf)).sqrt();
+                let (p2, dp2) = if n > 1 {
+                    (legendre[n - 2][m], derivative[n - 2][m])
+                } else {
+                    (0.0, 0.0)
+                };
+                let (p1, dp1) = (legendre[n - 1][m], derivative[n - 1][m]);
+                legendre[n][m] = k1 * sin * p1 - k2 * p2;
+                derivative[n][m] = k1 * (sin * dp1 + cos * p1) - k2 * dp2;
+            }
+        }
+    }
+
+    let (mut north, mut east, mut down) = (0.0, 0.0, 0.0);
+    for &(n, m, g, h, g_dot, h_dot) in model.coefficients {
+        let (g, h) = (g + dt * g_dot, h + dt * h_dot);
+        let scale = (REFERENCE_RADIUS / r).powi(n as i32 + 2);
+        let (sin_m, cos_m) = (m as f64 * lon).sin_cos();
+        let harmonic = g * cos_m + h * sin_m;
+        north -= scale * harmonic * derivative[n][m];
+        east += scale * m as f64 * (g * sin_m - h * cos_m) * legendre[n][m] / cos;
+        down -= scale * (n + 1) as f64 * harmonic * legendre[n][m];
+    }
+
+    // Rotate from the geocentric to the geodetic frame.
+    let (sin_d, cos_d) = (geocentric - lat).sin_cos();
+    Some(MagneticField {
+        north: north * cos_d - down * sin_d,
+        east,
+        down: north * sin_d + down * cos_d,
+    })
+}
+
+#[rustfmt::skip]
+const WMM2020: &Coefficients = &[
+    (1, 0, -29404.5, 0.0, 6.7, 0.0),
+    (1, 1, -1450.7, 4652.9, 7.7, -25.1),
+    (2, 0, -2500.0, 0.0, -11.5, 0.0),
+    (2, 1, 2982.0, -2991.6, -7.1, -30.2),
+    (2, 2, 1676.8, -734.8, -2.2, -23.9),
+    (3, 0, 1363.9, 0.0, 2.8, 0.0),
+    (3, 1, -2381.0, -82.2, -6.2, 5.7),
+    (3, 2, 1236.2, 241.8, 3.4, -1.0),
+    (3, 3, 525.7, -542.9, -12.2, 1.1),
+    (4, 0, 903.1, 0.0, -1.1, 0.0),
+    (4, 1, 809.4, 282.0, -1.6, 0.2),
+    (4, 2, 86.2, -158.4, -6.0, 6.9),
+    (4, 3, -309.4, 199.8, 5.4, 3.7),
+    (4, 4, 47.9, -350.1, -5.5, -5.6),
+    (5, 0, -234.4, 0.0, -0.3, 0.0),
+    (5, 1, 363.1, 47.7, 0.6, 0.1),
+    (5, 2, 187.8, 208.4, -0.7, 2.5),
+    (5, 3, -140.7, -121.3, 0.1, -0.9),
+    (5, 4, -151.2, 32.2, 1.2, 3.0),
+    (5, 5, 13.7, 99.1, 1.0, 0.5),
+    (6, 0, 65.9, 0.0, -0.6, 0.0),
+    (6, 1, 65.6, -19.1, -0.4, 0.1),
+    (6, 2, 73.0, 25.0, 0.5, -1.8),
+    (6, 3, -121.5, 52.7, 1.4, -1.4),
+    (6, 4, -36.2, -64.4, -1.4, 0.9),
+    (6, 5, 13.5, 9.0, 0.0, 0.1),
+    (6, 6, -64.7, 68.1, 0.8, 1.0),
+    (7, 0, 80.6, 0.0, -0.1, 0.0),
+    (7, 1, -76.8, -51.4, -0.3, 0.5),
+    (7, 2, -8.3, -16.8, -0.1, 0.6),
+    (7, 3, 56.5, 2.3, 0.7, -0.7),
+    (7, 4, 15.8, 23.5, 0.2, -0.2),
+    (7, 5, 6.4, -2.2, -0.5, -1.2),
+    (7, 6, -7.2, -27.2, -0.8, 0.2),
+    (7, 7, 9.8, -1.9, 1.0, 0.3),
+    (8, 0, 23.6, 0.0, -0.1, 0.0),
+    (8, 1, 9.8, 8.4, 0.1, -0.3),
+    (8, 2, -17.5, -15.3, -0.1, 0.7),
+    (8, 3, -0.4, 12.8, 0.5, -0.2),
+    (8, 4, -21.1, -11.8, -0.1, 0.5),
+    (8, 5, 15.3, 14.9, 0.4, -0.3),
+    (8, 6, 13.7, 3.6, 0.5, -0.5),
+    (8, 7, -16.5, -6.9, 0.0, 0.4),
+    (8, 8, -0.3, 2.8, 0.4, 0.1),
+    (9, 0, 5.0, 0.0, -0.1, 0.0),
+    (9, 1, 8.2, -23.3, -0.2, -0.3),
+    (9, 2, 2.9, 11.1, 0.0, 0.2),
+    (9, 3, -1.4, 9.8, 0.4, -0.4),
+    (9, 4, -1.1, -5.1, -0.3, 0.4),
+    (9, 5, -13.3, -6.2, 0.0, 0.1),
+    (9, 6, 1.1, 7.8, 0.3, 0.0),
+    (9, 7, 8.9, 0.4, 0.0, -0.2),
+    (9, 8, -9.3, -1.5, 0.0, 0.5),
+    (9, 9, -11.9, 9.7, -0.4, 0.2),
+    (10, 0, -1.9, 0.0, 0.0, 0.0),
+    (10, 1, -6.2, 3.4, 0.0, 0.0),
+    (10, 2, -0.1, -0.2, 0.0, 0.1),
+    (10, 3, 1.7, 3.5, 0.2, -0.3),
+    (10, 4, -0.9, 4.8, -0.1, 0.1),
+    (10, 5, 0.6, -8.6, -0.2, -0.2),
+    (10, 6, -0.9, -0.1, 0.0, 0.1),
+    (10, 7, 1.9, -4.2, -0.1, 0.0),
+    (10, 8, 1.4, -3.4, -0.2, -0.1),
+    (10, 9, -2.4, -0.1, -0.1, 0.2),
+    (10, 10, -3.9, -8.8, 0.0, 0.0),
+    (11, 0, 3.0, 0.0, 0.0, 0.0),
+    (11, 1, -1.4, 0.0, -0.1, 0.0),
+    (11, 2, -2.5, 2.6, 0.0, 0.1),
+    (11, 3, 2.4, -0.5, 0.0, 0.0),
+    (11, 4, -0.9, -0.4, 0.0, 0.2),
+    (11, 5, 0.3, 0.6, -0.1, 0.0),
+    (11, 6, -0.7, -0.2, 0.0, 0.0),
+    (11, 7, -0.1, -1.7, 0.0, 0.1),
+    (11, 8, 1.4, -1.6, -0.1, 0.0),
+    (11, 9, -0.6, -3.0, -0.1, -0.1),
+    (11, 10, 0.2, -2.0, -0.1, 0.0),
+    (11, 11, 3.1, -2.6, -0.1, 0.0),
+    (12, 0, -2.0, 0.0, 0.0, 0.0),
+    (12, 1, -0.1, -1.2, 0.0, 0.0),
+    (12, 2, 0.5, 0.5, 0.0, 0.0),
+    (12, 3, 1.3, 1.3, 0.0, -0.1),
+    (12, 4, -1.2, -1.8, 0.0, 0.1),
+    (12, 5, 0.7, 0.1, 0.0, 0.0),
+    (12, 6, 0.3, 0.7, 0.0, 0.0),
+    (12, 7, 0.5, -0.1, 0.0, 0.0),
+    (12, 8, -0.2, 0.6, 0.0, 0.1),
+    (12, 9, -0.5, 0.2, 0.0, 0.0),
+    (12, 10, 0.1, -0.9, 0.0, 0.0),
+    (12, 11, -1.1, 0.0, 0.0, 0.0),
+    (12, 12, -0.3, 0.5, -0.1, -0.1),
+];
+
+#[rustfmt::skip]
+const WMM2025: &Coefficients = &[
+    (1, 0, -29351.8, 0.0, 12.0, 0.0),
+    (1, 1, -1410.8, 4545.4, 9.7, -21.5),
+    (2, 0, -2556.6, 0.0, -11.6, 0.0),
+    (2, 1, 2951.1, -3133.6, -5.2, -27.7),
+    (2, 2, 1649.3, -815.1, -8.0, -12.1),
+    (3, 0, 1361.0, 0.0, -1.3, 0.0),
+    (3, 1, -2404.1, -56.6, -4.2, 4.0),
+    (3, 2, 1243.8, 237.5, 0.4, -0.3),
+    (3, 3, 453.6, -549.5, -15.6, -4.1),
+    (4, 0, 895.0, 0.0, -1.6, 0.0),
+    (4, 1, 799.5, 278.6, -2.4, -1.1),
+    (4, 2, 55.7, -133.9, -6.0, 4.1),
+    (4, 3, -281.1, 212.0, 5.6, 1.6),
+    (4, 4, 12.1, -375.6, -7.0, -4.4),
+    (5, 0, -233.2, 0.0, 0.6, 0.0),
+    (5, 1, 368.9, 45.4, 1.4, -0.5),
+    (5, 2, 187.2, 220.2, 0.0, 2.2),
+    (5, 3, -138.7, -122.9, 0.6, 0.4),
+    (5, 4, -142.0, 43.0, 2.2, 1.7),
+    (5, 5, 20.9, 106.1, 0.9, 1.9),
+    (6, 0, 64.4, 0.0, -0.2, 0.0),
+    (6, 1, 63.8, -18.4, -0.4, 0.3),
+    (6, 2, 76.9, 16.8, 0.9, -1.6),
+    (6, 3, -115.7, 48.8, 1.2, -0.4),
+    (6, 4, -40.9, -59.8, -0.9, 0.9),
+    (6, 5, 14.9, 10.9, 0.3, 0.7),
+    (6, 6, -60.7, 72.7, 0.9, 0.9),
+    (7, 0, 79.5, 0.0, 0.0, 0.0),
+    (7, 1, -77.0, -48.9, -0.1, 0.6),
+    (7, 2, -8.8, -14.4, -0.1, 0.5),
+    (7, 3, 59.3, -1.0, 0.5, -0.8),
+    (7, 4, 15.8, 23.4, -0.1, 0.0),
+    (7, 5, 2.5, -7.4, -0.8, -1.0),
+    (7, 6, -11.1, -25.1, -0.8, 0.6),
+    (7, 7, 14.2, -2.3, 0.8, -0.2),
+    (8, 0, 23.2, 0.0, -0.1, 0.0),
+    (8, 1, 10.8, 7.1, 0.2, -0.2),
+    (8, 2, -17.5, -12.6, 0.0, 0.5),
+    (8, 3, 2.0, 11.4, 0.5, -0.4),
+    (8, 4, -21.7, -9.7, -0.1, 0.4),
+    (8, 5, 16.9, 12.7, 0.3, -0.5),
+    (8, 6, 15.0, 0.7, 0.2, -0.6),
+    (8, 7, -16.8, -5.2, 0.0, 0.3),
+    (8, 8, 0.9, 3.9, 0.2, 0.2),
+    (9, 0, 4.6, 0.0, 0.0, 0.0),
+    (9, 1, 7.8, -24.8, -0.1, -0.3),
+    (9, 2, 3.0, 12.2, 0.1, 0.3),
+    (9, 3, -0.2, 8.3, 0.3, -0.3),
+    (9, 4, -2.5, -3.3, -0.3, 0.3),
+    (9, 5, -13.1, -5.2, 0.0, 0.2),
+    (9, 6, 2.4, 7.2, 0.3, -0.1),
+    (9, 7, 8.6, -0.6, -0.1, -0.2),
+    (9, 8, -8.7, 0.8, 0.1, 0.4),
+    (9, 9, -12.9, 10.0, -0.1, 0.1),
+    (10, 0, -1.3, 0.0, 0.1, 0.0),
+    (10, 1, -6.4, 3.3, 0.0, 0.0),
+    (10, 2, 0.2, 0.0, 0.1, 0.0),
+    (10, 3, 2.0, 2.4, 0.1, -0.2),
+    (10, 4, -1.0, 5.3, 0.0, 0.1),
+    (10, 5, -0.6, -9.1, -0.3, -0.1),
+    (10, 6, -0.9, 0.4, 0.0, 0.1),
+    (10, 7, 1.5, -4.2, -0.1, 0.0),
+    (10, 8, 0.9, -3.8, -0.1, -0.1),
+    (10, 9, -2.7, 0.9, 0.0, 0.2),
+    (10, 10, -3.9, -9.1, 0.0, 0.0),
+    (11, 0, 2.9, 0.0, 0.0, 0.0),
+    (11, 1, -1.5, 0.0, 0.0, 0.0),
+    (11, 2, -2.5, 2.9, 0.0, 0.1),
+    (11, 3, 2.4, -0.6, 0.0, 0.0),
+    (11, 4, -0.6, 0.2, 0.0, 0.1),
+    (11, 5, -0.1, 0.5, -0.1, 0.0),
+    (11, 6, -0.6, -0.3, 0.0, 0.0),
+    (11, 7, -0.1, -1.2, 0.0, 0.1),
+    (11, 8, 1.1, -1.7, -0.1, 0.0),
+    (11, 9, -1.0, -2.9, -0.1, 0.0),
+    (11, 10, -0.2, -1.8, -0.1, 0.0),
+    (11, 11, 2.6, -2.3, -0.1, 0.0),
+    (12, 0, -2.0, 0.0, 0.0, 0.0),
+    (12, 1, -0.2, -1.3, 0.0, 0.0),
+    (12, 2, 0.3, 0.7, 0.0, 0.0),
+    (12, 3, 1.2, 1.0, 0.0, -0.1),
+    (12, 4, -1.3, -1.4, 0.0, 0.1),
+    (12, 5, 0.6, 0.0, 0.0, 0.0),
+    (12, 6, 0.6, 0.6, 0.1, 0.0),
+    (12, 7, 0.5, -0.1, 0.0, 0.0),
+    (12, 8, -0.1, 0.8, 0.0, 0.0),
+    (12, 9, -0.4, 0.1, 0.0, 0.0),
+    (12, 10, -0.2, -1.0, -0.1, 0.0),
+    (12, 11, -1.3, 0.1, 0.0, 0.0),
+    (12, 12, -0.7, 0.2, -0.1, -0.1),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_field(point: GeoPoint, year: f64, expected: [f64; 3], declination: f64) {
+        let field = magnetic_field(&point, year).unwrap();
+        let actual = [field.north, field.east, field.down];
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 0.1, "{:?} != {:?}", actual, expected);
+        }
+        assert!((field.declination() - declination).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_wmm2020_test_values() {
+        // From the WMM2020 technical report.
+        let at = |lat, lon, alt| GeoPoint::new(lat, lon, alt);
+        assert_field(at(80.0, 0.0, 0.0), 2020.0, [6570.4, -146.3, 54606.0], -1.28);
+        assert_field(
+            at(0.0, 120.0, 0.0),
+            2020.0,
+            [39624.3, 109.9, -10932.5],
+            0.16,
+        );
+        assert_field(
+            at(-80.0, 240.0, 0.0),
+            2020.0,
+            [5940.6, 15772.1, -52480.8],
+            69.36,
+        );
+        assert_field(
+            at(80.0, 0.0, 100e3),
+            2020.0,
+            [6261.8, -185.5, 52429.1],
+            -1.70,
+        );
+        assert_field(
+            at(0.0, 120.0, 100e3),
+            2020.0,
+            [37636.7, 104.9, -10474.8],
+            0.16,
+        );
+        assert_field(
+            at(-80.0, 240.0, 100e3),
+            2020.0,
+            [5744.9, 14799.5, -49969.4],
+            68.78,
+        );
+        assert_field(at(80.0, 0.0, 0.0), 2022.5, [6529.9, 1.1, 54713.4], 0.01);
+        assert_field(
+            at(0.0, 120.0, 0.0),
+            2022.5,
+            [39684.7, -42.2, -10809.5],
+            -0.06,
+        );
+
+        let field = magnetic_field(&at(80.0, 0.0, 0.0), 2020.0).unwrap();
+        assert!((field.inclination() - 83.14).abs() < 0.01);
+        assert!((field.intensity() - 55000.1).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_wmm2025_continues_wmm2020() {
+        // WMM2025 takes over from the WMM2020 forecast within its error.
+        for (lat, lon) in [(35.7, 139.7), (48.9, 2.3), (40.0, -105.0), (-33.9, 151.2)] {
+            let point = GeoPoint::new(lat, lon, 0.0);
+            let forecast = magnetic_field(&point, 2025.0 - 1e-9).unwrap();
+            let model = magnetic_field(&point, 2025.0).unwrap();
+            assert!((forecast.declination() - model.declination()).abs() < 0.2);
+            assert!((forecast.intensity() - model.intensity()).abs() < 150.0);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let point = GeoPoint::new(35.0, 139.0, 0.0);
+        assert!(magnetic_field(&point, 2019.9).is_none());
+        assert!(magnetic_field(&point, 2030.1).is_none());
+        assert!(magnetic_field(&GeoPoint::new(90.0, 0.0, 0.0), 2022.0).is_none());
+    }
+
+    #[test]
+    fn test_decimal_year() {
+        // 2020-01-01T00:00:00Z and 2022-07-02T12:00:00Z.
+        assert!((decimal_year(1_577_836_800.0) - 2020.0).abs() < 0.01);
+        assert!((decimal_year(1_656_763_200.0) - 2022.5).abs() < 0.01);
+    }
+}
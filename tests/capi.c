@@ -0,0 +1,67 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "theta_mp4.h"
+
+#define CHECK(cond)                                              \
+    do {                                                         \
+        if (!(cond)) {                                           \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                            \
+        }                                                        \
+    } while (0)
+
+int main(int argc, char **argv) {
+    size_t len = 1;
+    ThetaFile *file;
+    const ThetaImuSample *rdta;
+    const uint8_t *thumbnail;
+    char *json;
+
+    CHECK(argc == 4);
+    CHECK(strlen(theta_version()) > 0);
+
+    CHECK(theta_open(NULL) == NULL);
+    CHECK(theta_last_error() != NULL);
+
+    CHECK(theta_open(argv[1]) == NULL);
+    CHECK(strcmp(theta_last_error(), "Failed to parse the file") == 0);
+
+    CHECK(theta_has_box(NULL, "RDTA") == false);
+    CHECK(theta_imu_table(NULL, "RDTA", &len) == NULL);
+    CHECK(len == 0);
+    len = 1;
+    CHECK(theta_gps_table(NULL, &len) == NULL);
+    CHECK(len == 0);
+    CHECK(theta_json(NULL) == NULL);
+    theta_string_free(NULL);
+    theta_close(NULL);
+
+    file = theta_open(argv[2]);
+    CHECK(file != NULL);
+    CHECK(theta_has_box(file, "RDTA"));
+    CHECK(theta_has_box(file, "RTHU"));
+    CHECK(!theta_has_box(file, "RDTC"));
+
+    rdta = theta_imu_table(file, "RDTA", &len);
+    CHECK(rdta != NULL && len == 2);
+    CHECK(rdta[1].x == 1.5f && rdta[1].z == 1.0f && rdta[1].timestamp == 10000);
+    CHECK(theta_imu_table(file, "RDTC", &len) == NULL && len == 0);
+
+    thumbnail = theta_thumbnail(file, &len);
+    CHECK(thumbnail != NULL && len == 4);
+    CHECK(thumbnail[0] == 0xff && thumbnail[1] == 0xd8);
+
+    json = theta_json(file);
+    CHECK(json != NULL);
+    CHECK(strstr(json, "\"RICOH THETA X\"") != NULL);
+    CHECK(strstr(json, "\"timestamp\": 10000") != NULL);
+    theta_string_free(json);
+
+    theta_close(file);
+
+    CHECK(theta_open(argv[3]) == NULL);
+    CHECK(strstr(theta_last_error(), "Unknown endian type") != NULL);
+
+    return 0;
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "capi")]
+
+use std::{env, path::PathBuf, process::Command};
+
+#[test]
+fn test_header_up_to_date() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let generated = std::fs::read_to_string(concat!(env!("OUT_DIR"), "/theta_mp4.h")).unwrap();
+    let checked_in = std::fs::read_to_string(manifest_dir.join("include/theta_mp4.h")).unwrap();
+    assert!(
+        generated == checked_in,
+        "include/theta_mp4.h is stale; regenerate it with \
+         `cargo install cbindgen && cbindgen --config cbindgen.toml --output include/theta_mp4.h`"
+    );
+}
+
+fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+    data.extend_from_slice(name);
+    data.extend_from_slice(payload);
+    data
+}
+
+/// MP4 with a THETA X udta holding a thumbnail and two RDTA samples, whose table
+/// header carries the `endian` marker.
+fn fixture(endian: u16) -> Vec<u8> {
+    let mut rdta = 2u32.to_le_bytes().to_vec();
+    rdta.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+    rdta.extend_from_slice(&endian.to_le_bytes());
+    rdta.extend_from_slice(&[0; 6]);
+    for (x, timestamp) in [(0.5f32, 0u64), (1.5, 10_000)] {
+        rdta.extend([x, 0.0, 1.0, 0.0].map(f32::to_le_bytes).concat());
+        rdta.extend_from_slice(&timestamp.to_le_bytes());
+    }
+    let mut mvhd = vec![0u8; 100];
+    mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+    let udta = [
+        mp4_box(b"modl", b"RICOH THETA X"),
+        mp4_box(b"RTHU", &[0xff, 0xd8, 0xff, 0xd9]),
+        mp4_box(b"RDTA", &rdta),
+    ]
+    .concat();
+    let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+    data.extend(mp4_box(
+        b"moov",
+        &[mp4_box(b"mvhd", &mvhd), mp4_box(b"udta", &udta)].concat(),
+    ));
+    data
+}
+
+#[test]
+fn test_capi_c_program() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    // target/<profile>/deps/<test binary> -> target/<profile>
+    let lib_dir = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|deps| deps.parent())
+        .unwrap()
+        .to_path_buf();
+    let pid = std::process::id();
+    let exe = tmp_dir.join(format!("theta_mp4_capi_test_{}", pid));
+    let not_mp4 = tmp_dir.join(format!("theta_mp4_capi_not_mp4_{}.mp4", pid));
+    std::fs::write(&not_mp4, b"not an mp4").unwrap();
+    let theta_mp4 = tmp_dir.join(format!("theta_mp4_capi_test_{}.mp4", pid));
+    std::fs::write(&theta_mp4, fixture(0x0123)).unwrap();
+    // An unknown endian marker panics in the table decoder.
+    let malformed = tmp_dir.join(format!("theta_mp4_capi_malformed_{}.mp4", pid));
+    std::fs::write(&malformed, fixture(0xffff)).unwrap();
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest_dir.join("tests/capi.c"))
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-ltheta_mp4")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success());
+
+    let status = Command::new(&exe)
+        .arg(&not_mp4)
+        .arg(&theta_mp4)
+        .arg(&malformed)
+        .status()
+        .unwrap();
+    std::fs::remove_file(&exe).unwrap();
+    std::fs::remove_file(&not_mp4).unwrap();
+    std::fs::remove_file(&theta_mp4).unwrap();
+    std::fs::remove_file(&malformed).unwrap();
+    assert!(status.success());
+}
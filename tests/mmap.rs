@@ -0,0 +1,101 @@
+#![cfg(feature = "mmap")]
+
+use std::path::PathBuf;
+
+use theta_mp4::{parse_mmap, theta::ThetaMeta, Error};
+
+fn setup() -> Vec<u8> {
+    vec![
+        0x00, 0x00, 0x00, 0x0c, b'f', b't', b'y', b'p', // ftyp (12 bytes)
+        b'i', b's', b'o', b'm', // major_brand
+        0x00, 0x00, 0x00, 0x3d, b'm', b'o', b'o', b'v', // moov (61 bytes)
+        0x00, 0x00, 0x00, 0x0c, b'm', b'v', b'h', b'd', // mvhd (12 bytes)
+        0x00, 0x00, 0x00, 0x00, // payload
+        0x00, 0x00, 0x00, 0x29, b'u', b'd', b't', b'a', // udta (41 bytes)
+        0x00, 0x00, 0x00, 0x15, b'm', b'o', b'd', b'l', // modl (21 bytes)
+        b'R', b'I', b'C', b'O', b'H', b' ', b'T', b'H', b'E', b'T', b'A', b' ',
+        b'X', // payload
+        0x00, 0x00, 0x00, 0x0c, 0xa9, b'm', b'a', b'k', // @mak (12 bytes)
+        b'R', b'I', b'C', b'O', // payload
+    ]
+}
+
+fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+    data.extend_from_slice(name);
+    data.extend_from_slice(payload);
+    data
+}
+
+/// RDTG table with the given endian marker and two frame timestamps.
+fn rdtg(endian: u16) -> Vec<u8> {
+    let mut data = 2u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&[0x01, 0x00, 0x08, 0x00]);
+    data.extend_from_slice(&endian.to_le_bytes());
+    data.extend_from_slice(&[0; 6]);
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.extend_from_slice(&34u64.to_le_bytes());
+    data
+}
+
+fn with_rdtg(endian: u16) -> Vec<u8> {
+    let udta = [
+        mp4_box(b"modl", b"RICOH THETA X"),
+        mp4_box(b"RDTG", &rdtg(endian)),
+    ]
+    .concat();
+    let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+    data.extend(mp4_box(b"moov", &mp4_box(b"udta", &udta)));
+    data
+}
+
+fn parse_file(name: &str, data: &[u8], targets: &[&str]) -> Result<Option<ThetaMeta>, Error> {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "theta_mp4_{}_{}.mp4",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, data).unwrap();
+    let targets: Vec<String> = targets.iter().map(|t| t.to_string()).collect();
+    let theta_meta = parse_mmap(&path, Some(&targets));
+    std::fs::remove_file(&path).unwrap();
+    theta_meta
+}
+
+#[test]
+fn test_parse_mmap() {
+    let theta_meta = parse_file("parse_mmap", &setup(), &[]).unwrap().unwrap();
+    assert_eq!(theta_meta.modl, "RICOH THETA X");
+    assert_eq!(theta_meta._mak, "RICO");
+    assert!(theta_meta.video.is_none());
+}
+
+#[test]
+fn test_parse_mmap_tables() {
+    let theta_meta = parse_file("parse_mmap_tables", &with_rdtg(0x0123), &["RDTG"])
+        .unwrap()
+        .unwrap();
+    let rdtg = theta_meta.rdtg.unwrap();
+    assert_eq!(rdtg.header().number_of_entries, 2);
+    assert_eq!(rdtg.entries()[1].timestamp, 34);
+
+    // Not requested.
+    let theta_meta = parse_file("parse_mmap_untargeted", &with_rdtg(0x0123), &[])
+        .unwrap()
+        .unwrap();
+    assert!(theta_meta.rdtg.is_none());
+
+    // An unknown endian marker leaves the table out instead of panicking.
+    let theta_meta = parse_file("parse_mmap_malformed", &with_rdtg(0xffff), &["all"])
+        .unwrap()
+        .unwrap();
+    assert!(theta_meta.rdtg.is_none());
+}
+
+#[test]
+fn test_parse_mmap_not_mp4() {
+    assert!(matches!(
+        parse_file("parse_mmap_not_mp4", b"not an mp4", &[]),
+        Err(Error::InvalidMp4(_))
+    ));
+}